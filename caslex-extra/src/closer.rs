@@ -1,40 +1,232 @@
 //! Contains auto closer.
 //!
+//! Callbacks are executed in the order they were added, each callback is executed once.
+//!
 //! Example
 //!
 //! ```rust,no_run
 //! use caslex_extra::closer;
 //!
 //! closer::push_callback(Box::new(|| println!("close me")));
+//! closer::push_async_callback(Box::new(|| {
+//!     Box::pin(async {
+//!         println!("close me async");
+//!     })
+//! }));
 //!
 //! closer::cleanup_resources();
 //! ````
-use std::sync::{LazyLock, Mutex};
+//!
+//! Use [`cleanup_resources_with_timeout`] to abandon callbacks which hang:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use caslex_extra::closer;
+//!
+//! closer::push_callback(Box::new(|| std::thread::sleep(Duration::from_secs(60))));
+//!
+//! // logs warning and returns after 5 seconds
+//! closer::cleanup_resources_with_timeout(Duration::from_secs(5));
+//! ```
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{LazyLock, Mutex, mpsc},
+    time::Duration,
+};
 
 static CLOSER: LazyLock<Mutex<Closer>> = LazyLock::new(|| Mutex::new(Closer::default()));
 
 /// Add callback to global closer array.
 pub fn push_callback(callback: CloserFunc<'static>) {
-    CLOSER.lock().unwrap().push(callback);
+    CLOSER.lock().unwrap().push(Callback::Sync(callback));
+}
+
+/// Add async callback to global closer array.
+pub fn push_async_callback(callback: AsyncCloserFunc<'static>) {
+    CLOSER.lock().unwrap().push(Callback::Async(callback));
 }
 
 /// Execute all added callbacks to global closer array.
+///
+/// Async callbacks are executed on a separate runtime, so it can be called both inside and
+/// outside of a tokio runtime.
 pub fn cleanup_resources() {
-    CLOSER.lock().unwrap().close()
+    run_callbacks(CLOSER.lock().unwrap().take());
+}
+
+fn run_callbacks(closers: Vec<Callback<'_>>) {
+    for closer in closers {
+        match closer {
+            Callback::Sync(cb) => cb(),
+            Callback::Async(cb) => std::thread::scope(|s| {
+                s.spawn(|| block_on(cb()));
+            }),
+        }
+    }
+}
+
+/// Execute all added callbacks to global closer array, each callback is abandoned with a warning
+/// when it runs longer than `timeout` and the remaining callbacks are still executed.
+///
+/// Callbacks are executed on separate threads, abandoned callbacks keep running in background.
+pub fn cleanup_resources_with_timeout(timeout: Duration) {
+    run_callbacks_with_timeout(CLOSER.lock().unwrap().take(), timeout);
+}
+
+fn run_callbacks_with_timeout(closers: Vec<Callback<'static>>, timeout: Duration) {
+    for closer in closers {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            match closer {
+                Callback::Sync(cb) => cb(),
+                Callback::Async(cb) => block_on(cb()),
+            }
+            let _ = tx.send(());
+        });
+
+        match rx.recv_timeout(timeout) {
+            Ok(()) => {}
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                tracing::warn!("cleanup callback timed out after {timeout:?}, skipping it");
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                tracing::error!("cleanup callback panicked");
+            }
+        }
+    }
+}
+
+/// Execute all added callbacks to global closer array, awaiting async callbacks on the current
+/// runtime.
+pub async fn cleanup_resources_async() {
+    let closers = CLOSER.lock().unwrap().take();
+    run_callbacks_async(closers).await;
+}
+
+async fn run_callbacks_async(closers: Vec<Callback<'_>>) {
+    for closer in closers {
+        match closer {
+            Callback::Sync(cb) => cb(),
+            Callback::Async(cb) => cb().await,
+        }
+    }
 }
 
 type CloserFunc<'a> = Box<dyn Fn() + 'a + Send + Sync>;
 
+type AsyncCloserFunc<'a> = Box<dyn Fn() -> CloserFuture<'a> + 'a + Send + Sync>;
+
+type CloserFuture<'a> = Pin<Box<dyn Future<Output = ()> + 'a + Send>>;
+
+enum Callback<'a> {
+    Sync(CloserFunc<'a>),
+    Async(AsyncCloserFunc<'a>),
+}
+
 #[derive(Default)]
 struct Closer<'a> {
-    closers: Vec<CloserFunc<'a>>,
+    closers: Vec<Callback<'a>>,
 }
 
 impl<'a> Closer<'a> {
-    fn push(&mut self, callback: CloserFunc<'a>) {
+    fn push(&mut self, callback: Callback<'a>) {
         self.closers.push(callback);
     }
-    fn close(&mut self) {
-        self.closers.iter_mut().for_each(|cb| cb());
+    fn take(&mut self) -> Vec<Callback<'a>> {
+        std::mem::take(&mut self.closers)
+    }
+}
+
+/// Runs future to completion on a new runtime, must be called outside of a tokio runtime.
+fn block_on(future: CloserFuture<'_>) {
+    match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime.block_on(future),
+        Err(e) => tracing::error!("failed to build runtime for async closer: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    use super::*;
+
+    type Calls = Arc<Mutex<Vec<&'static str>>>;
+
+    fn sync_callback(calls: &Calls, name: &'static str) -> Callback<'static> {
+        let calls = calls.clone();
+        Callback::Sync(Box::new(move || calls.lock().unwrap().push(name)))
+    }
+
+    fn async_callback(calls: &Calls, name: &'static str) -> Callback<'static> {
+        let calls = calls.clone();
+        Callback::Async(Box::new(move || {
+            let calls = calls.clone();
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                calls.lock().unwrap().push(name);
+            })
+        }))
+    }
+
+    fn callbacks(calls: &Calls) -> Vec<Callback<'static>> {
+        vec![
+            sync_callback(calls, "first"),
+            async_callback(calls, "second"),
+            sync_callback(calls, "third"),
+        ]
+    }
+
+    #[test]
+    fn async_callbacks_run_outside_runtime() {
+        let calls = Calls::default();
+
+        run_callbacks(callbacks(&calls));
+
+        assert_eq!(*calls.lock().unwrap(), ["first", "second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn async_callbacks_run_inside_runtime() {
+        let calls = Calls::default();
+
+        run_callbacks(callbacks(&calls));
+        run_callbacks_async(callbacks(&calls)).await;
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["first", "second", "third", "first", "second", "third"]
+        );
+    }
+
+    #[test]
+    fn slow_callback_is_abandoned_after_timeout() {
+        let calls = Calls::default();
+        let slow = Callback::Sync(Box::new(|| std::thread::sleep(Duration::from_secs(10))));
+        let slow_async = Callback::Async(Box::new(|| {
+            Box::pin(tokio::time::sleep(Duration::from_secs(10)))
+        }));
+
+        let started = Instant::now();
+        run_callbacks_with_timeout(
+            vec![
+                slow,
+                sync_callback(&calls, "after sync"),
+                slow_async,
+                async_callback(&calls, "after async"),
+            ],
+            Duration::from_millis(100),
+        );
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(*calls.lock().unwrap(), ["after sync", "after async"]);
     }
 }
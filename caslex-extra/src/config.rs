@@ -0,0 +1,156 @@
+//! Contains config file loading for configs parsed by clap.
+//!
+//! Config file is a TOML file with keys named as the config struct fields, values are parsed the
+//! same way as environment variables. Values are resolved in the following order, the first found
+//! wins:
+//!
+//! 1. Environment variables.
+//! 2. Config file.
+//! 3. Default values.
+//!
+//! # Example
+//!
+//! ```toml
+//! host = "0.0.0.0"
+//! port = 9000
+//! request_timeout = "5s"
+//! cors_allow_origins = ["https://example.com"]
+//! ```
+//!
+//! ```rust,no_run
+//! use caslex_extra::{config, storages::postgres_pool::Config};
+//!
+//! let config: Config = config::from_file("config.toml").unwrap();
+//! ```
+
+use std::{fs, io::Read, path::Path};
+
+use anyhow::anyhow;
+use clap::Parser;
+use toml::{Table, Value};
+
+/// Parses config from TOML file, environment variables take precedence over the file values.
+pub fn from_file<C: Parser>(path: impl AsRef<Path>) -> anyhow::Result<C> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("failed to read config file {}: {e}", path.display()))?;
+
+    from_str(&content)
+}
+
+/// Parses config from TOML reader, environment variables take precedence over the reader values.
+pub fn from_reader<C: Parser>(mut reader: impl Read) -> anyhow::Result<C> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map_err(|e| anyhow!("failed to read config: {e}"))?;
+
+    from_str(&content)
+}
+
+fn from_str<C: Parser>(content: &str) -> anyhow::Result<C> {
+    let table: Table = toml::from_str(content).map_err(|e| anyhow!("invalid config: {e}"))?;
+
+    let mut command = C::command();
+    let name = command.get_name().to_owned();
+    for (key, value) in table {
+        if !command
+            .get_arguments()
+            .any(|arg| arg.get_id() == key.as_str())
+        {
+            return Err(anyhow!("unknown config key: {key}"));
+        }
+
+        let values = match value {
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| value_to_string(&key, item))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            value => vec![value_to_string(&key, value)?],
+        };
+
+        // file values become defaults, so environment variables still override them
+        command = command.mut_arg(key, |arg| arg.default_values(values).required(false));
+    }
+
+    let matches = command
+        .try_get_matches_from([name])
+        .map_err(|e| anyhow!("failed to parse config: {e}"))?;
+
+    C::from_arg_matches(&matches).map_err(|e| anyhow!("failed to parse config: {e}"))
+}
+
+fn value_to_string(key: &str, value: Value) -> anyhow::Result<String> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Integer(v) => Ok(v.to_string()),
+        Value::Float(v) => Ok(v.to_string()),
+        Value::Boolean(v) => Ok(v.to_string()),
+        Value::Datetime(v) => Ok(v.to_string()),
+        Value::Array(_) | Value::Table(_) => Err(anyhow!("unsupported value of config key: {key}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Parser, Debug)]
+    struct TestConfig {
+        #[arg(long, env = "CASLEX_TEST_CONFIG_HOST", default_value = "127.0.0.1")]
+        host: String,
+        #[arg(long, env = "CASLEX_TEST_CONFIG_PORT", default_value = "8080")]
+        port: u16,
+        #[arg(long, env = "CASLEX_TEST_CONFIG_DEBUG", default_value = "false")]
+        debug: bool,
+        #[arg(long, env = "CASLEX_TEST_CONFIG_ORIGINS", value_delimiter = ',')]
+        origins: Vec<String>,
+    }
+
+    #[test]
+    fn config_is_read_from_file() {
+        let path = std::env::temp_dir().join(format!("caslex-config-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            r#"
+            port = 9000
+            debug = true
+            origins = ["https://a.example.com", "https://b.example.com"]
+            "#,
+        )
+        .unwrap();
+
+        let config: TestConfig = from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.host, "127.0.0.1");
+        assert_eq!(config.port, 9000);
+        assert!(config.debug);
+        assert_eq!(
+            config.origins,
+            ["https://a.example.com", "https://b.example.com"]
+        );
+    }
+
+    #[test]
+    fn unknown_key_is_an_error() {
+        let error = from_reader::<TestConfig>(r#"prot = 9000"#.as_bytes()).unwrap_err();
+
+        assert!(
+            error.to_string().contains("unknown config key: prot"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn invalid_value_is_an_error() {
+        assert!(from_reader::<TestConfig>(r#"port = "http""#.as_bytes()).is_err());
+        assert!(from_reader::<TestConfig>("origins = [[1]]".as_bytes()).is_err());
+        assert!(from_reader::<TestConfig>("port = ".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        assert!(from_file::<TestConfig>("/nonexistent/caslex.toml").is_err());
+    }
+}
@@ -10,9 +10,62 @@
 //! setup_panic_hook();
 //! panic!("test")
 //! ```
+//!
+//! Keep the process alive and observe panics with a custom callback:
+//!
+//! ```rust,no_run
+//! use caslex_extra::hooks::{PanicHookOptions, setup_panic_hook_with};
+//!
+//! setup_panic_hook_with(
+//!     PanicHookOptions::default()
+//!         .exit(false)
+//!         .callback(|info| eprintln!("observed panic: {info}")),
+//! );
+//! ```
+
+use std::panic::PanicHookInfo;
+
+type PanicCallback = Box<dyn Fn(&PanicHookInfo<'_>) + Send + Sync>;
+
+/// Define panic hook options.
+pub struct PanicHookOptions {
+    exit: bool,
+    callback: Option<PanicCallback>,
+}
+
+impl Default for PanicHookOptions {
+    fn default() -> Self {
+        PanicHookOptions {
+            exit: true,
+            callback: None,
+        }
+    }
+}
+
+impl PanicHookOptions {
+    /// Exit the process with code 1 after logging the panic, enabled by default.
+    pub fn exit(mut self, exit: bool) -> Self {
+        self.exit = exit;
+        self
+    }
+
+    /// Callback called after logging the panic and before exit.
+    pub fn callback(
+        mut self,
+        callback: impl Fn(&PanicHookInfo<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+}
 
 /// Setup custom panic hook.
 pub fn setup_panic_hook() {
+    setup_panic_hook_with(PanicHookOptions::default())
+}
+
+/// Setup custom panic hook with options.
+pub fn setup_panic_hook_with(opts: PanicHookOptions) {
     std::panic::set_hook(Box::new(move |panic_info| {
         // If the panic has a source location, record it as structured fields.
         if let Some(location) = panic_info.location() {
@@ -25,6 +78,44 @@ pub fn setup_panic_hook() {
         } else {
             tracing::error!(message = %panic_info);
         }
-        std::process::exit(1);
+
+        if let Some(callback) = &opts.callback {
+            callback(panic_info);
+        }
+
+        if opts.exit {
+            std::process::exit(1);
+        }
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn panic_is_observed_without_exit() {
+        let observed = Arc::new(Mutex::new(vec![]));
+        let messages = observed.clone();
+        setup_panic_hook_with(
+            PanicHookOptions::default()
+                .exit(false)
+                .callback(move |info| messages.lock().unwrap().push(info.to_string())),
+        );
+
+        let result = std::panic::catch_unwind(|| panic!("observed panic"));
+        // restore the default hook
+        drop(std::panic::take_hook());
+
+        assert!(result.is_err());
+        assert!(
+            observed
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|message| message.contains("observed panic"))
+        );
+    }
+}
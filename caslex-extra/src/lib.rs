@@ -14,8 +14,11 @@
 //! Name | Description | Default?
 //! ---|---|---
 //! `jwt` | Enables jwt supporting | No
+//! `jwks` | Enables jwt verification by remote JWKS | No
 //! `postgres` | Enables postgres pool | No
+//! `migrations` | Enables postgres migration runner | No
 //! `observability` | Enables tracing and logging supporting | No
+//! `redis` | Enables redis pool | No
 //!
 //! [feature flags]: https://doc.rust-lang.org/cargo/reference/features.html#the-features-section
 //! [examples]: https://github.com/mkbeh/caslex/tree/main/examples
@@ -23,15 +26,20 @@
 #![cfg_attr(docsrs, feature(doc_auto_cfg, doc_cfg))]
 
 pub mod closer;
+pub mod config;
 pub mod hooks;
+pub mod log_level;
 #[cfg(feature = "observability")]
 pub mod observability;
+pub mod sampling;
 pub mod security;
 pub mod storages;
 
-pub use closer::cleanup_resources;
+pub use closer::{cleanup_resources, cleanup_resources_async};
 
 /// Setup application defaults such as custom panic hook and opentelemetry.
+///
+/// The tracer provider is available via `observability::tracer_provider` after the setup.
 pub fn setup_application(_name: &'static str) {
     // Setup custom panic hook
     hooks::setup_panic_hook();
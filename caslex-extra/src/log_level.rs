@@ -0,0 +1,43 @@
+//! Contains runtime log level reload.
+//!
+//! The tracing subscriber set up by `observability` feature registers its filters here, so the log
+//! level can be changed without restart. Custom subscribers can register own reloader with
+//! [`set_log_level_reloader`].
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use caslex_extra::log_level::set_log_level;
+//!
+//! set_log_level("debug,hyper=info").unwrap();
+//! ```
+
+use std::sync::OnceLock;
+
+use anyhow::anyhow;
+
+type LogLevelReloader = Box<dyn Fn(&str) -> anyhow::Result<()> + Send + Sync>;
+
+static RELOADER: OnceLock<LogLevelReloader> = OnceLock::new();
+
+/// Registers function applying new log level, only the first registered function is used.
+pub fn set_log_level_reloader(
+    reloader: impl Fn(&str) -> anyhow::Result<()> + Send + Sync + 'static,
+) {
+    let _ = RELOADER.set(Box::new(reloader));
+}
+
+/// Sets log level of the registered tracing subscriber, the level is `tracing_subscriber`
+/// filter directives, e.g. `info` or `info,my_crate=debug`.
+pub fn set_log_level(level: &str) -> anyhow::Result<()> {
+    let reloader = RELOADER
+        .get()
+        .ok_or_else(|| anyhow!("log level reload is not configured"))?;
+
+    reloader(level)
+}
+
+/// Returns true if log level reloader is registered.
+pub fn is_log_level_reloadable() -> bool {
+    RELOADER.get().is_some()
+}
@@ -14,71 +14,697 @@
 //! ```
 //!
 //! Log level of logs and traces configure via `LOG_LEVEL` and `OTEL_LOG_LEVEL` environment
-//! variables.
+//! variables, both are replaced at runtime by [`crate::log_level::set_log_level`]. Log format
+//! configure via `LOG_FORMAT` environment variable, `json` (default), `pretty` or `compact`.
+//!
+//! JSON log field names follow the schema set via `LOG_SCHEMA` environment variable:
+//! * `default` - `timestamp`, `level`, `message`, `target`, `line_number`, `threadName`.
+//! * `ecs` - Elastic Common Schema names `@timestamp`, `log.level`, `message`, `log.logger`,
+//!   `log.origin.file.line`, `process.thread.name`.
+//! * `gcp` - Google Cloud Logging names `time`, `severity` with `WARNING` and `DEBUG` severities
+//!   instead of `WARN` and `TRACE`, other fields keep the default names.
+//!
+//! Fields are renamed on top of the schema via `LOG_FIELD_NAMES` environment variable,
+//! comma-separated default names mapped to the new ones, e.g. `message=msg,timestamp=@timestamp`.
+//!
+//! Noisy dependencies like `hyper` or `h2` are muted by default directives, append comma-separated
+//! directives to logs and traces via `LOG_EXTRA_DIRECTIVES` and `OTEL_EXTRA_DIRECTIVES`
+//! environment variables, e.g. `hyper=debug,my_dep=warn`. They override the default directives of
+//! the same targets and are kept on the runtime log level change.
+//!
+//! Exporter is configured via the standard OTLP environment variables:
+//! * `OTEL_EXPORTER_OTLP_ENDPOINT` - collector endpoint, defaults to `http://localhost:4318` for `http/protobuf`
+//!   and `http://localhost:4317` for `grpc` protocol.
+//! * `OTEL_EXPORTER_OTLP_PROTOCOL` - exporter protocol, `http/protobuf` (default) or `grpc`. The
+//!   `grpc` exporter must be set up inside a tokio runtime.
+//! * `OTEL_RESOURCE_ATTRIBUTES` - comma-separated `key=value` resource attributes, e.g.
+//!   `deployment.environment=prod,service.namespace=shop`. The attributes take precedence over the
+//!   default `service.name` and `service.version` attributes, attributes passed to
+//!   [`setup_opentelemetry_with_attributes`] take precedence over both.
+//! * `OTEL_METRICS_EXPORTER` - metrics exporter, `otlp` to push metrics of the global meter
+//!   provider to the collector or `none` (default) to disable it.
+//! * `OTEL_LOGS_EXPORTER` - logs exporter, `otlp` to export log events as OpenTelemetry logs along
+//!   with the stdout logs or `none` (default) to disable it. Logs are filtered by the `LOG_LEVEL`
+//!   and linked to the trace of the current span.
+//! * `OTEL_SAMPLING_RATIO` - ratio of sampled traces in `0..=1` range, defaults to `1`. The ratio
+//!   is replaced at runtime by [`crate::sampling::set_sampling_ratio`].
+//!
+//! Spans and logs are exported in batches periodically, short-lived jobs call [`force_flush`] to
+//! export pending spans before exit or a critical operation.
+//!
+//! Exporters connect lazily, so the setup doesn't wait for the collector. Batches the collector
+//! didn't accept, e.g. while it's unreachable, are dropped with a warning logged once a minute
+//! per signal, and pending exports aren't awaited on shutdown then. The tracer provider is
+//! available via [`tracer_provider`] for manual spans.
+//!
+//! Outbound requests continue the trace of the current span with [`inject_context`], it writes
+//! `traceparent` and `tracestate` headers with the configured propagator:
+//!
+//! ```rust,no_run
+//! use caslex_extra::observability::inject_context;
+//! use http::HeaderMap;
+//!
+//! let mut headers = HeaderMap::new();
+//! inject_context(&mut headers);
+//! // pass the headers to the HTTP client, e.g. reqwest `RequestBuilder::headers`
+//! ```
 
-use std::{env, sync::OnceLock};
+use std::{
+    env,
+    sync::{
+        Mutex, Once, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
-use opentelemetry::{KeyValue, global, trace::TracerProvider};
-use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use anyhow::anyhow;
+use http::{HeaderMap, HeaderName, HeaderValue};
+pub use opentelemetry::KeyValue;
+use opentelemetry::{
+    Context, global,
+    logs::{AnyValue, LogRecord, Logger, LoggerProvider, Severity},
+    propagation::Injector,
+    trace::{Link, SamplingResult, SpanKind, TraceContextExt, TraceId, TracerProvider},
+};
+use opentelemetry_otlp::{LogExporter, MetricExporter, Protocol, SpanExporter, WithExportConfig};
 use opentelemetry_sdk::{
     Resource,
+    error::OTelSdkResult,
+    logs::{SdkLogRecord, SdkLogger, SdkLoggerProvider},
+    metrics::SdkMeterProvider,
     propagation::TraceContextPropagator,
-    trace::{Sampler, SdkTracerProvider},
+    resource::{EnvResourceDetector, SdkProvidedResourceDetector, TelemetryResourceDetector},
+    trace::{Sampler, SdkTracerProvider, ShouldSample},
+};
+use serde::{
+    Deserialize, Deserializer,
+    de::{MapAccess, Visitor},
+};
+use serde_json::value::RawValue;
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_opentelemetry::{OpenTelemetrySpanExt, OtelData};
+use tracing_subscriber::{
+    EnvFilter,
+    fmt::{
+        FmtContext, FormatEvent, FormatFields, MakeWriter,
+        format::{FmtSpan, Writer},
+    },
+    layer::{self, Layer},
+    prelude::*,
+    registry::LookupSpan,
+    reload,
 };
-use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan, prelude::*};
 
-use crate::closer;
+use crate::{closer, log_level, sampling};
 
 const DEFAULT_LOG_LEVEL: &str = "debug";
 
-fn get_resource(name: String) -> Resource {
+enum LogFormat {
+    Json,
+    Pretty,
+    Compact,
+}
+
+fn get_log_format() -> LogFormat {
+    match env::var("LOG_FORMAT").as_deref() {
+        Ok("json") | Err(_) => LogFormat::Json,
+        Ok("pretty") => LogFormat::Pretty,
+        Ok("compact") => LogFormat::Compact,
+        Ok(format) => panic!("Invalid LOG_FORMAT: {format}"),
+    }
+}
+
+/// Define renames of JSON log fields.
+struct LogFieldNames {
+    renames: Vec<(String, String)>,
+    gcp_severity: bool,
+}
+
+impl LogFieldNames {
+    fn rename<'a>(&'a self, name: &'a str) -> &'a str {
+        self.renames
+            .iter()
+            .find(|(from, _)| from == name)
+            .map_or(name, |(_, to)| to)
+    }
+}
+
+/// Returns renames of the log schema and the overrides, `None` when the names are the default.
+fn get_log_field_names() -> Option<LogFieldNames> {
+    let (renames, gcp_severity) = match env::var("LOG_SCHEMA").as_deref() {
+        Ok("default") | Err(_) => (vec![], false),
+        Ok("ecs") => (
+            vec![
+                ("timestamp", "@timestamp"),
+                ("level", "log.level"),
+                ("target", "log.logger"),
+                ("line_number", "log.origin.file.line"),
+                ("threadName", "process.thread.name"),
+            ],
+            false,
+        ),
+        Ok("gcp") => (vec![("timestamp", "time"), ("level", "severity")], true),
+        Ok(schema) => panic!("Invalid LOG_SCHEMA: {schema}"),
+    };
+    let mut renames: Vec<_> = renames
+        .into_iter()
+        .map(|(from, to)| (from.to_owned(), to.to_owned()))
+        .collect();
+
+    let overrides = env::var("LOG_FIELD_NAMES").unwrap_or_default();
+    for rename in overrides
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+    {
+        let (from, to) = rename
+            .split_once('=')
+            .map(|(from, to)| (from.trim(), to.trim()))
+            .filter(|(from, to)| !from.is_empty() && !to.is_empty())
+            .unwrap_or_else(|| panic!("Invalid LOG_FIELD_NAMES rename: {rename}"));
+        renames.retain(|(name, _)| name != from);
+        renames.push((from.to_owned(), to.to_owned()));
+    }
+
+    (!renames.is_empty() || gcp_severity).then_some(LogFieldNames {
+        renames,
+        gcp_severity,
+    })
+}
+
+/// Renames fields of JSON events formatted by the inner format.
+struct RenamedFields<F> {
+    inner: F,
+    field_names: LogFieldNames,
+}
+
+impl<S, N, F> FormatEvent<S, N> for RenamedFields<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let mut line = String::new();
+        self.inner
+            .format_event(ctx, Writer::new(&mut line), event)?;
+
+        let Ok(OrderedFields(fields)) = serde_json::from_str(&line) else {
+            return writer.write_str(&line);
+        };
+
+        writer.write_char('{')?;
+        for (i, (name, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                writer.write_char(',')?;
+            }
+
+            let value = match value.get() {
+                "\"WARN\"" if self.field_names.gcp_severity && name == "level" => "\"WARNING\"",
+                "\"TRACE\"" if self.field_names.gcp_severity && name == "level" => "\"DEBUG\"",
+                value => value,
+            };
+            let name = serde_json::to_string(self.field_names.rename(name))
+                .map_err(|_| std::fmt::Error)?;
+            write!(writer, "{name}:{value}")?;
+        }
+        writer.write_str("}\n")
+    }
+}
+
+/// Fields of JSON object in the original order, values are kept as is.
+struct OrderedFields(Vec<(String, Box<RawValue>)>);
+
+impl<'de> Deserialize<'de> for OrderedFields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = OrderedFields;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(OrderedFields(fields))
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+enum OtlpProtocol {
+    HttpBinary,
+    Grpc,
+}
+
+fn get_otlp_protocol() -> OtlpProtocol {
+    match env::var("OTEL_EXPORTER_OTLP_PROTOCOL").as_deref() {
+        Ok("http/protobuf") | Err(_) => OtlpProtocol::HttpBinary,
+        Ok("grpc") => OtlpProtocol::Grpc,
+        Ok(protocol) => panic!("Invalid OTEL_EXPORTER_OTLP_PROTOCOL: {protocol}"),
+    }
+}
+
+/// Returns resource, attributes are used only by the first call.
+fn get_resource(name: String, attributes: Vec<KeyValue>) -> Resource {
     static RESOURCE: OnceLock<Resource> = OnceLock::new();
     RESOURCE
         .get_or_init(|| {
-            Resource::builder()
+            // later attributes override the earlier ones
+            Resource::builder_empty()
+                .with_detectors(&[
+                    Box::new(SdkProvidedResourceDetector),
+                    Box::new(TelemetryResourceDetector),
+                ])
                 .with_service_name(name)
                 .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+                .with_detector(Box::new(EnvResourceDetector::new()))
+                .with_attributes(attributes)
                 .build()
         })
         .clone()
 }
 
+/// Interval of warnings about batches dropped by an exporter.
+const EXPORT_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Timeout of providers shutdown while the collector is unreachable.
+const UNREACHABLE_SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Set when the last export of any signal failed.
+static COLLECTOR_UNREACHABLE: AtomicBool = AtomicBool::new(false);
+
+/// Exporter dropping batches the collector didn't accept, so the SDK doesn't log an error for each
+/// of them.
+#[derive(Debug)]
+struct DegradingExporter<E> {
+    inner: E,
+    signal: &'static str,
+    failures: Mutex<ExportFailures>,
+}
+
+#[derive(Debug, Default)]
+struct ExportFailures {
+    failing: bool,
+    dropped: u64,
+    warned_at: Option<Instant>,
+}
+
+impl<E> DegradingExporter<E> {
+    fn new(inner: E, signal: &'static str) -> Self {
+        DegradingExporter {
+            inner,
+            signal,
+            failures: Mutex::default(),
+        }
+    }
+
+    fn handle_result(&self, result: OTelSdkResult) -> OTelSdkResult {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        COLLECTOR_UNREACHABLE.store(result.is_err(), Ordering::Relaxed);
+
+        match result {
+            Ok(()) if failures.failing => {
+                tracing::info!("Export of {} to the collector is recovered", self.signal);
+                *failures = ExportFailures::default();
+            }
+            Ok(()) => {}
+            Err(e) => {
+                failures.failing = true;
+                failures.dropped += 1;
+                if failures
+                    .warned_at
+                    .is_none_or(|at| at.elapsed() >= EXPORT_WARNING_INTERVAL)
+                {
+                    tracing::warn!(
+                        "Failed to export {} to the collector, dropped {} batches: {e}",
+                        self.signal,
+                        failures.dropped
+                    );
+                    failures.dropped = 0;
+                    failures.warned_at = Some(Instant::now());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<E: opentelemetry_sdk::trace::SpanExporter> opentelemetry_sdk::trace::SpanExporter
+    for DegradingExporter<E>
+{
+    async fn export(&self, batch: Vec<opentelemetry_sdk::trace::SpanData>) -> OTelSdkResult {
+        let result = self.inner.export(batch).await;
+        self.handle_result(result)
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+impl<E: opentelemetry_sdk::logs::LogExporter> opentelemetry_sdk::logs::LogExporter
+    for DegradingExporter<E>
+{
+    async fn export(&self, batch: opentelemetry_sdk::logs::LogBatch<'_>) -> OTelSdkResult {
+        let result = self.inner.export(batch).await;
+        self.handle_result(result)
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+impl<E: opentelemetry_sdk::metrics::exporter::PushMetricExporter>
+    opentelemetry_sdk::metrics::exporter::PushMetricExporter for DegradingExporter<E>
+{
+    async fn export(
+        &self,
+        metrics: &opentelemetry_sdk::metrics::data::ResourceMetrics,
+    ) -> OTelSdkResult {
+        let result = self.inner.export(metrics).await;
+        self.handle_result(result)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn temporality(&self) -> opentelemetry_sdk::metrics::Temporality {
+        self.inner.temporality()
+    }
+}
+
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
 fn get_tracer_provider(name: String) -> SdkTracerProvider {
-    static INSTANCE: OnceLock<SdkTracerProvider> = OnceLock::new();
-    INSTANCE.get_or_init(|| init_traces(name)).clone()
+    TRACER_PROVIDER.get_or_init(|| init_traces(name)).clone()
 }
 
 fn init_traces(name: String) -> SdkTracerProvider {
     const DEFAULT_SAMPLE_RATIO: f64 = 1.0;
-    let exporter = SpanExporter::builder()
-        .with_http()
-        .with_protocol(Protocol::HttpBinary)
-        .build()
-        .expect("Failed to create span exporter");
+    // endpoint is resolved by the exporters from the OTLP environment variables
+    let exporter = match get_otlp_protocol() {
+        OtlpProtocol::HttpBinary => SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .build(),
+        OtlpProtocol::Grpc => SpanExporter::builder().with_tonic().build(),
+    }
+    .expect("Failed to create span exporter");
+    let exporter = DegradingExporter::new(exporter, "spans");
 
     let ratio = env::var("OTEL_SAMPLING_RATIO")
         .unwrap_or_else(|_| DEFAULT_SAMPLE_RATIO.to_string())
         .parse::<f64>()
         .expect("Invalid OTEL_SAMPLING_RATIO");
 
-    let sampler = if ratio < DEFAULT_SAMPLE_RATIO {
-        Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio)))
-    } else {
-        Sampler::AlwaysOn
-    };
+    // out of range ratios are accepted as always on or always off
+    sampling::init_sampling_ratio(ratio.clamp(0.0, DEFAULT_SAMPLE_RATIO))
+        .expect("Invalid OTEL_SAMPLING_RATIO");
 
     SdkTracerProvider::builder()
-        .with_resource(get_resource(name))
+        .with_resource(get_resource(name, vec![]))
         .with_batch_exporter(exporter)
-        .with_sampler(sampler)
+        .with_sampler(ReloadableSampler(Sampler::ParentBased(Box::new(
+            RatioSampler,
+        ))))
         .build()
 }
 
+/// Samples all spans with ratio 1, otherwise follows the parent decision and samples root spans
+/// with the current ratio.
+#[derive(Debug, Clone)]
+struct ReloadableSampler(Sampler);
+
+impl ShouldSample for ReloadableSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        match sampling::sampling_ratio() {
+            Some(ratio) if ratio < 1.0 => {
+                self.0
+                    .should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+            }
+            _ => Sampler::AlwaysOn.should_sample(
+                parent_context,
+                trace_id,
+                name,
+                span_kind,
+                attributes,
+                links,
+            ),
+        }
+    }
+}
+
+/// Samples spans with the current ratio.
+#[derive(Debug, Clone)]
+struct RatioSampler;
+
+impl ShouldSample for RatioSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let ratio = sampling::sampling_ratio().unwrap_or(1.0);
+
+        Sampler::TraceIdRatioBased(ratio).should_sample(
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        )
+    }
+}
+
+static METER_PROVIDER: OnceLock<Option<SdkMeterProvider>> = OnceLock::new();
+
+fn get_meter_provider(name: String) -> Option<SdkMeterProvider> {
+    METER_PROVIDER.get_or_init(|| init_metrics(name)).clone()
+}
+
+fn init_metrics(name: String) -> Option<SdkMeterProvider> {
+    match env::var("OTEL_METRICS_EXPORTER").as_deref() {
+        Ok("otlp") => {}
+        Ok("none") | Err(_) => return None,
+        Ok(exporter) => panic!("Invalid OTEL_METRICS_EXPORTER: {exporter}"),
+    }
+
+    let exporter = match get_otlp_protocol() {
+        OtlpProtocol::HttpBinary => MetricExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .build(),
+        OtlpProtocol::Grpc => MetricExporter::builder().with_tonic().build(),
+    }
+    .expect("Failed to create metric exporter");
+    let exporter = DegradingExporter::new(exporter, "metrics");
+
+    Some(
+        SdkMeterProvider::builder()
+            .with_resource(get_resource(name, vec![]))
+            .with_periodic_exporter(exporter)
+            .build(),
+    )
+}
+
+static LOGGER_PROVIDER: OnceLock<Option<SdkLoggerProvider>> = OnceLock::new();
+
+fn get_logger_provider(name: String) -> Option<SdkLoggerProvider> {
+    LOGGER_PROVIDER.get_or_init(|| init_logs(name)).clone()
+}
+
+fn init_logs(name: String) -> Option<SdkLoggerProvider> {
+    match env::var("OTEL_LOGS_EXPORTER").as_deref() {
+        Ok("otlp") => {}
+        Ok("none") | Err(_) => return None,
+        Ok(exporter) => panic!("Invalid OTEL_LOGS_EXPORTER: {exporter}"),
+    }
+
+    let exporter = match get_otlp_protocol() {
+        OtlpProtocol::HttpBinary => LogExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .build(),
+        OtlpProtocol::Grpc => LogExporter::builder().with_tonic().build(),
+    }
+    .expect("Failed to create log exporter");
+    let exporter = DegradingExporter::new(exporter, "logs");
+
+    Some(
+        SdkLoggerProvider::builder()
+            .with_resource(get_resource(name, vec![]))
+            .with_batch_exporter(exporter)
+            .build(),
+    )
+}
+
+/// Emits tracing events as OpenTelemetry log records linked to the span of the event.
+struct LogsLayer {
+    logger: SdkLogger,
+}
+
+impl<S> Layer<S> for LogsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) {
+        let metadata = event.metadata();
+
+        let mut record = self.logger.create_log_record();
+        record.set_timestamp(SystemTime::now());
+        record.set_target(metadata.target().to_owned());
+        record.set_event_name(metadata.name());
+        record.set_severity_number(get_severity(metadata.level()));
+        record.set_severity_text(metadata.level().as_str());
+        event.record(&mut LogRecordVisitor(&mut record));
+
+        // `tracing::Span::current` can't be used while the event is dispatched, the span is read
+        // from the registry, spans skipped by the layer filter resolve to their nearest parent
+        if let Some(span) = ctx.event_span(event) {
+            let extensions = span.extensions();
+            if let Some(data) = extensions.get::<OtelData>() {
+                let trace_id = data
+                    .builder
+                    .trace_id
+                    .unwrap_or_else(|| data.parent_cx.span().span_context().trace_id());
+                if let Some(span_id) = data.builder.span_id {
+                    record.set_trace_context(trace_id, span_id, None);
+                }
+            }
+        }
+
+        self.logger.emit(record);
+    }
+}
+
+fn get_severity(level: &tracing::Level) -> Severity {
+    match *level {
+        tracing::Level::TRACE => Severity::Trace,
+        tracing::Level::DEBUG => Severity::Debug,
+        tracing::Level::INFO => Severity::Info,
+        tracing::Level::WARN => Severity::Warn,
+        tracing::Level::ERROR => Severity::Error,
+    }
+}
+
+/// Records `message` field as the log body and other fields as attributes.
+struct LogRecordVisitor<'a>(&'a mut SdkLogRecord);
+
+impl Visit for LogRecordVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.add_attribute(field.name(), value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.add_attribute(field.name(), value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match i64::try_from(value) {
+            Ok(value) => self.0.add_attribute(field.name(), value),
+            Err(_) => self.0.add_attribute(field.name(), value.to_string()),
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.add_attribute(field.name(), value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.0.set_body(AnyValue::from(value.to_owned())),
+            name => self.0.add_attribute(name, value.to_owned()),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => self.0.set_body(AnyValue::from(format!("{value:?}"))),
+            name => self.0.add_attribute(name, format!("{value:?}")),
+        }
+    }
+}
+
 /// Setup opentelemetry.
 ///
-/// Init opentelemetry tracer provider and tracing.
+/// Init opentelemetry tracer provider, meter provider if enabled and tracing.
 pub fn setup_opentelemetry(name: &'static str) -> SdkTracerProvider {
+    setup_opentelemetry_with_attributes(name, vec![])
+}
+
+/// Setup opentelemetry with additional resource attributes.
+///
+/// Init opentelemetry tracer provider, meter provider if enabled and tracing. Only the first call
+/// initializes opentelemetry, the next calls return the same tracer provider.
+pub fn setup_opentelemetry_with_attributes(
+    name: &'static str,
+    attributes: impl IntoIterator<Item = KeyValue>,
+) -> SdkTracerProvider {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| init_opentelemetry(name, attributes.into_iter().collect()));
+
+    get_tracer_provider(name.to_owned())
+}
+
+fn init_opentelemetry(name: &'static str, attributes: Vec<KeyValue>) {
     global::set_text_map_propagator(TraceContextPropagator::new());
+
+    // init resource before providers, so they are built with the passed attributes
+    get_resource(name.to_owned(), attributes);
+
+    if let Some(meter_provider) = get_meter_provider(name.to_owned()) {
+        global::set_meter_provider(meter_provider);
+    }
+
     let tracer_provider = get_tracer_provider(name.to_owned());
     // Set the global tracer provider using a clone of the tracer_provider.
     // Setting global tracer provider is required if other parts of the application
@@ -104,31 +730,125 @@ pub fn setup_opentelemetry(name: &'static str) -> SdkTracerProvider {
     // https://github.com/open-telemetry/opentelemetry-rust/issues/761
     let otel_log_level =
         env::var("OTEL_LOG_LEVEL").unwrap_or_else(|_| DEFAULT_LOG_LEVEL.to_string());
+    let (filter_otel, otel_handle) = reload::Layer::new(with_otel_directives(
+        EnvFilter::new(&otel_log_level),
+        name,
+        &otel_log_level,
+    ));
+    let otel_layer = otel_layer.with_filter(filter_otel);
 
-    let filter_otel = EnvFilter::new(otel_log_level.clone())
-        .add_directive(
-            format!("{}={otel_log_level}", name.to_owned())
-                .parse()
-                .unwrap(),
-        )
+    // Create a new tracing::Fmt layer to print the logs to stdout. It has a
+    // default filter of `info` level and above, and `debug` and above for logs
+    // from OpenTelemetry crates. The filter levels can be customized as needed.
+    let fmt_log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| DEFAULT_LOG_LEVEL.to_string());
+    let (filter_fmt, fmt_handle) = reload::Layer::new(with_fmt_directives(
+        EnvFilter::new(&fmt_log_level),
+        name,
+        &fmt_log_level,
+    ));
+    let log_format = get_log_format();
+    let field_names = match log_format {
+        LogFormat::Json => get_log_field_names(),
+        LogFormat::Pretty | LogFormat::Compact => None,
+    };
+    let fmt_layer = get_fmt_layer(log_format, field_names, std::io::stdout).with_filter(filter_fmt);
+
+    // Create a logs layer exporting the logs through OTLP if enabled, it's filtered as the Fmt
+    // layer.
+    let (logs_layer, logs_handle) = match get_logger_provider(name.to_owned()) {
+        Some(logger_provider) => {
+            let (filter_logs, logs_handle) = reload::Layer::new(with_logs_directives(
+                EnvFilter::new(&fmt_log_level),
+                name,
+                &fmt_log_level,
+            ));
+            let logs_layer = LogsLayer {
+                logger: logger_provider.logger(name),
+            }
+            .with_filter(filter_logs);
+            (Some(logs_layer), Some(logs_handle))
+        }
+        None => (None, None),
+    };
+
+    // Initialize the tracing subscriber with the OpenTelemetry layer, the
+    // Fmt layer and the logs layer.
+    // Fails when a global subscriber was set outside, keep that subscriber then.
+    match tracing_subscriber::registry()
+        .with(otel_layer)
+        .with(fmt_layer)
+        .with(logs_layer)
+        .try_init()
+    {
+        // All filters are replaced by the runtime log level
+        Ok(()) => log_level::set_log_level_reloader(move |level| {
+            let filter_otel = with_otel_directives(EnvFilter::try_new(level)?, name, level);
+            let filter_fmt = with_fmt_directives(EnvFilter::try_new(level)?, name, level);
+            otel_handle.reload(filter_otel)?;
+            fmt_handle.reload(filter_fmt)?;
+            if let Some(logs_handle) = &logs_handle {
+                logs_handle.reload(with_logs_directives(
+                    EnvFilter::try_new(level)?,
+                    name,
+                    level,
+                ))?;
+            }
+            Ok(())
+        }),
+        Err(e) => tracing::warn!("Failed to set global tracing subscriber: {e}"),
+    }
+
+    // Add callback to unset opentelemetry automatically
+    closer::push_callback(Box::new(|| unset_opentelemetry(name)));
+}
+
+/// Returns layer writing events in the format, JSON field names are renamed if passed.
+fn get_fmt_layer<S, W>(
+    format: LogFormat,
+    field_names: Option<LogFieldNames>,
+    writer: W,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_thread_names(true)
+        .with_level(true)
+        .with_line_number(true);
+
+    match format {
+        LogFormat::Json => {
+            let fmt_layer = fmt_layer
+                .with_span_events(FmtSpan::CLOSE)
+                .json()
+                .flatten_event(true);
+            match field_names {
+                Some(field_names) => fmt_layer
+                    .map_event_format(|inner| RenamedFields { inner, field_names })
+                    .boxed(),
+                None => fmt_layer.boxed(),
+            }
+        }
+        LogFormat::Pretty => fmt_layer.pretty().boxed(),
+        LogFormat::Compact => fmt_layer.compact().boxed(),
+    }
+}
+
+fn with_otel_directives(filter: EnvFilter, name: &str, level: &str) -> EnvFilter {
+    let filter = with_service_directive(filter, name, level)
         .add_directive("axum=off".parse().unwrap())
         .add_directive("hyper=off".parse().unwrap())
         .add_directive("opentelemetry=off".parse().unwrap())
         .add_directive("h2=off".parse().unwrap())
         .add_directive("reqwest=off".parse().unwrap());
-    let otel_layer = otel_layer.with_filter(filter_otel);
 
-    // Create a new tracing::Fmt layer to print the logs to stdout. It has a
-    // default filter of `info` level and above, and `debug` and above for logs
-    // from OpenTelemetry crates. The filter levels can be customized as needed.
-    let fmt_log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| DEFAULT_LOG_LEVEL.to_string());
+    with_extra_directives(filter, "OTEL_EXTRA_DIRECTIVES")
+}
 
-    let filter_fmt = EnvFilter::new(fmt_log_level.clone())
-        .add_directive(
-            format!("{}={fmt_log_level}", name.to_owned())
-                .parse()
-                .unwrap(),
-        )
+fn with_fmt_directives(filter: EnvFilter, name: &str, level: &str) -> EnvFilter {
+    let filter = with_service_directive(filter, name, level)
         .add_directive("hyper=error".parse().unwrap())
         .add_directive("h2=error".parse().unwrap())
         .add_directive("reqwest=error".parse().unwrap())
@@ -137,31 +857,440 @@ pub fn setup_opentelemetry(name: &'static str) -> SdkTracerProvider {
         .add_directive("tokio_postgres=error".parse().unwrap())
         .add_directive("tracing=error".parse().unwrap())
         .add_directive("opentelemetry=error".parse().unwrap());
-    let fmt_layer = tracing_subscriber::fmt::layer()
-        .with_span_events(FmtSpan::CLOSE)
-        .with_thread_names(true)
-        .json()
-        .flatten_event(true)
-        .with_level(true)
-        .with_line_number(true)
-        .with_filter(filter_fmt);
 
-    // Initialize the tracing subscriber with the OpenTelemetry layer and the
-    // Fmt layer.
-    tracing_subscriber::registry()
-        .with(otel_layer)
-        .with(fmt_layer)
-        .init();
+    with_extra_directives(filter, "LOG_EXTRA_DIRECTIVES")
+}
 
-    // Add callback to unset opentelemetry automatically
-    closer::push_callback(Box::new(|| unset_opentelemetry(name)));
+/// Mutes exporter dependencies on top of the Fmt layer directives, so logs of the export itself
+/// aren't exported.
+fn with_logs_directives(filter: EnvFilter, name: &str, level: &str) -> EnvFilter {
+    with_fmt_directives(filter, name, level)
+        .add_directive("opentelemetry=off".parse().unwrap())
+        .add_directive("hyper=off".parse().unwrap())
+        .add_directive("h2=off".parse().unwrap())
+        .add_directive("tonic=off".parse().unwrap())
+        .add_directive("reqwest=off".parse().unwrap())
+}
 
-    tracer_provider
+/// Applies comma-separated directives from the env variable, they override the default ones with
+/// the same target.
+fn with_extra_directives(filter: EnvFilter, var: &str) -> EnvFilter {
+    add_directives(filter, var, &env::var(var).unwrap_or_default())
 }
 
-/// Close tracer provider.
+fn add_directives(filter: EnvFilter, var: &str, directives: &str) -> EnvFilter {
+    directives
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .fold(filter, |filter, directive| {
+            filter.add_directive(
+                directive
+                    .parse()
+                    .unwrap_or_else(|e| panic!("Invalid {var} directive {directive}: {e}")),
+            )
+        })
+}
+
+/// Applies the level to the service crate, skipped when the level is a list of directives.
+fn with_service_directive(filter: EnvFilter, name: &str, level: &str) -> EnvFilter {
+    match format!("{name}={level}").parse() {
+        Ok(directive) => filter.add_directive(directive),
+        Err(_) => filter,
+    }
+}
+
+/// Returns tracer provider set up by [`setup_opentelemetry`], `None` before the setup.
+pub fn tracer_provider() -> Option<SdkTracerProvider> {
+    TRACER_PROVIDER.get().cloned()
+}
+
+/// Exports pending spans, metrics and logs immediately, e.g. before exit of short-lived jobs. Does
+/// nothing before [`setup_opentelemetry`].
+pub fn force_flush() -> anyhow::Result<()> {
+    flush_providers(
+        TRACER_PROVIDER.get(),
+        METER_PROVIDER.get().and_then(Option::as_ref),
+        LOGGER_PROVIDER.get().and_then(Option::as_ref),
+    )
+}
+
+fn flush_providers(
+    tracer_provider: Option<&SdkTracerProvider>,
+    meter_provider: Option<&SdkMeterProvider>,
+    logger_provider: Option<&SdkLoggerProvider>,
+) -> anyhow::Result<()> {
+    if let Some(tracer_provider) = tracer_provider {
+        tracer_provider
+            .force_flush()
+            .map_err(|e| anyhow!("failed to flush tracer provider: {e}"))?;
+    }
+
+    if let Some(meter_provider) = meter_provider {
+        meter_provider
+            .force_flush()
+            .map_err(|e| anyhow!("failed to flush meter provider: {e}"))?;
+    }
+
+    if let Some(logger_provider) = logger_provider {
+        logger_provider
+            .force_flush()
+            .map_err(|e| anyhow!("failed to flush logger provider: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Writes context of the current span into outbound request headers with the global propagator.
+pub fn inject_context(headers: &mut HeaderMap) {
+    let cx = tracing::Span::current().context();
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&cx, &mut HeaderInjector(headers))
+    });
+}
+
+/// Writes propagated context into request headers.
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Close tracer, meter and logger providers, flushing pending spans, metrics and logs. Pending
+/// exports aren't awaited when the collector is unreachable.
 pub fn unset_opentelemetry(name: &str) {
-    if let Err(e) = get_tracer_provider(name.to_owned()).shutdown() {
+    const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+    let timeout = match COLLECTOR_UNREACHABLE.load(Ordering::Relaxed) {
+        true => UNREACHABLE_SHUTDOWN_TIMEOUT,
+        false => SHUTDOWN_TIMEOUT,
+    };
+
+    if let Err(e) = get_tracer_provider(name.to_owned()).shutdown_with_timeout(timeout) {
         tracing::error!("Failed to shutdown tracer provider: {}", e);
     };
+
+    if let Some(Some(meter_provider)) = METER_PROVIDER.get()
+        && let Err(e) = meter_provider.shutdown_with_timeout(timeout)
+    {
+        tracing::error!("Failed to shutdown meter provider: {}", e);
+    }
+
+    if let Some(Some(logger_provider)) = LOGGER_PROVIDER.get()
+        && let Err(e) = logger_provider.shutdown_with_timeout(timeout)
+    {
+        tracing::error!("Failed to shutdown logger provider: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use opentelemetry::{Key, trace::Tracer};
+    use opentelemetry_sdk::{logs::InMemoryLogExporter, trace::InMemorySpanExporter};
+
+    use super::*;
+
+    /// Collects logs written by the fmt layer.
+    #[derive(Clone, Default)]
+    struct LogWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for LogWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Returns logs written by `f` with the fmt layer.
+    fn capture_logs(
+        format: LogFormat,
+        field_names: Option<LogFieldNames>,
+        f: impl FnOnce(),
+    ) -> String {
+        let writer = LogWriter::default();
+        let make_writer = writer.clone();
+        let subscriber =
+            tracing_subscriber::registry().with(get_fmt_layer(format, field_names, move || {
+                make_writer.clone()
+            }));
+
+        tracing::subscriber::with_default(subscriber, f);
+
+        let logs = writer.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    #[test]
+    fn each_log_format_writes_events() {
+        for format in [LogFormat::Json, LogFormat::Pretty, LogFormat::Compact] {
+            let logs = capture_logs(format, None, || tracing::info!(answer = 42, "hello"));
+
+            assert!(logs.contains("hello"), "{logs}");
+            assert!(logs.contains("42"), "{logs}");
+        }
+    }
+
+    #[test]
+    fn json_log_format_writes_flat_objects() {
+        let logs = capture_logs(LogFormat::Json, None, || {
+            tracing::info!(answer = 42, "hello")
+        });
+
+        let event: serde_json::Value = serde_json::from_str(&logs).unwrap();
+        assert_eq!(event["message"], "hello");
+        assert_eq!(event["answer"], 42);
+        assert_eq!(event["level"], "INFO");
+    }
+
+    fn field_names(renames: &[(&str, &str)], gcp_severity: bool) -> LogFieldNames {
+        LogFieldNames {
+            renames: renames
+                .iter()
+                .map(|(from, to)| ((*from).to_owned(), (*to).to_owned()))
+                .collect(),
+            gcp_severity,
+        }
+    }
+
+    #[test]
+    fn json_log_fields_are_renamed() {
+        let names = field_names(&[("message", "msg"), ("timestamp", "@timestamp")], false);
+        let logs = capture_logs(LogFormat::Json, Some(names), || {
+            tracing::warn!(answer = 42, "hello")
+        });
+
+        let event: serde_json::Value = serde_json::from_str(&logs).unwrap();
+        assert_eq!(event["msg"], "hello");
+        assert!(event["@timestamp"].is_string(), "{logs}");
+        assert!(event.get("message").is_none(), "{logs}");
+        assert!(event.get("timestamp").is_none(), "{logs}");
+        // fields without renames are kept
+        assert_eq!(event["answer"], 42);
+        assert_eq!(event["level"], "WARN");
+    }
+
+    #[test]
+    fn gcp_log_severity_uses_gcp_levels() {
+        let names = field_names(&[("level", "severity")], true);
+        let logs = capture_logs(LogFormat::Json, Some(names), || {
+            tracing::warn!("hello");
+            tracing::info!("hello");
+        });
+
+        let severities: Vec<_> = logs
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).unwrap()["severity"].clone()
+            })
+            .collect();
+        assert_eq!(severities, ["WARNING", "INFO"]);
+    }
+
+    #[test]
+    fn setup_twice_is_a_no_op() {
+        setup_opentelemetry("caslex-test");
+        setup_opentelemetry("caslex-test");
+
+        assert!(tracing::dispatcher::has_been_set());
+        assert!(tracer_provider().is_some());
+    }
+
+    #[test]
+    fn pending_spans_are_exported_on_flush() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter.clone())
+            .build();
+
+        provider.tracer("caslex-test").in_span("job", |_| {});
+        assert!(exporter.get_finished_spans().unwrap().is_empty());
+
+        flush_providers(Some(&provider), None, None).unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "job");
+    }
+
+    #[test]
+    fn log_events_are_exported_with_trace_context() {
+        let exporter = InMemoryLogExporter::default();
+        let logger_provider = SdkLoggerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let tracer_provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("caslex-test")))
+            .with(LogsLayer {
+                logger: logger_provider.logger("caslex-test"),
+            });
+
+        let trace_id = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("job");
+            let _entered = span.enter();
+            // the event of a child span is linked to the trace of the root span
+            tracing::info_span!("step").in_scope(|| tracing::info!(order_id = 42, "order created"));
+            span.context().span().span_context().trace_id()
+        });
+
+        let logs = exporter.get_emitted_logs().unwrap();
+        assert_eq!(logs.len(), 1);
+        let record = &logs[0].record;
+        assert_eq!(record.body(), Some(&AnyValue::from("order created")));
+        assert_eq!(record.severity_number(), Some(Severity::Info));
+        assert!(
+            record
+                .attributes_iter()
+                .any(|(key, value)| *key == Key::from("order_id") && *value == AnyValue::Int(42))
+        );
+        assert_eq!(record.trace_context().unwrap().trace_id, trace_id);
+    }
+
+    /// Returns logs written by `f` with the fmt layer filtered by the filter.
+    fn capture_filtered_logs(filter: EnvFilter, f: impl FnOnce()) -> String {
+        let writer = LogWriter::default();
+        let make_writer = writer.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            get_fmt_layer(LogFormat::Compact, None, move || make_writer.clone())
+                .with_filter(filter),
+        );
+
+        tracing::subscriber::with_default(subscriber, f);
+
+        let logs = writer.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    fn emit_noisy_events() {
+        tracing::debug!(target: "hyper", "hyper debug");
+        tracing::trace!(target: "caslex_test", "service trace");
+    }
+
+    #[test]
+    fn extra_directives_override_defaults() {
+        let defaults = || with_fmt_directives(EnvFilter::new("info"), "caslex_test", "info");
+        let logs = capture_filtered_logs(defaults(), emit_noisy_events);
+        assert!(logs.is_empty(), "{logs}");
+
+        let filter = add_directives(
+            defaults(),
+            "LOG_EXTRA_DIRECTIVES",
+            " hyper=debug,,caslex_test=trace ",
+        );
+        let logs = capture_filtered_logs(filter, emit_noisy_events);
+
+        assert!(logs.contains("hyper debug"), "{logs}");
+        assert!(logs.contains("service trace"), "{logs}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid LOG_EXTRA_DIRECTIVES directive")]
+    fn invalid_extra_directive_panics() {
+        add_directives(EnvFilter::new("info"), "LOG_EXTRA_DIRECTIVES", "hyper=loud");
+    }
+
+    #[test]
+    fn context_of_current_span_is_injected() {
+        use opentelemetry::trace::TraceContextExt;
+        use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("caslex-test")));
+
+        let (headers, trace_id) = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("outbound");
+            let _entered = span.enter();
+
+            let mut headers = HeaderMap::new();
+            inject_context(&mut headers);
+            (headers, span.context().span().span_context().trace_id())
+        });
+
+        let traceparent = headers["traceparent"].to_str().unwrap();
+        assert!(
+            traceparent.starts_with(&format!("00-{trace_id}-")),
+            "{traceparent}"
+        );
+        assert!(traceparent.ends_with("-01"), "{traceparent}");
+    }
+
+    #[test]
+    fn nothing_is_injected_without_active_span() {
+        use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let mut headers = HeaderMap::new();
+        inject_context(&mut headers);
+
+        assert!(headers.is_empty());
+    }
+
+    fn sampling_decision() -> opentelemetry::trace::SamplingDecision {
+        let sampler = ReloadableSampler(Sampler::ParentBased(Box::new(RatioSampler)));
+
+        sampler
+            .should_sample(None, TraceId::from(1), "root", &SpanKind::Server, &[], &[])
+            .decision
+    }
+
+    #[test]
+    fn sampling_decision_follows_changed_ratio() {
+        use opentelemetry::trace::SamplingDecision;
+
+        sampling::init_sampling_ratio(1.0).unwrap();
+
+        sampling::set_sampling_ratio(0.0).unwrap();
+        assert_eq!(sampling_decision(), SamplingDecision::Drop);
+
+        sampling::set_sampling_ratio(1.0).unwrap();
+        assert_eq!(sampling_decision(), SamplingDecision::RecordAndSample);
+    }
+
+    #[test]
+    fn unreachable_collector_does_not_block_export() {
+        let started = Instant::now();
+        // TEST-NET-1 address, nothing answers there
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint("http://192.0.2.1:4318/v1/traces")
+            .with_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(DegradingExporter::new(exporter, "spans"))
+            .build();
+        provider.tracer("caslex-test").in_span("job", |_| {});
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // the batch is dropped instead of failing the flush
+        provider.force_flush().unwrap();
+        assert!(COLLECTOR_UNREACHABLE.load(Ordering::Relaxed));
+
+        let started = Instant::now();
+        provider
+            .shutdown_with_timeout(UNREACHABLE_SHUTDOWN_TIMEOUT)
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
 }
@@ -0,0 +1,80 @@
+//! Contains runtime trace sampling ratio reload.
+//!
+//! The tracer provider set up by `observability` feature samples root spans with the ratio stored
+//! here, the initial ratio is taken from `OTEL_SAMPLING_RATIO` environment variable. Ratio `1`
+//! samples all spans, lower ratios follow the sampling decision of the parent span.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use caslex_extra::sampling::set_sampling_ratio;
+//!
+//! // sample all traces during an incident
+//! set_sampling_ratio(1.0).unwrap();
+//! ```
+
+use std::sync::{
+    OnceLock,
+    atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::anyhow;
+
+/// Ratio stored as `f64` bits.
+static RATIO: OnceLock<AtomicU64> = OnceLock::new();
+
+/// Sets initial ratio, only the first call takes effect.
+#[cfg(feature = "observability")]
+pub(crate) fn init_sampling_ratio(ratio: f64) -> anyhow::Result<()> {
+    validate(ratio)?;
+    let _ = RATIO.set(AtomicU64::new(ratio.to_bits()));
+
+    Ok(())
+}
+
+/// Sets sampling ratio of the tracer provider, the ratio must be in `0..=1` range.
+pub fn set_sampling_ratio(ratio: f64) -> anyhow::Result<()> {
+    let current = RATIO
+        .get()
+        .ok_or_else(|| anyhow!("sampling ratio reload is not configured"))?;
+    validate(ratio)?;
+    current.store(ratio.to_bits(), Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Returns current sampling ratio, `None` before the tracer provider is set up.
+pub fn sampling_ratio() -> Option<f64> {
+    RATIO
+        .get()
+        .map(|ratio| f64::from_bits(ratio.load(Ordering::Relaxed)))
+}
+
+/// Returns true if sampling ratio can be changed.
+pub fn is_sampling_ratio_reloadable() -> bool {
+    RATIO.get().is_some()
+}
+
+fn validate(ratio: f64) -> anyhow::Result<()> {
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(anyhow!(
+            "sampling ratio must be in 0..=1 range, got {ratio}"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratio_out_of_range_is_rejected() {
+        assert!(validate(0.0).is_ok());
+        assert!(validate(1.0).is_ok());
+        assert!(validate(-0.1).is_err());
+        assert!(validate(1.5).is_err());
+        assert!(validate(f64::NAN).is_err());
+    }
+}
@@ -3,7 +3,9 @@
 //! # Example
 //!
 //! ```rust,no_run
-//! use caslex_extra::security::jwt::{decode_token, encode_token, expiry};
+//! use std::time::Duration;
+//!
+//! use caslex_extra::security::jwt::{decode_token, encode_token, expiry_in, is_expired};
 //! use serde::{Deserialize, Serialize};
 //!
 //! #[derive(Serialize, Deserialize)]
@@ -13,7 +15,7 @@
 //! }
 //!
 //! // generate expiry 60 seconds
-//! let exp = expiry(60);
+//! let exp = expiry_in(Duration::from_secs(60));
 //!
 //! let claims = Claims {
 //!     sub: "123".to_owned(),
@@ -24,49 +26,607 @@
 //! let decoded_token = decode_token::<Claims>(&encoded_token).unwrap();
 //!
 //! assert_eq!(decoded_token.claims.sub, claims.sub);
+//! assert!(!is_expired(decoded_token.claims.exp, None));
 //! ```
 //!
-//! JWT secret key configure via environment variable `JWT_SECRET`.
+//! JWT keys configure via environment variables:
+//! * `JWT_ALGORITHM` - signing algorithm, `HS256` by default;
+//! * `JWT_SECRET` - secret key for `HS256`, `HS384`, `HS512` algorithms;
+//! * `JWT_PUBLIC_KEY` - PEM encoded public key for `RS*`, `PS*`, `ES*`, `EdDSA` algorithms;
+//! * `JWT_PRIVATE_KEY` - PEM encoded private key for `RS*`, `PS*`, `ES*`, `EdDSA` algorithms,
+//!   required only for encoding tokens;
+//! * `JWT_LEEWAY_SECS` - leeway in seconds for `exp` and `nbf` claims validation, `0` by default;
+//! * `JWT_AUDIENCE` - comma-separated accepted `aud` claim values, the claim is required when set;
+//! * `JWT_ISSUER` - comma-separated accepted `iss` claim values, the claim is required when set.
+//!
+//! Missing or invalid keys and leeway never panic, token functions return
+//! [`ErrorKind::InvalidKeyFormat`] error instead. Call [`init_keys`] on startup to surface keys
+//! configuration errors early.
+//!
+//! # JWKS
+//!
+//! With `jwks` feature tokens can be verified by keys from a remote JWKS endpoint. Decoding key
+//! is selected by the token `kid` header. Keys are refreshed every `JWT_JWKS_TTL` (`5m` by
+//! default) and on unknown `kid`.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "jwks")]
+//! # async fn run() -> anyhow::Result<()> {
+//! use caslex_extra::security::jwt::init_jwks;
+//!
+//! init_jwks("https://example.com/.well-known/jwks.json").await?;
+//! # Ok(())
+//! # }
+//! ```
 
-use std::sync::LazyLock;
+use std::{env, str::FromStr, sync::LazyLock, time::Duration};
 
 use jsonwebtoken::{
-    DecodingKey, EncodingKey, Header, TokenData, Validation, decode, encode, errors::Error,
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation, decode, encode,
+    errors::{Error, ErrorKind},
     get_current_timestamp,
 };
 use serde::{Serialize, de::DeserializeOwned};
 
-static KEYS: LazyLock<Keys> = LazyLock::new(|| {
-    let secret = std::env::var("JWT_SECRET")
-        .expect("environment variable must be set for using jwt: JWT_SECRET");
-    Keys::new(secret.as_bytes())
-});
+static KEYS: LazyLock<Result<Keys, ErrorKind>> = LazyLock::new(Keys::from_env);
+
+static AUDIENCE: LazyLock<Vec<String>> = LazyLock::new(|| env_list("JWT_AUDIENCE"));
+
+static ISSUER: LazyLock<Vec<String>> = LazyLock::new(|| env_list("JWT_ISSUER"));
+
+fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns `JWT_LEEWAY_SECS` leeway, `0` when omitted.
+fn leeway_from_env() -> Result<u64, ErrorKind> {
+    env::var("JWT_LEEWAY_SECS").map_or(Ok(0), |leeway| parse_leeway(&leeway))
+}
+
+fn parse_leeway(leeway: &str) -> Result<u64, ErrorKind> {
+    leeway
+        .trim()
+        .parse()
+        .map_err(|_| ErrorKind::InvalidKeyFormat)
+}
 
 struct Keys {
-    encoding: EncodingKey,
+    algorithm: Algorithm,
+    encoding: Option<EncodingKey>,
     decoding: DecodingKey,
+    leeway: u64,
 }
 
 impl Keys {
-    fn new(secret: &[u8]) -> Self {
+    fn from_env() -> Result<Self, ErrorKind> {
+        let algorithm = match env::var("JWT_ALGORITHM") {
+            Ok(algorithm) => Algorithm::from_str(&algorithm).map_err(Error::into_kind)?,
+            Err(_) => Algorithm::HS256,
+        };
+        let leeway = leeway_from_env()?;
+
+        match algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                let secret = env::var("JWT_SECRET").unwrap_or_default();
+                if secret.is_empty() {
+                    return Err(ErrorKind::InvalidKeyFormat);
+                }
+                Ok(Self::from_secret(algorithm, secret.as_bytes(), leeway))
+            }
+            _ => {
+                let public_key =
+                    env::var("JWT_PUBLIC_KEY").map_err(|_| ErrorKind::InvalidKeyFormat)?;
+                let private_key = env::var("JWT_PRIVATE_KEY").ok();
+                Self::from_pem(
+                    algorithm,
+                    public_key.as_bytes(),
+                    private_key.as_ref().map(String::as_bytes),
+                    leeway,
+                )
+            }
+        }
+    }
+
+    fn from_secret(algorithm: Algorithm, secret: &[u8], leeway: u64) -> Self {
         Self {
-            encoding: EncodingKey::from_secret(secret),
+            algorithm,
+            encoding: Some(EncodingKey::from_secret(secret)),
             decoding: DecodingKey::from_secret(secret),
+            leeway,
         }
     }
+
+    fn from_pem(
+        algorithm: Algorithm,
+        public_key: &[u8],
+        private_key: Option<&[u8]>,
+        leeway: u64,
+    ) -> Result<Self, ErrorKind> {
+        let (encoding, decoding) = match algorithm {
+            Algorithm::ES256 | Algorithm::ES384 => (
+                private_key.map(EncodingKey::from_ec_pem).transpose(),
+                DecodingKey::from_ec_pem(public_key),
+            ),
+            Algorithm::EdDSA => (
+                private_key.map(EncodingKey::from_ed_pem).transpose(),
+                DecodingKey::from_ed_pem(public_key),
+            ),
+            _ => (
+                private_key.map(EncodingKey::from_rsa_pem).transpose(),
+                DecodingKey::from_rsa_pem(public_key),
+            ),
+        };
+
+        let encoding = encoding.map_err(Error::into_kind)?;
+        let decoding = decoding.map_err(Error::into_kind)?;
+
+        Ok(Self {
+            algorithm,
+            encoding,
+            decoding,
+            leeway,
+        })
+    }
+}
+
+fn get_keys() -> Result<&'static Keys, Error> {
+    KEYS.as_ref().map_err(|kind| kind.clone().into())
+}
+
+/// Initialize keys from environment variables.
+///
+/// Returns error if keys configuration is invalid.
+pub fn init_keys() -> Result<(), Error> {
+    get_keys().map(|_| ())
+}
+
+fn default_validation(algorithm: Algorithm, leeway: u64) -> Validation {
+    build_validation(algorithm, leeway, &AUDIENCE, &ISSUER)
+}
+
+/// Returns validation requiring `aud` and `iss` claims to match one of the values unless they are
+/// empty.
+fn build_validation(
+    algorithm: Algorithm,
+    leeway: u64,
+    audience: &[String],
+    issuer: &[String],
+) -> Validation {
+    let mut validation = Validation::new(algorithm);
+    validation.leeway = leeway;
+    if !audience.is_empty() {
+        validation.set_audience(audience);
+        validation.required_spec_claims.insert("aud".to_owned());
+    }
+    if !issuer.is_empty() {
+        validation.set_issuer(issuer);
+        validation.required_spec_claims.insert("iss".to_owned());
+    }
+    validation
+}
+
+/// Returns leeway of the initialized JWKS or keys.
+fn configured_leeway() -> u64 {
+    #[cfg(feature = "jwks")]
+    if let Some(jwks) = JWKS.get() {
+        return jwks.leeway;
+    }
+
+    get_keys().map_or_else(
+        |_| leeway_from_env().unwrap_or_default(),
+        |keys| keys.leeway,
+    )
+}
+
+/// Returns expiry timestamp in seconds, `valid_for` from now.
+pub fn expiry_in(valid_for: Duration) -> u64 {
+    get_current_timestamp().saturating_add(valid_for.as_secs())
 }
 
 /// Returns expiry seconds.
 pub fn expiry(secs_valid_for: u64) -> u64 {
-    get_current_timestamp() + secs_valid_for
+    expiry_in(Duration::from_secs(secs_valid_for))
+}
+
+/// Returns true if `exp` claim is in the past, `leeway` extends the validity as in token
+/// validation. `JWT_LEEWAY_SECS` leeway is used when omitted, no leeway when it's invalid.
+pub fn is_expired(exp: u64, leeway: Option<Duration>) -> bool {
+    let leeway = leeway.map_or_else(configured_leeway, |leeway| leeway.as_secs());
+
+    exp.saturating_add(leeway) < get_current_timestamp()
 }
 
 /// Encode token.
 pub fn encode_token<T: Serialize>(claims: &T) -> Result<String, Error> {
-    encode(&Header::default(), &claims, &KEYS.encoding)
+    let keys = get_keys()?;
+    let encoding = keys
+        .encoding
+        .as_ref()
+        .ok_or_else(|| Error::from(ErrorKind::InvalidKeyFormat))?;
+
+    encode(&Header::new(keys.algorithm), &claims, encoding)
 }
 
 /// Decode token.
+///
+/// Uses JWKS keys if [`init_jwks`] was called.
 pub fn decode_token<T: DeserializeOwned>(token: &str) -> Result<TokenData<T>, Error> {
-    decode::<T>(token, &KEYS.decoding, &Validation::default())
+    #[cfg(feature = "jwks")]
+    if let Some(jwks) = JWKS.get() {
+        return jwks.decode_token(token, None);
+    }
+
+    let keys = get_keys()?;
+    decode::<T>(
+        token,
+        &keys.decoding,
+        &default_validation(keys.algorithm, keys.leeway),
+    )
+}
+
+/// Decode token with custom validation.
+pub fn decode_token_with_validation<T: DeserializeOwned>(
+    token: &str,
+    validation: &Validation,
+) -> Result<TokenData<T>, Error> {
+    #[cfg(feature = "jwks")]
+    if let Some(jwks) = JWKS.get() {
+        return jwks.decode_token(token, Some(validation));
+    }
+
+    let keys = get_keys()?;
+    decode::<T>(token, &keys.decoding, validation)
+}
+
+#[cfg(feature = "jwks")]
+static JWKS: std::sync::OnceLock<Jwks> = std::sync::OnceLock::new();
+
+#[cfg(feature = "jwks")]
+struct JwksKey {
+    decoding: DecodingKey,
+    algorithms: Vec<Algorithm>,
+}
+
+#[cfg(feature = "jwks")]
+struct Jwks {
+    url: String,
+    leeway: u64,
+    keys: std::sync::RwLock<std::collections::HashMap<String, JwksKey>>,
+    refresh: tokio::sync::Notify,
+}
+
+#[cfg(feature = "jwks")]
+impl Jwks {
+    async fn fetch(&self) -> anyhow::Result<()> {
+        let set = reqwest::get(&self.url)
+            .await?
+            .error_for_status()?
+            .json::<jsonwebtoken::jwk::JwkSet>()
+            .await?;
+
+        let keys = set
+            .keys
+            .iter()
+            .filter_map(|jwk| {
+                let kid = jwk.common.key_id.clone()?;
+                let decoding = DecodingKey::from_jwk(jwk).ok()?;
+                Some((
+                    kid,
+                    JwksKey {
+                        decoding,
+                        algorithms: jwk_algorithms(jwk),
+                    },
+                ))
+            })
+            .collect();
+
+        *self.keys.write().unwrap() = keys;
+
+        Ok(())
+    }
+
+    async fn refresh_loop(&self, ttl: std::time::Duration) {
+        const MIN_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(ttl) => {},
+                _ = self.refresh.notified() => {},
+            }
+
+            if let Err(e) = self.fetch().await {
+                tracing::warn!("Failed to refresh JWKS from {}: {}", self.url, e);
+            }
+
+            // don't hammer JWKS endpoint with tokens signed by unknown keys
+            tokio::time::sleep(MIN_REFRESH_INTERVAL).await;
+        }
+    }
+
+    fn decode_token<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        validation: Option<&Validation>,
+    ) -> Result<TokenData<T>, Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let kid = header
+            .kid
+            .ok_or_else(|| Error::from(ErrorKind::InvalidToken))?;
+
+        let keys = self.keys.read().unwrap();
+        let Some(key) = keys.get(&kid) else {
+            self.refresh.notify_one();
+            return Err(ErrorKind::InvalidSignature.into());
+        };
+
+        if !key.algorithms.contains(&header.alg) {
+            return Err(ErrorKind::InvalidAlgorithm.into());
+        }
+
+        match validation {
+            Some(validation) => decode::<T>(token, &key.decoding, validation),
+            None => decode::<T>(
+                token,
+                &key.decoding,
+                &default_validation(header.alg, self.leeway),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "jwks")]
+fn jwk_algorithms(jwk: &jsonwebtoken::jwk::Jwk) -> Vec<Algorithm> {
+    use jsonwebtoken::jwk::AlgorithmParameters;
+
+    if let Some(algorithm) = jwk.common.key_algorithm {
+        return Algorithm::from_str(&algorithm.to_string())
+            .map(|algorithm| vec![algorithm])
+            .unwrap_or_default();
+    }
+
+    match jwk.algorithm {
+        AlgorithmParameters::RSA(_) => vec![
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::PS256,
+            Algorithm::PS384,
+            Algorithm::PS512,
+        ],
+        AlgorithmParameters::EllipticCurve(_) => vec![Algorithm::ES256, Algorithm::ES384],
+        AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+        // symmetric keys must never be published
+        AlgorithmParameters::OctetKey(_) => vec![],
+    }
+}
+
+/// Initialize JWKS verification.
+///
+/// Fetches key set from the url and spawns background task refreshing it every `JWT_JWKS_TTL`
+/// (`5m` by default) and on unknown `kid`. After initialization [`decode_token`] verifies tokens
+/// by JWKS keys only.
+#[cfg(feature = "jwks")]
+pub async fn init_jwks(url: &str) -> anyhow::Result<()> {
+    const DEFAULT_JWKS_TTL: std::time::Duration = std::time::Duration::from_secs(300);
+
+    let ttl = match env::var("JWT_JWKS_TTL") {
+        Ok(ttl) => humantime::parse_duration(&ttl)?,
+        Err(_) => DEFAULT_JWKS_TTL,
+    };
+
+    let leeway = leeway_from_env().map_err(|_| anyhow::anyhow!("Invalid JWT_LEEWAY_SECS"))?;
+
+    let jwks = Jwks {
+        url: url.to_owned(),
+        leeway,
+        keys: Default::default(),
+        refresh: tokio::sync::Notify::new(),
+    };
+    jwks.fetch().await?;
+
+    if JWKS.set(jwks).is_err() {
+        return Err(anyhow::anyhow!("JWKS is already initialized"));
+    }
+
+    tokio::spawn(async move {
+        if let Some(jwks) = JWKS.get() {
+            jwks.refresh_loop(ttl).await;
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: u64,
+    }
+
+    fn token_expired_secs_ago(keys: &Keys, secs: u64) -> String {
+        let claims = Claims {
+            sub: "123".to_owned(),
+            exp: get_current_timestamp() - secs,
+        };
+        encode(
+            &Header::new(keys.algorithm),
+            &claims,
+            keys.encoding.as_ref().unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn decode_with_keys(keys: &Keys, token: &str) -> Result<TokenData<Claims>, Error> {
+        decode::<Claims>(
+            token,
+            &keys.decoding,
+            &default_validation(keys.algorithm, keys.leeway),
+        )
+    }
+
+    #[test]
+    fn token_expired_within_leeway_is_valid() {
+        let keys = Keys::from_secret(Algorithm::HS256, b"secret", 60);
+        let token = token_expired_secs_ago(&keys, 30);
+
+        let token = decode_with_keys(&keys, &token).unwrap();
+
+        assert_eq!(token.claims.sub, "123");
+    }
+
+    #[test]
+    fn token_expired_beyond_leeway_is_rejected() {
+        let keys = Keys::from_secret(Algorithm::HS256, b"secret", 10);
+        let token = token_expired_secs_ago(&keys, 30);
+
+        let error = decode_with_keys(&keys, &token).unwrap_err();
+
+        assert_eq!(*error.kind(), ErrorKind::ExpiredSignature);
+    }
+
+    #[test]
+    fn invalid_leeway_is_an_error() {
+        assert_eq!(parse_leeway(" 30 "), Ok(30));
+        assert_eq!(parse_leeway("30s"), Err(ErrorKind::InvalidKeyFormat));
+        assert_eq!(parse_leeway("-1"), Err(ErrorKind::InvalidKeyFormat));
+    }
+
+    #[test]
+    fn is_expired_respects_leeway() {
+        let exp = get_current_timestamp() - 30;
+
+        assert!(is_expired(exp, Some(Duration::from_secs(10))));
+        assert!(!is_expired(exp, Some(Duration::from_secs(60))));
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TenantClaims {
+        sub: String,
+        exp: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        aud: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        iss: Option<String>,
+    }
+
+    /// Decodes token with `aud` and `iss` claims, validating them against the passed values.
+    fn decode_tenant_token(
+        aud: Option<&str>,
+        iss: Option<&str>,
+        audience: &[&str],
+        issuer: &[&str],
+    ) -> Result<TokenData<TenantClaims>, Error> {
+        let keys = Keys::from_secret(Algorithm::HS256, b"secret", 0);
+        let claims = TenantClaims {
+            sub: "123".to_owned(),
+            exp: get_current_timestamp() + 60,
+            aud: aud.map(str::to_owned),
+            iss: iss.map(str::to_owned),
+        };
+        let token = encode(
+            &Header::new(keys.algorithm),
+            &claims,
+            keys.encoding.as_ref().unwrap(),
+        )
+        .unwrap();
+
+        let to_owned = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let validation = build_validation(
+            keys.algorithm,
+            keys.leeway,
+            &to_owned(audience),
+            &to_owned(issuer),
+        );
+
+        decode(&token, &keys.decoding, &validation)
+    }
+
+    #[test]
+    fn matching_audience_and_issuer_are_accepted() {
+        let token = decode_tenant_token(
+            Some("orders"),
+            Some("https://auth.local"),
+            &["billing", "orders"],
+            &["https://auth.local"],
+        )
+        .unwrap();
+
+        assert_eq!(token.claims.aud.as_deref(), Some("orders"));
+    }
+
+    #[test]
+    fn mismatching_audience_and_issuer_are_rejected() {
+        let error = decode_tenant_token(Some("billing"), None, &["orders"], &[]).unwrap_err();
+        assert_eq!(*error.kind(), ErrorKind::InvalidAudience);
+
+        let error = decode_tenant_token(
+            None,
+            Some("https://evil.local"),
+            &[],
+            &["https://auth.local"],
+        )
+        .unwrap_err();
+        assert_eq!(*error.kind(), ErrorKind::InvalidIssuer);
+    }
+
+    #[test]
+    fn missing_audience_and_issuer_are_rejected_when_configured() {
+        let error = decode_tenant_token(None, None, &["orders"], &[]).unwrap_err();
+        assert_eq!(
+            *error.kind(),
+            ErrorKind::MissingRequiredClaim("aud".to_owned())
+        );
+
+        let error = decode_tenant_token(None, None, &[], &["https://auth.local"]).unwrap_err();
+        assert_eq!(
+            *error.kind(),
+            ErrorKind::MissingRequiredClaim("iss".to_owned())
+        );
+    }
+
+    #[test]
+    fn audience_and_issuer_are_not_validated_when_unset() {
+        assert!(decode_tenant_token(None, None, &[], &[]).is_ok());
+        assert!(decode_tenant_token(None, Some("https://any.local"), &[], &[]).is_ok());
+    }
+
+    #[test]
+    fn expiry_in_adds_duration_to_now() {
+        let before = get_current_timestamp();
+        let exp = expiry_in(Duration::from_mins(5));
+        let secs_exp = expiry(300);
+        let after = get_current_timestamp();
+
+        assert!((before + 300..=after + 300).contains(&exp), "{exp}");
+        assert!(
+            (before + 300..=after + 300).contains(&secs_exp),
+            "{secs_exp}"
+        );
+    }
+
+    #[test]
+    fn expiration_is_checked_around_now_with_leeway() {
+        let now = get_current_timestamp();
+        let no_leeway = Some(Duration::ZERO);
+
+        assert!(!is_expired(now + 1, no_leeway));
+        assert!(is_expired(now - 1, no_leeway));
+        assert!(!is_expired(now - 10, Some(Duration::from_secs(30))));
+        assert!(is_expired(now - 10, Some(Duration::from_secs(5))));
+    }
 }
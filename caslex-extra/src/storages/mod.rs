@@ -2,5 +2,9 @@
 
 #![cfg_attr(docsrs, feature(doc_auto_cfg, doc_cfg))]
 
+#[cfg(feature = "migrations")]
+pub mod postgres_migrations;
 #[cfg(feature = "postgres")]
 pub mod postgres_pool;
+#[cfg(feature = "redis")]
+pub mod redis_pool;
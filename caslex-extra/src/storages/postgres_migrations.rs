@@ -0,0 +1,324 @@
+//! Contains postgres migration runner.
+//!
+//! Migrations are SQL files named `<version>_<name>.sql`, e.g. `0001_create_users.sql`, applied in
+//! the version order. Each pending migration is applied in its own transaction together with its
+//! record in the `_migrations` table, so already applied migrations are skipped on the next run.
+//! Runs are serialized with a postgres advisory lock, so concurrent service instances don't apply
+//! the same migration twice.
+//!
+//! # Example
+//!
+//! Apply migrations from a directory:
+//!
+//! ```rust,no_run
+//! use caslex_extra::storages::postgres_migrations::Migrator;
+//!
+//! # async fn run(pool: deadpool_postgres::Pool) {
+//! let migrator = Migrator::from_dir(pool, "migrations").unwrap();
+//! let applied = migrator.migrate().await.unwrap();
+//! # }
+//! ```
+//!
+//! Embed migrations into binary:
+//!
+//! ```rust,no_run
+//! use caslex_extra::storages::postgres_migrations::{Migration, Migrator};
+//!
+//! # fn run(pool: deadpool_postgres::Pool) {
+//! let migrator = Migrator::new(
+//!     pool,
+//!     vec![
+//!         Migration::new(
+//!             "0001_create_users",
+//!             "CREATE TABLE users (id BIGINT PRIMARY KEY);",
+//!         )
+//!         .unwrap(),
+//!     ],
+//! )
+//! .unwrap();
+//! # }
+//! ```
+
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::anyhow;
+
+/// Advisory lock id held while migrations are applied.
+const MIGRATIONS_LOCK_ID: i64 = 0x6361_736c_6578;
+
+const CREATE_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS _migrations (
+    version BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+)";
+
+/// Define SQL migration.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    version: i64,
+    name: String,
+    sql: String,
+}
+
+impl Migration {
+    /// Creates migration, `name` must be formatted as `<version>_<name>`, `.sql` extension is
+    /// ignored.
+    pub fn new(name: &str, sql: impl Into<String>) -> anyhow::Result<Migration> {
+        let name = name.strip_suffix(".sql").unwrap_or(name);
+        let version = name
+            .split_once('_')
+            .map_or(name, |(version, _)| version)
+            .parse::<i64>()
+            .map_err(|_| anyhow!("invalid migration name {name}, expected <version>_<name>"))?;
+
+        Ok(Migration {
+            version,
+            name: name.to_owned(),
+            sql: sql.into(),
+        })
+    }
+
+    /// Returns migration version.
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// Returns migration name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Define migration runner.
+pub struct Migrator {
+    pool: deadpool_postgres::Pool,
+    migrations: Vec<Migration>,
+}
+
+impl Migrator {
+    /// Creates runner applying passed migrations.
+    pub fn new(
+        pool: deadpool_postgres::Pool,
+        mut migrations: Vec<Migration>,
+    ) -> anyhow::Result<Migrator> {
+        migrations.sort_by_key(|m| m.version);
+
+        let mut versions = HashSet::new();
+        for migration in &migrations {
+            if !versions.insert(migration.version) {
+                return Err(anyhow!("duplicate migration version {}", migration.version));
+            }
+        }
+
+        Ok(Migrator { pool, migrations })
+    }
+
+    /// Creates runner applying `.sql` files from the directory.
+    pub fn from_dir(
+        pool: deadpool_postgres::Pool,
+        dir: impl AsRef<Path>,
+    ) -> anyhow::Result<Migrator> {
+        let dir = dir.as_ref();
+        let entries = fs::read_dir(dir)
+            .map_err(|e| anyhow!("failed to read migrations dir {}: {e}", dir.display()))?;
+
+        let mut migrations = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "sql") {
+                continue;
+            }
+
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| anyhow!("invalid migration file name {}", path.display()))?;
+            let sql = fs::read_to_string(&path)
+                .map_err(|e| anyhow!("failed to read migration {}: {e}", path.display()))?;
+
+            migrations.push(Migration::new(name, sql)?);
+        }
+
+        Migrator::new(pool, migrations)
+    }
+
+    /// Applies pending migrations, returns versions of the applied ones.
+    pub async fn migrate(&self) -> anyhow::Result<Vec<i64>> {
+        let mut client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("failed to get postgres connection: {e}"))?;
+
+        client
+            .execute("SELECT pg_advisory_lock($1)", &[&MIGRATIONS_LOCK_ID])
+            .await
+            .map_err(|e| anyhow!("failed to acquire migrations lock: {e}"))?;
+
+        let result = self.apply_pending(&mut client).await;
+
+        if let Err(e) = client
+            .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATIONS_LOCK_ID])
+            .await
+        {
+            // the lock is released with the session, don't return the connection to the pool
+            let _ = deadpool_postgres::Object::take(client);
+            tracing::warn!("failed to release migrations lock: {e}");
+        }
+
+        result
+    }
+
+    async fn apply_pending(
+        &self,
+        client: &mut deadpool_postgres::Client,
+    ) -> anyhow::Result<Vec<i64>> {
+        client
+            .batch_execute(CREATE_MIGRATIONS_TABLE)
+            .await
+            .map_err(|e| anyhow!("failed to create migrations table: {e}"))?;
+
+        let applied: HashSet<i64> = client
+            .query("SELECT version FROM _migrations", &[])
+            .await
+            .map_err(|e| anyhow!("failed to fetch applied migrations: {e}"))?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let mut versions = Vec::new();
+        for migration in self
+            .migrations
+            .iter()
+            .filter(|m| !applied.contains(&m.version))
+        {
+            let tx = client.transaction().await?;
+            tx.batch_execute(&migration.sql)
+                .await
+                .map_err(|e| anyhow!("failed to apply migration {}: {e}", migration.name))?;
+            tx.execute(
+                "INSERT INTO _migrations (version, name) VALUES ($1, $2)",
+                &[&migration.version, &migration.name],
+            )
+            .await?;
+            tx.commit()
+                .await
+                .map_err(|e| anyhow!("failed to commit migration {}: {e}", migration.name))?;
+
+            tracing::info!("applied migration {}", migration.name);
+            versions.push(migration.version);
+        }
+
+        Ok(versions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::storages::postgres_pool;
+
+    /// Returns pool which doesn't connect until a connection is requested.
+    fn lazy_pool() -> deadpool_postgres::Pool {
+        let mut config = deadpool_postgres::Config::new();
+        config.dbname = Some("caslex".to_owned());
+        config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
+            )
+            .unwrap()
+    }
+
+    fn migration(name: &str, sql: &str) -> Migration {
+        Migration::new(name, sql).unwrap()
+    }
+
+    #[test]
+    fn migration_version_is_parsed_from_name() {
+        let migration = migration("0002_create_orders.sql", "");
+        assert_eq!(migration.version(), 2);
+        assert_eq!(migration.name(), "0002_create_orders");
+
+        assert_eq!(Migration::new("7", "").unwrap().version(), 7);
+        assert!(Migration::new("create_orders.sql", "").is_err());
+        assert!(Migration::new("v1_create_orders", "").is_err());
+    }
+
+    #[test]
+    fn duplicate_versions_are_rejected() {
+        let migrations = vec![migration("1_users", ""), migration("01_orders", "")];
+
+        assert!(Migrator::new(lazy_pool(), migrations).is_err());
+    }
+
+    #[test]
+    fn sql_files_are_read_in_version_order() {
+        let dir = std::env::temp_dir().join(format!("caslex-migrations-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("0010_orders.sql"), "SELECT 10").unwrap();
+        fs::write(dir.join("0002_users.sql"), "SELECT 2").unwrap();
+        fs::write(dir.join("README.md"), "not a migration").unwrap();
+
+        let migrator = Migrator::from_dir(lazy_pool(), &dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let migrations: Vec<_> = migrator
+            .unwrap()
+            .migrations
+            .iter()
+            .map(|m| (m.version(), m.sql.clone()))
+            .collect();
+        assert_eq!(
+            migrations,
+            [(2, "SELECT 2".to_owned()), (10, "SELECT 10".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires postgres, set POSTGRES_* environment variables"]
+    async fn pending_migrations_are_applied_once() {
+        let config = postgres_pool::Config::parse_from(["caslex-test"]);
+        let pool = postgres_pool::build_pool_from_config(config).await.unwrap();
+        pool.get()
+            .await
+            .unwrap()
+            .batch_execute(
+                "DROP TABLE IF EXISTS _migrations, caslex_test_users, caslex_test_orders",
+            )
+            .await
+            .unwrap();
+
+        let migrator = || {
+            Migrator::new(
+                pool.clone(),
+                vec![
+                    migration("1_users", "CREATE TABLE caslex_test_users (id BIGINT)"),
+                    migration("2_orders", "CREATE TABLE caslex_test_orders (id BIGINT)"),
+                ],
+            )
+            .unwrap()
+        };
+
+        // concurrent runs are serialized by the advisory lock
+        let (first, second) = (migrator(), migrator());
+        let (first, second) = tokio::join!(first.migrate(), second.migrate());
+        let mut applied = [first.unwrap(), second.unwrap()].concat();
+        applied.sort();
+        assert_eq!(applied, [1, 2]);
+
+        assert!(migrator().migrate().await.unwrap().is_empty());
+
+        let recorded = pool
+            .get()
+            .await
+            .unwrap()
+            .query_one("SELECT count(*) FROM _migrations", &[])
+            .await
+            .unwrap()
+            .get::<_, i64>(0);
+        assert_eq!(recorded, 2);
+    }
+}
@@ -0,0 +1,163 @@
+//! Contains redis pool builder.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use caslex_extra::storages::redis_pool::{Config, build_pool_from_config};
+//!
+//! # async fn run() {
+//! // Parse config environment variables
+//! let config = Config::parse();
+//!
+//! // Initialize pool from above config
+//! let pool = build_pool_from_config(config).await.unwrap();
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use clap::Parser;
+use deadpool_redis::{self, redis};
+use humantime;
+
+const DEFAULT_MAX_CONNECTIONS: usize = 15;
+const DEFAULT_CREATE_TIMEOUT: &str = "5s";
+const DEFAULT_WAIT_TIMEOUT: &str = "30s";
+const DEFAULT_RECYCLE_TIMEOUT: &str = "5s";
+
+#[derive(Parser, Debug, Clone)]
+/// Define pool config.
+pub struct Config {
+    /// Adds a host to the configuration. Env variable name: `REDIS_HOST`.
+    #[arg(long, env = "REDIS_HOST", default_value = "127.0.0.1")]
+    pub host: String,
+    /// Adds a port to the configuration. Env variable name: `REDIS_PORT`.
+    #[arg(long, env = "REDIS_PORT", default_value = "6379")]
+    pub port: u16,
+    /// Sets the database number to select. Env variable name: `REDIS_DB`.
+    #[arg(long, env = "REDIS_DB", default_value = "0")]
+    pub db: i64,
+    /// Sets the ACL user to authenticate with. Env variable name: `REDIS_USER`.
+    #[arg(long, env = "REDIS_USER")]
+    pub user: Option<String>,
+    /// Sets the password to authenticate with. Env variable name: `REDIS_PASSWORD`.
+    #[arg(long, env = "REDIS_PASSWORD")]
+    pub password: Option<String>,
+    /// Maximum size of the pool. Env variable name: `REDIS_MAX_CONNECTIONS`.
+    #[arg(long, env = "REDIS_MAX_CONNECTIONS", default_value_t = DEFAULT_MAX_CONNECTIONS)]
+    pub max_connections: usize,
+    /// Timeout when creating a new connection. Env variable name: `REDIS_CREATE_TIMEOUT`.
+    #[arg(long, env = "REDIS_CREATE_TIMEOUT", default_value = DEFAULT_CREATE_TIMEOUT)]
+    pub create_timeout: humantime::Duration,
+    /// Timeout when waiting for a slot to become available. Env variable name:
+    /// `REDIS_WAIT_TIMEOUT`.
+    #[arg(long, env = "REDIS_WAIT_TIMEOUT", default_value = DEFAULT_WAIT_TIMEOUT)]
+    pub wait_timeout: humantime::Duration,
+    /// Timeout when checking a connection before reuse. Env variable name:
+    /// `REDIS_RECYCLE_TIMEOUT`.
+    #[arg(long, env = "REDIS_RECYCLE_TIMEOUT", default_value = DEFAULT_RECYCLE_TIMEOUT)]
+    pub recycle_timeout: humantime::Duration,
+}
+
+impl Config {
+    pub fn parse() -> Config {
+        Config::try_parse().expect("Parsing configuration failed.")
+    }
+
+    /// Parses config from TOML file, env variables take precedence over the file values. See
+    /// [`crate::config`] for the file format.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Config> {
+        crate::config::from_file(path)
+    }
+
+    /// Parses config from TOML reader, env variables take precedence over the reader values.
+    pub fn from_reader(reader: impl std::io::Read) -> anyhow::Result<Config> {
+        crate::config::from_reader(reader)
+    }
+}
+
+/// Build pool from config.
+pub async fn build_pool_from_config(config: Config) -> anyhow::Result<deadpool_redis::Pool> {
+    let mut pool_config = deadpool_redis::PoolConfig::new(config.max_connections);
+    pool_config.timeouts = deadpool_redis::Timeouts {
+        wait: Some(<humantime::Duration as Into<Duration>>::into(
+            config.wait_timeout,
+        )),
+        create: Some(<humantime::Duration as Into<Duration>>::into(
+            config.create_timeout,
+        )),
+        recycle: Some(<humantime::Duration as Into<Duration>>::into(
+            config.recycle_timeout,
+        )),
+    };
+
+    let conn_opts = deadpool_redis::Config {
+        url: None,
+        connection: Some(deadpool_redis::ConnectionInfo {
+            addr: deadpool_redis::ConnectionAddr::Tcp(config.host.clone(), config.port),
+            redis: deadpool_redis::RedisConnectionInfo {
+                db: config.db,
+                username: config.user.clone(),
+                password: config.password.clone(),
+            },
+        }),
+        pool: Some(pool_config),
+    };
+
+    let pool = conn_opts
+        .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+        .map_err(|err| anyhow!("Failed create redis pool {}", err))?;
+
+    // ping redis
+    let mut conn = pool.get().await.map_err(|err| {
+        anyhow!(
+            "Failed get redis connection {} addr: {}:{} db:{}",
+            err,
+            config.host,
+            config.port,
+            config.db
+        )
+    })?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut conn)
+        .await
+        .map_err(|err| anyhow!("Failed ping redis {}", err))?;
+
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unreachable_server_is_reported() {
+        let mut config = Config::parse_from(["caslex-test"]);
+        config.host = "127.0.0.1".to_owned();
+        config.port = 1;
+        config.create_timeout = Duration::from_secs(1).into();
+
+        let Err(err) = build_pool_from_config(config).await else {
+            panic!("pool is built without redis");
+        };
+
+        assert!(err.to_string().contains("addr: 127.0.0.1:1"), "{err}");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis, set REDIS_* environment variables"]
+    async fn connection_answers_ping() {
+        let pool = build_pool_from_config(Config::parse_from(["caslex-test"]))
+            .await
+            .unwrap();
+        let mut conn = pool.get().await.unwrap();
+
+        let pong = redis::cmd("PING")
+            .query_async::<_, String>(&mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(pong, "PONG");
+    }
+}
@@ -0,0 +1,296 @@
+//! Contains message broker publisher process.
+//!
+//! [`Publisher`] runs as a server [`Process`], it owns a [`MessageSink`] and drains a bounded
+//! channel of messages enqueued by handlers via [`PublisherHandle`]. On shutdown the messages
+//! enqueued before the cancellation are sent and the sink is flushed. Failed messages are logged
+//! and dropped, the publisher keeps running.
+//!
+//! NATS sink is available with `nats` feature, other brokers implement [`MessageSink`].
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::LazyLock;
+//!
+//! use async_trait::async_trait;
+//! use axum::{Extension, routing::post};
+//! use caslex::{
+//!     broker::{Message, MessageSink, Publisher, PublisherHandle},
+//!     errors::DefaultError,
+//!     server::{Config, Process, Server},
+//! };
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! struct LogSink;
+//!
+//! #[async_trait]
+//! impl MessageSink for LogSink {
+//!     async fn send(&self, message: Message) -> anyhow::Result<()> {
+//!         tracing::info!("{}: {:?}", message.subject, message.payload);
+//!         Ok(())
+//!     }
+//!
+//!     async fn flush(&self) -> anyhow::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! static PUBLISHER: LazyLock<Publisher<LogSink>> =
+//!     LazyLock::new(|| Publisher::new(LogSink, 1024));
+//!
+//! async fn order_handler(
+//!     Extension(publisher): Extension<PublisherHandle>,
+//! ) -> Result<(), DefaultError> {
+//!     publisher.publish("orders.created", "{\"id\":1}").await?;
+//!     Ok(())
+//! }
+//!
+//! # async fn run() {
+//! let router: OpenApiRouter = OpenApiRouter::new()
+//!     .route("/orders", post(order_handler))
+//!     .layer(Extension(PUBLISHER.handle()));
+//!
+//! let processes: Vec<&'static dyn Process> = vec![&*PUBLISHER];
+//! let result = Server::new(Config::parse())
+//!     .router(router)
+//!     .processes(&processes)
+//!     .run()
+//!     .await;
+//! # }
+//! ```
+
+#[cfg(feature = "nats")]
+pub mod nats;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::sync::{Mutex, mpsc};
+use tokio_util::sync::CancellationToken;
+
+use crate::server::Process;
+
+/// Define message published to a broker.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Subject or topic of the message.
+    pub subject: String,
+    /// Message payload.
+    pub payload: Bytes,
+}
+
+/// Define message broker producer.
+#[async_trait]
+pub trait MessageSink: Send + Sync {
+    /// Connects to the broker, called before the server starts.
+    async fn connect(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Sends the message, the message may be buffered by the producer until flush.
+    async fn send(&self, message: Message) -> anyhow::Result<()>;
+
+    /// Flushes buffered messages.
+    async fn flush(&self) -> anyhow::Result<()>;
+}
+
+/// Define background publisher draining enqueued messages into the sink.
+pub struct Publisher<S> {
+    sink: S,
+    sender: mpsc::Sender<Message>,
+    receiver: Mutex<mpsc::Receiver<Message>>,
+}
+
+impl<S: MessageSink> Publisher<S> {
+    /// Creates publisher buffering up to `capacity` messages.
+    pub fn new(sink: S, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+
+        Publisher {
+            sink,
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// Returns handle enqueuing messages.
+    pub fn handle(&self) -> PublisherHandle {
+        PublisherHandle {
+            sender: self.sender.clone(),
+        }
+    }
+
+    async fn send(&self, message: Message) {
+        let subject = message.subject.clone();
+        if let Err(e) = self.sink.send(message).await {
+            tracing::error!("failed to publish message to {subject}: {e}");
+        }
+    }
+}
+
+#[async_trait]
+impl<S: MessageSink> Process for Publisher<S> {
+    async fn pre_run(&self) -> anyhow::Result<()> {
+        self.sink.connect().await
+    }
+
+    async fn run(&self, token: CancellationToken) -> anyhow::Result<()> {
+        let mut receiver = self.receiver.lock().await;
+
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                message = receiver.recv() => match message {
+                    Some(message) => self.send(message).await,
+                    None => break,
+                },
+            }
+        }
+
+        // send messages enqueued before the shutdown
+        while let Ok(message) = receiver.try_recv() {
+            self.send(message).await;
+        }
+
+        self.sink.flush().await
+    }
+}
+
+/// Define handle enqueuing messages to [`Publisher`].
+#[derive(Debug, Clone)]
+pub struct PublisherHandle {
+    sender: mpsc::Sender<Message>,
+}
+
+impl PublisherHandle {
+    /// Enqueues the message, waits when the channel is full.
+    pub async fn publish(
+        &self,
+        subject: impl Into<String>,
+        payload: impl Into<Bytes>,
+    ) -> anyhow::Result<()> {
+        self.sender
+            .send(Message {
+                subject: subject.into(),
+                payload: payload.into(),
+            })
+            .await
+            .map_err(|_| anyhow!("publisher is closed"))
+    }
+
+    /// Enqueues the message, fails when the channel is full.
+    pub fn try_publish(
+        &self,
+        subject: impl Into<String>,
+        payload: impl Into<Bytes>,
+    ) -> anyhow::Result<()> {
+        self.sender
+            .try_send(Message {
+                subject: subject.into(),
+                payload: payload.into(),
+            })
+            .map_err(|e| anyhow!("failed to enqueue message: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// Keeps sent messages in memory, fails messages to `invalid` subject.
+    #[derive(Clone, Default)]
+    struct MemorySink {
+        sent: Arc<std::sync::Mutex<Vec<String>>>,
+        flushed: Arc<AtomicBool>,
+    }
+
+    impl MemorySink {
+        fn sent(&self) -> Vec<String> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl MessageSink for MemorySink {
+        async fn send(&self, message: Message) -> anyhow::Result<()> {
+            if message.subject == "invalid" {
+                return Err(anyhow!("invalid subject"));
+            }
+            self.sent.lock().unwrap().push(format!(
+                "{}:{}",
+                message.subject,
+                String::from_utf8_lossy(&message.payload)
+            ));
+            Ok(())
+        }
+
+        async fn flush(&self) -> anyhow::Result<()> {
+            self.flushed.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn enqueued_messages_are_sent_and_flushed() {
+        let sink = MemorySink::default();
+        let publisher = Arc::new(Publisher::new(sink.clone(), 8));
+        let handle = publisher.handle();
+        let token = CancellationToken::new();
+        let task = tokio::spawn({
+            let publisher = Arc::clone(&publisher);
+            let token = token.clone();
+            async move { publisher.run(token).await }
+        });
+
+        handle.publish("orders.created", "1").await.unwrap();
+        handle.publish("invalid", "2").await.unwrap();
+        handle.publish("orders.created", "3").await.unwrap();
+        for _ in 0..100 {
+            if sink.sent().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(sink.sent(), ["orders.created:1", "orders.created:3"]);
+        assert!(!sink.flushed.load(Ordering::SeqCst));
+
+        token.cancel();
+        task.await.unwrap().unwrap();
+
+        assert!(sink.flushed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn messages_enqueued_before_shutdown_are_sent() {
+        let sink = MemorySink::default();
+        let publisher = Publisher::new(sink.clone(), 8);
+        let handle = publisher.handle();
+        handle.publish("orders.created", "1").await.unwrap();
+        handle.publish("orders.created", "2").await.unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        publisher.run(token).await.unwrap();
+
+        assert_eq!(sink.sent(), ["orders.created:1", "orders.created:2"]);
+        assert!(sink.flushed.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn try_publish_fails_when_channel_is_full() {
+        let publisher = Publisher::new(MemorySink::default(), 1);
+        let handle = publisher.handle();
+
+        handle.try_publish("orders.created", "1").unwrap();
+
+        assert!(handle.try_publish("orders.created", "2").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Contains NATS message sink.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::LazyLock;
+//!
+//! use caslex::{
+//!     broker::{Publisher, nats::NatsSink},
+//!     server::Process,
+//! };
+//!
+//! static PUBLISHER: LazyLock<Publisher<NatsSink>> =
+//!     LazyLock::new(|| Publisher::new(NatsSink::new("nats://127.0.0.1:4222"), 1024));
+//!
+//! let processes: Vec<&'static dyn Process> = vec![&*PUBLISHER];
+//! ```
+
+use anyhow::anyhow;
+use async_nats::Client;
+use async_trait::async_trait;
+use tokio::sync::OnceCell;
+
+use crate::broker::{Message, MessageSink};
+
+/// Define NATS sink publishing messages to subjects.
+pub struct NatsSink {
+    url: String,
+    client: OnceCell<Client>,
+}
+
+impl NatsSink {
+    /// Creates sink connecting to the server `url` in the publisher pre run.
+    pub fn new(url: impl Into<String>) -> Self {
+        NatsSink {
+            url: url.into(),
+            client: OnceCell::new(),
+        }
+    }
+
+    fn client(&self) -> anyhow::Result<&Client> {
+        self.client
+            .get()
+            .ok_or_else(|| anyhow!("nats client is not connected"))
+    }
+}
+
+#[async_trait]
+impl MessageSink for NatsSink {
+    async fn connect(&self) -> anyhow::Result<()> {
+        self.client
+            .get_or_try_init(|| async_nats::connect(self.url.as_str()))
+            .await
+            .map_err(|e| anyhow!("failed to connect to nats server {}: {e}", self.url))?;
+
+        Ok(())
+    }
+
+    async fn send(&self, message: Message) -> anyhow::Result<()> {
+        self.client()?
+            .publish(message.subject, message.payload)
+            .await
+            .map_err(|e| anyhow!("failed to publish message: {e}"))
+    }
+
+    async fn flush(&self) -> anyhow::Result<()> {
+        self.client()?
+            .flush()
+            .await
+            .map_err(|e| anyhow!("failed to flush nats client: {e}"))
+    }
+}
@@ -58,10 +58,11 @@
 //!
 //! # Struct validation error
 //!
-//! Handling validation error example
+//! [`ValidatedJson`] validates the payload after deserializing, failed validation is rejected with
+//! `422` and `validation_error` kind before entering the handler
 //!
 //! ```rust,no_run
-//! use caslex::errors::{AppJson, DefaultError};
+//! use caslex::errors::ValidatedJson;
 //! use serde::Deserialize;
 //! use validator::Validate;
 //!
@@ -71,16 +72,26 @@
 //!     message: String,
 //! }
 //!
-//! async fn validation_error_handler(
-//!     AppJson(payload): AppJson<BodyError>,
-//! ) -> Result<&'static str, DefaultError> {
-//!     match payload.validate() {
-//!         Ok(_) => Ok("nothing"),
-//!         Err(err) => Err(DefaultError::ValidationError(err)),
-//!     }
+//! async fn validation_error_handler(ValidatedJson(payload): ValidatedJson<BodyError>) {
+//!     // payload is valid here
 //! }
 //! ```
 //!
+//! [`ValidatedQuery`] does the same for query params, params which can't be deserialized are
+//! rejected with `400` and `query_rejection` kind. [`AppPath`] rejects path params which can't be
+//! deserialized with `400` and `path_rejection` kind.
+//!
+//! Validation failures are listed in the `fields` array of the error body:
+//!
+//! ```json
+//! {
+//!   "error": {
+//!     "kind": "validation_error",
+//!     "details": "...",
+//!     "fields": [{ "field": "message", "code": "length", "message": "..." }]
+//!   }
+//! }
+//! ```
 //!
 //! # Other errors
 //!
@@ -94,23 +105,93 @@
 //!     Err(DefaultError::Other(anyhow!("other error")))
 //! }
 //! ```
+//!
+//! # Database errors
+//!
+//! With `postgres` feature `tokio_postgres::Error` and `deadpool_postgres::PoolError` convert into
+//! [`DefaultError`], so handlers can use `?` directly. Unique violations map to `409`, foreign
+//! key, not null and check violations map to `400`, everything else maps to `500` with
+//! `database_error` kind. Raw database errors are never returned to clients, the response is
+//! logged once as any other error, see [Logging](#logging).
+//!
+//! # Error format
+//!
+//! Errors are rendered as `{ "error": { "kind", "details", "trace_id" } }` by default, where
+//! `trace_id` is the active OpenTelemetry trace id or the `x-request-id` request header, omitted
+//! when neither exists. The trace id takes precedence, so the request id, sent by the client or
+//! generated by the server, is rendered for errors built outside a sampled request span, e.g.
+//! panics, timeouts and requests to paths excluded from tracing. RFC 7807
+//! `application/problem+json` format is enabled via `SERVER_ERROR_FORMAT=problem` server config
+//! or [`set_error_format`].
+//!
+//! Requests with `Accept` header preferring `application/xml` or `text/xml` over JSON receive
+//! errors of the same structure as XML, `<error>` element in the default format and RFC 7807
+//! `<problem>` element in the problem format. Lists are wrapped, e.g. `<fields><field>...`.
+//!
+//! # Logging
+//!
+//! [`DefaultError`] responses and panics are logged with `status`, `kind`, `details` and
+//! `trace_id` fields, server errors at `error` level and client errors at `debug` level, so
+//! handlers don't need to log returned errors.
 
-use std::{error::Error as StdError, fmt::Debug};
+use std::{error::Error as StdError, fmt::Debug, sync::RwLock};
 
 use axum::{
     Json,
-    extract::{FromRequest, rejection::JsonRejection},
+    extract::{
+        FromRequest, FromRequestParts, Query, Request,
+        rejection::{FormRejection, JsonRejection, PathRejection, QueryRejection},
+    },
+    middleware::Next,
 };
 use axum_core::response::{IntoResponse, Response};
-use http::StatusCode;
-use serde::Serialize;
+use clap::ValueEnum;
+use http::{StatusCode, header, request::Parts};
+use opentelemetry::trace::TraceContextExt;
+use serde::{Serialize, de::DeserializeOwned};
 use thiserror::Error;
-use validator::ValidationErrors;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use validator::{Validate, ValidationErrors, ValidationErrorsKind};
+
+use crate::middlewares::request_id::REQUEST_ID_HEADER;
+
+static ERROR_FORMAT: RwLock<ErrorFormat> = RwLock::new(ErrorFormat::Default);
+
+tokio::task_local! {
+    static REQUEST_SCOPE: RequestScope;
+}
+
+/// Define request data used by error responses built while handling the request.
+struct RequestScope {
+    request_id: Option<String>,
+    prefers_xml: bool,
+}
+
+/// Define error response format.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// `{ "error": { "kind", "details" } }` JSON body.
+    #[default]
+    Default,
+    /// RFC 7807 `application/problem+json` body.
+    Problem,
+}
+
+/// Set error response format for all errors.
+pub fn set_error_format(format: ErrorFormat) {
+    *ERROR_FORMAT.write().unwrap() = format;
+}
 
 pub trait AppError: StdError {
     fn status(&self) -> StatusCode;
     fn details(&self) -> String;
     fn kind(&self) -> String;
+
+    /// Stable machine-readable error code clients can branch on, omitted from the response when
+    /// `None`.
+    fn code(&self) -> Option<String> {
+        None
+    }
 }
 
 /// Define default custom API error.
@@ -119,12 +200,29 @@ pub enum DefaultError {
     #[error(transparent)]
     JsonRejection(#[from] JsonRejection),
 
+    #[error(transparent)]
+    QueryRejection(#[from] QueryRejection),
+
+    #[error(transparent)]
+    FormRejection(#[from] FormRejection),
+
+    #[error(transparent)]
+    PathRejection(#[from] PathRejection),
+
+    #[cfg(feature = "multipart")]
+    #[error(transparent)]
+    MultipartRejection(#[from] crate::multipart::MultipartError),
+
     #[error(transparent)]
     ValidationError(#[from] ValidationErrors),
 
     #[error("application error")]
     AppError(#[source] &'static dyn AppError),
 
+    /// Several errors reported at once, e.g. failed items of a batch request.
+    #[error("{} errors", .0.len())]
+    Multiple(Vec<ErrorInfo>),
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -137,12 +235,106 @@ pub struct ErrorResponse {
 }
 
 /// Define error info.
-#[derive(Serialize)]
+///
+/// Construct it with [`ErrorInfo::new`] and the `with_*` methods, fields may be added in future
+/// releases.
+#[derive(Serialize, Debug, Clone)]
+#[non_exhaustive]
 pub struct ErrorInfo {
     /// Error kind.
     pub kind: String,
     /// Error full description.
     pub details: String,
+    /// Machine-readable error code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Trace id or, without an active trace, request id for correlation with logs and traces.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Field-level validation errors.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    /// Aggregated errors of [`DefaultError::Multiple`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ErrorInfo>,
+}
+
+impl ErrorInfo {
+    /// Creates error info with kind and details only.
+    pub fn new(kind: impl Into<String>, details: impl Into<String>) -> Self {
+        ErrorInfo {
+            kind: kind.into(),
+            details: details.into(),
+            code: None,
+            trace_id: None,
+            fields: vec![],
+            errors: vec![],
+        }
+    }
+
+    /// Sets machine-readable error code.
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Sets field-level validation errors.
+    pub fn with_fields(mut self, fields: Vec<FieldError>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Sets aggregated errors.
+    pub fn with_errors(mut self, errors: Vec<ErrorInfo>) -> Self {
+        self.errors = errors;
+        self
+    }
+}
+
+impl<E: AppError + ?Sized> From<&E> for ErrorInfo {
+    fn from(error: &E) -> Self {
+        ErrorInfo {
+            code: error.code(),
+            ..ErrorInfo::new(error.kind(), error.details())
+        }
+    }
+}
+
+/// Define field-level validation error.
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    /// Field path, nested fields are joined with `.` and list items with `[index]`.
+    pub field: String,
+    /// Validation error code.
+    pub code: String,
+    /// Validation error message.
+    pub message: String,
+}
+
+/// Define RFC 7807 problem details response.
+#[derive(Serialize)]
+pub struct ProblemDetails {
+    /// Problem type, error kind.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Problem summary, error kind.
+    pub title: String,
+    /// HTTP status code.
+    pub status: u16,
+    /// Error full description.
+    pub detail: String,
+    /// Machine-readable error code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Trace id or, without an active trace, request id for correlation with logs and traces.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Field-level validation errors.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    /// Aggregated errors of [`DefaultError::Multiple`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ErrorInfo>,
 }
 
 #[derive(FromRequest)]
@@ -158,15 +350,131 @@ where
     }
 }
 
+/// Define `application/x-www-form-urlencoded` body extractor, rejects with
+/// [`DefaultError::FormRejection`].
+#[derive(FromRequest)]
+#[from_request(via(axum::Form), rejection(DefaultError))]
+pub struct AppForm<T>(pub T);
+
+/// Define path params extractor, rejects with [`DefaultError::PathRejection`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(DefaultError))]
+pub struct AppPath<T>(pub T);
+
+/// Define JSON extractor validating the payload.
+///
+/// Rejects the same way as [`AppJson`] and with [`DefaultError::ValidationError`] when the
+/// validation fails.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = DefaultError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let AppJson(payload) = AppJson::<T>::from_request(req, state).await?;
+        payload.validate()?;
+
+        Ok(ValidatedJson(payload))
+    }
+}
+
+/// Define query string extractor validating the params.
+///
+/// Rejects with [`DefaultError::QueryRejection`] when the query can't be deserialized and with
+/// [`DefaultError::ValidationError`] when the validation fails.
+#[derive(Debug)]
+pub struct ValidatedQuery<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = DefaultError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<T>::from_request_parts(parts, state).await?;
+        params.validate()?;
+
+        Ok(ValidatedQuery(params))
+    }
+}
+
 impl IntoResponse for DefaultError {
     fn into_response(self) -> Response {
+        let fields = match &self {
+            DefaultError::ValidationError(errors) => field_errors(errors),
+            _ => vec![],
+        };
+        let code = match &self {
+            DefaultError::AppError(application_error) => application_error.code(),
+            _ => None,
+        };
+        let errors = match &self {
+            DefaultError::Multiple(errors) => errors.clone(),
+            _ => vec![],
+        };
+
         let (status, details, kind) = match self {
+            DefaultError::JsonRejection(rejection)
+                if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE =>
+            {
+                (
+                    rejection.status(),
+                    rejection.body_text(),
+                    "payload_too_large".to_owned(),
+                )
+            }
+
             DefaultError::JsonRejection(rejection) => (
                 rejection.status(),
                 rejection.body_text(),
                 "json_rejection".to_owned(),
             ),
 
+            DefaultError::QueryRejection(rejection) => (
+                rejection.status(),
+                rejection.body_text(),
+                "query_rejection".to_owned(),
+            ),
+
+            DefaultError::FormRejection(rejection)
+                if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE =>
+            {
+                (
+                    rejection.status(),
+                    rejection.body_text(),
+                    "payload_too_large".to_owned(),
+                )
+            }
+
+            DefaultError::FormRejection(rejection) => (
+                rejection.status(),
+                rejection.body_text(),
+                "form_rejection".to_owned(),
+            ),
+
+            DefaultError::PathRejection(rejection) => (
+                rejection.status(),
+                rejection.body_text(),
+                "path_rejection".to_owned(),
+            ),
+
+            #[cfg(feature = "multipart")]
+            DefaultError::MultipartRejection(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                (e.status(), e.to_string(), "payload_too_large".to_owned())
+            }
+
+            #[cfg(feature = "multipart")]
+            DefaultError::MultipartRejection(e) => {
+                (e.status(), e.to_string(), "multipart_rejection".to_owned())
+            }
+
             DefaultError::ValidationError(_) => (
                 StatusCode::UNPROCESSABLE_ENTITY,
                 format!("[{self}]").replace('\n', ", "),
@@ -179,6 +487,12 @@ impl IntoResponse for DefaultError {
                 application_error.kind(),
             ),
 
+            DefaultError::Multiple(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                self.to_string(),
+                "multiple_errors".to_owned(),
+            ),
+
             DefaultError::Other(e) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 e.to_string(),
@@ -186,10 +500,856 @@ impl IntoResponse for DefaultError {
             ),
         };
 
-        let body = Json(ErrorResponse {
-            error: ErrorInfo { kind, details },
+        log_error(status, &kind, &details);
+
+        let info = ErrorInfo {
+            kind,
+            details,
+            code,
+            trace_id: None,
+            fields,
+            errors,
+        };
+        let (content_type, body) = error_info_body(status, info);
+
+        (status, [(header::CONTENT_TYPE, content_type)], body).into_response()
+    }
+}
+
+fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    fn collect(prefix: &str, errors: &ValidationErrors, fields: &mut Vec<FieldError>) {
+        for (field, kind) in errors.errors() {
+            let field = if prefix.is_empty() {
+                field.to_string()
+            } else {
+                format!("{prefix}.{field}")
+            };
+
+            match kind {
+                ValidationErrorsKind::Field(errors) => {
+                    fields.extend(errors.iter().map(|e| FieldError {
+                        field: field.clone(),
+                        code: e.code.to_string(),
+                        message: e.message.as_ref().map_or_else(
+                            || format!("validation error: {}", e.code),
+                            |m| m.to_string(),
+                        ),
+                    }))
+                }
+                ValidationErrorsKind::Struct(errors) => collect(&field, errors, fields),
+                ValidationErrorsKind::List(items) => {
+                    for (idx, errors) in items {
+                        collect(&format!("{field}[{idx}]"), errors, fields);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut fields = vec![];
+    collect("", errors, &mut fields);
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+    fields
+}
+
+/// Keep request id and accepted format available for error responses built while handling the
+/// request.
+pub(crate) async fn request_scope(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(ToOwned::to_owned);
+    let prefers_xml = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(prefers_xml);
+
+    let scope = RequestScope {
+        request_id,
+        prefers_xml,
+    };
+    REQUEST_SCOPE.scope(scope, next.run(req)).await
+}
+
+/// Returns true if XML media type has higher quality than JSON in `Accept` header, wildcards
+/// count as JSON.
+fn prefers_xml(accept: &str) -> bool {
+    let (mut xml, mut json) = (0.0_f32, 0.0_f32);
+
+    for range in accept.split(',') {
+        let mut params = range.split(';');
+        let media_type = params
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        match media_type.as_str() {
+            "application/xml" | "text/xml" | "application/problem+xml" => xml = xml.max(quality),
+            "application/json" | "application/problem+json" | "application/*" | "*/*" => {
+                json = json.max(quality)
+            }
+            _ => {}
+        }
+    }
+
+    xml > json
+}
+
+fn current_trace_id() -> Option<String> {
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+
+    if span_context.is_valid() {
+        return Some(span_context.trace_id().to_string());
+    }
+
+    REQUEST_SCOPE
+        .try_with(|scope| scope.request_id.clone())
+        .ok()
+        .flatten()
+}
+
+/// Logs error response, server errors at `error` level and client errors at `debug` level. Errors
+/// converted into responses are logged here only, handlers don't need to log them.
+pub(crate) fn log_error(status: StatusCode, kind: &str, details: &str) {
+    let trace_id = current_trace_id();
+    let trace_id = trace_id.as_deref();
+
+    if status.is_server_error() {
+        tracing::error!(
+            status = status.as_u16(),
+            kind,
+            details,
+            trace_id,
+            "request failed"
+        );
+    } else {
+        tracing::debug!(
+            status = status.as_u16(),
+            kind,
+            details,
+            trace_id,
+            "request failed"
+        );
+    }
+}
+
+/// Build error body in the configured format, returns content type and body.
+pub(crate) fn error_body(status: StatusCode, kind: &str, details: &str) -> (&'static str, String) {
+    error_info_body(status, ErrorInfo::new(kind, details))
+}
+
+fn error_info_body(status: StatusCode, info: ErrorInfo) -> (&'static str, String) {
+    let format = *ERROR_FORMAT.read().unwrap();
+    format_error_info(format, status, info)
+}
+
+fn format_error_info(
+    format: ErrorFormat,
+    status: StatusCode,
+    mut info: ErrorInfo,
+) -> (&'static str, String) {
+    info.trace_id = current_trace_id();
+
+    let prefers_xml = REQUEST_SCOPE
+        .try_with(|scope| scope.prefers_xml)
+        .unwrap_or_default();
+    if prefers_xml {
+        return xml_error_body(format, status, &info);
+    }
+
+    match format {
+        ErrorFormat::Default => {
+            let body = ErrorResponse { error: info };
+            ("application/json", serde_json::to_string(&body).unwrap())
+        }
+        ErrorFormat::Problem => {
+            let body = ProblemDetails {
+                title: info.kind.clone(),
+                kind: info.kind,
+                status: status.as_u16(),
+                detail: info.details,
+                code: info.code,
+                trace_id: info.trace_id,
+                fields: info.fields,
+                errors: info.errors,
+            };
+            (
+                "application/problem+json",
+                serde_json::to_string(&body).unwrap(),
+            )
+        }
+    }
+}
+
+fn xml_error_body(
+    format: ErrorFormat,
+    status: StatusCode,
+    info: &ErrorInfo,
+) -> (&'static str, String) {
+    let mut body = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+
+    match format {
+        ErrorFormat::Default => {
+            write_xml_error(&mut body, info);
+            ("application/xml", body)
+        }
+        ErrorFormat::Problem => {
+            body.push_str(r#"<problem xmlns="urn:ietf:rfc:7807">"#);
+            write_xml_element(&mut body, "type", &info.kind);
+            write_xml_element(&mut body, "title", &info.kind);
+            write_xml_element(&mut body, "status", &status.as_u16().to_string());
+            write_xml_element(&mut body, "detail", &info.details);
+            write_xml_error_extras(&mut body, info);
+            body.push_str("</problem>");
+            ("application/problem+xml", body)
+        }
+    }
+}
+
+fn write_xml_error(body: &mut String, info: &ErrorInfo) {
+    body.push_str("<error>");
+    write_xml_element(body, "kind", &info.kind);
+    write_xml_element(body, "details", &info.details);
+    write_xml_error_extras(body, info);
+    body.push_str("</error>");
+}
+
+fn write_xml_error_extras(body: &mut String, info: &ErrorInfo) {
+    if let Some(code) = &info.code {
+        write_xml_element(body, "code", code);
+    }
+    if let Some(trace_id) = &info.trace_id {
+        write_xml_element(body, "trace_id", trace_id);
+    }
+    if !info.fields.is_empty() {
+        body.push_str("<fields>");
+        for field in &info.fields {
+            body.push_str("<field>");
+            write_xml_element(body, "field", &field.field);
+            write_xml_element(body, "code", &field.code);
+            write_xml_element(body, "message", &field.message);
+            body.push_str("</field>");
+        }
+        body.push_str("</fields>");
+    }
+    if !info.errors.is_empty() {
+        body.push_str("<errors>");
+        for error in &info.errors {
+            write_xml_error(body, error);
+        }
+        body.push_str("</errors>");
+    }
+}
+
+fn write_xml_element(body: &mut String, name: &str, value: &str) {
+    body.push('<');
+    body.push_str(name);
+    body.push('>');
+    for c in value.chars() {
+        match c {
+            '&' => body.push_str("&amp;"),
+            '<' => body.push_str("&lt;"),
+            '>' => body.push_str("&gt;"),
+            '"' => body.push_str("&quot;"),
+            '\'' => body.push_str("&apos;"),
+            // control characters aren't allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => body.push(c),
+        }
+    }
+    body.push_str("</");
+    body.push_str(name);
+    body.push('>');
+}
+
+/// Build response with the standard error body.
+pub(crate) fn error_response(status: StatusCode, kind: &str, details: &str) -> Response {
+    let (content_type, body) = error_body(status, kind, details);
+
+    (status, [(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// Define database errors.
+#[cfg(feature = "postgres")]
+#[derive(Debug, PartialEq, Eq)]
+pub enum DatabaseError {
+    UniqueViolation,
+    ForeignKeyViolation,
+    NotNullViolation,
+    CheckViolation,
+    Other,
+}
+
+#[cfg(feature = "postgres")]
+impl StdError for DatabaseError {}
+
+#[cfg(feature = "postgres")]
+impl std::fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "error: status={} kind={} details={}",
+            self.status(),
+            self.kind(),
+            self.details()
+        )
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl AppError for DatabaseError {
+    fn status(&self) -> StatusCode {
+        match self {
+            DatabaseError::UniqueViolation => StatusCode::CONFLICT,
+            DatabaseError::ForeignKeyViolation
+            | DatabaseError::NotNullViolation
+            | DatabaseError::CheckViolation => StatusCode::BAD_REQUEST,
+            DatabaseError::Other => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn details(&self) -> String {
+        match self {
+            DatabaseError::UniqueViolation => "unique constraint violation".to_owned(),
+            DatabaseError::ForeignKeyViolation => "foreign key constraint violation".to_owned(),
+            DatabaseError::NotNullViolation => "not null constraint violation".to_owned(),
+            DatabaseError::CheckViolation => "check constraint violation".to_owned(),
+            DatabaseError::Other => "database error".to_owned(),
+        }
+    }
+
+    fn kind(&self) -> String {
+        match self {
+            DatabaseError::UniqueViolation => "database_unique_violation".to_owned(),
+            DatabaseError::ForeignKeyViolation => "database_foreign_key_violation".to_owned(),
+            DatabaseError::NotNullViolation => "database_not_null_violation".to_owned(),
+            DatabaseError::CheckViolation => "database_check_violation".to_owned(),
+            DatabaseError::Other => "database_error".to_owned(),
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<tokio_postgres::Error> for DefaultError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        DefaultError::AppError(database_error(err.code()))
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn database_error(code: Option<&tokio_postgres::error::SqlState>) -> &'static DatabaseError {
+    use tokio_postgres::error::SqlState;
+
+    match code {
+        Some(code) if *code == SqlState::UNIQUE_VIOLATION => &DatabaseError::UniqueViolation,
+        Some(code) if *code == SqlState::FOREIGN_KEY_VIOLATION => {
+            &DatabaseError::ForeignKeyViolation
+        }
+        Some(code) if *code == SqlState::NOT_NULL_VIOLATION => &DatabaseError::NotNullViolation,
+        Some(code) if *code == SqlState::CHECK_VIOLATION => &DatabaseError::CheckViolation,
+        _ => &DatabaseError::Other,
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<deadpool_postgres::PoolError> for DefaultError {
+    fn from(err: deadpool_postgres::PoolError) -> Self {
+        match err {
+            deadpool_postgres::PoolError::Backend(err) => err.into(),
+            _ => DefaultError::AppError(&DatabaseError::Other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    /// Collects logs written by the fmt subscriber.
+    #[derive(Clone, Default)]
+    struct LogWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for LogWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Returns logs emitted by `f` at `debug` level and above.
+    fn capture_logs(f: impl FnOnce()) -> String {
+        let writer = LogWriter::default();
+        let make_writer = writer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || make_writer.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, f);
+
+        let logs = writer.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    #[test]
+    fn server_error_is_logged_once() {
+        let logs = capture_logs(|| {
+            let error = DefaultError::Other(anyhow::anyhow!("connection reset"));
+            let _ = error.into_response();
         });
 
-        (status, body).into_response()
+        assert_eq!(logs.lines().count(), 1, "{logs}");
+        assert!(logs.contains("ERROR"), "{logs}");
+        assert!(logs.contains("connection reset"), "{logs}");
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn database_error_is_logged_once() {
+        let logs = capture_logs(|| {
+            let error = DefaultError::from(deadpool_postgres::PoolError::Timeout(
+                deadpool_postgres::TimeoutType::Wait,
+            ));
+            let _ = error.into_response();
+        });
+
+        assert_eq!(logs.lines().count(), 1, "{logs}");
+        assert!(logs.contains("database_error"), "{logs}");
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn sql_states_are_mapped_to_database_errors() {
+        use tokio_postgres::error::SqlState;
+
+        let cases = [
+            (
+                Some(SqlState::UNIQUE_VIOLATION),
+                StatusCode::CONFLICT,
+                "database_unique_violation",
+            ),
+            (
+                Some(SqlState::FOREIGN_KEY_VIOLATION),
+                StatusCode::BAD_REQUEST,
+                "database_foreign_key_violation",
+            ),
+            (
+                Some(SqlState::from_code("23502")),
+                StatusCode::BAD_REQUEST,
+                "database_not_null_violation",
+            ),
+            (
+                Some(SqlState::SYNTAX_ERROR),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database_error",
+            ),
+            (None, StatusCode::INTERNAL_SERVER_ERROR, "database_error"),
+        ];
+
+        for (code, status, kind) in cases {
+            let error = database_error(code.as_ref());
+
+            assert_eq!(error.status(), status, "{code:?}");
+            assert_eq!(error.kind(), kind, "{code:?}");
+        }
+    }
+
+    #[derive(Error, Debug)]
+    #[error("insufficient funds")]
+    struct InsufficientFunds;
+
+    impl AppError for InsufficientFunds {
+        fn status(&self) -> StatusCode {
+            StatusCode::CONFLICT
+        }
+
+        fn details(&self) -> String {
+            self.to_string()
+        }
+
+        fn kind(&self) -> String {
+            "payment_error".to_owned()
+        }
+
+        fn code(&self) -> Option<String> {
+            Some("PAYMENT_INSUFFICIENT_FUNDS".to_owned())
+        }
+    }
+
+    fn json(body: &str) -> serde_json::Value {
+        serde_json::from_str(body).unwrap()
+    }
+
+    async fn response_json(response: Response) -> (StatusCode, serde_json::Value) {
+        let status = response.status();
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[derive(Debug, serde::Deserialize, Validate)]
+    struct SignUp {
+        #[validate(email)]
+        email: String,
+        #[validate(length(min = 8, message = "password is too short"))]
+        password: String,
+    }
+
+    #[tokio::test]
+    async fn validation_errors_are_reported_per_field() {
+        let sign_up = SignUp {
+            email: "not-an-email".to_owned(),
+            password: "short".to_owned(),
+        };
+        let error = DefaultError::from(sign_up.validate().unwrap_err());
+
+        let (status, body) = response_json(error.into_response()).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["kind"], serde_json::json!("validation_error"));
+        assert_eq!(
+            body["error"]["fields"],
+            serde_json::json!([
+                {"field": "email", "code": "email", "message": "validation error: email"},
+                {"field": "password", "code": "length", "message": "password is too short"},
+            ])
+        );
+    }
+
+    fn json_request(body: &str) -> Request {
+        Request::post("/")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(body.to_owned()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn valid_json_body_is_extracted() {
+        let request = json_request(r#"{"email": "user@example.com", "password": "long enough"}"#);
+
+        let ValidatedJson(sign_up) = ValidatedJson::<SignUp>::from_request(request, &())
+            .await
+            .unwrap();
+
+        assert_eq!(sign_up.email, "user@example.com");
+    }
+
+    #[tokio::test]
+    async fn invalid_json_body_is_rejected_with_validation_error() {
+        let request = json_request(r#"{"email": "user@example.com", "password": "short"}"#);
+
+        let error = ValidatedJson::<SignUp>::from_request(request, &())
+            .await
+            .unwrap_err();
+        let (status, body) = response_json(error.into_response()).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["kind"], serde_json::json!("validation_error"));
+        assert_eq!(
+            body["error"]["fields"][0]["field"],
+            serde_json::json!("password")
+        );
+    }
+
+    #[derive(Debug, serde::Deserialize, Validate)]
+    struct Page {
+        #[validate(range(min = 1, max = 100))]
+        limit: u32,
+    }
+
+    async fn extract_query(uri: &str) -> Result<Page, DefaultError> {
+        let (mut parts, _) = Request::get(uri).body(()).unwrap().into_parts();
+
+        ValidatedQuery::<Page>::from_request_parts(&mut parts, &())
+            .await
+            .map(|ValidatedQuery(page)| page)
+    }
+
+    #[tokio::test]
+    async fn valid_query_is_extracted() {
+        assert_eq!(extract_query("/?limit=10").await.unwrap().limit, 10);
+    }
+
+    #[tokio::test]
+    async fn invalid_query_is_rejected_with_validation_error() {
+        let error = extract_query("/?limit=1000").await.unwrap_err();
+        let (status, body) = response_json(error.into_response()).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["kind"], serde_json::json!("validation_error"));
+        assert_eq!(
+            body["error"]["fields"][0]["field"],
+            serde_json::json!("limit")
+        );
+    }
+
+    #[tokio::test]
+    async fn mistyped_query_is_rejected_with_query_rejection() {
+        let error = extract_query("/?limit=ten").await.unwrap_err();
+        let (status, body) = response_json(error.into_response()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["kind"], serde_json::json!("query_rejection"));
+    }
+
+    fn form_request(body: &str) -> Request {
+        Request::post("/")
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(axum::body::Body::from(body.to_owned()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn form_body_is_extracted() {
+        let request = form_request("limit=10");
+
+        let AppForm(page) = AppForm::<Page>::from_request(request, &()).await.unwrap();
+
+        assert_eq!(page.limit, 10);
+    }
+
+    #[tokio::test]
+    async fn malformed_form_body_is_rejected_with_form_rejection() {
+        let request = form_request("limit=ten");
+
+        let error = AppForm::<Page>::from_request(request, &())
+            .await
+            .err()
+            .unwrap();
+        let (status, body) = response_json(error.into_response()).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["kind"], serde_json::json!("form_rejection"));
+    }
+
+    #[tokio::test]
+    async fn non_numeric_path_param_is_rejected_with_path_rejection() {
+        use axum::{Router, routing::get};
+        use tower::ServiceExt;
+
+        let router = Router::new().route(
+            "/items/{id}",
+            get(|AppPath(id): AppPath<u64>| async move { id.to_string() }),
+        );
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get("/items/7")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router
+            .oneshot(
+                Request::get("/items/abc")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let (status, body) = response_json(response).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["kind"], serde_json::json!("path_rejection"));
+    }
+
+    #[tokio::test]
+    async fn aggregated_errors_are_listed_in_response() {
+        let error = DefaultError::Multiple(vec![
+            ErrorInfo::new("validation_error", "item 1 amount must be positive"),
+            ErrorInfo::from(&InsufficientFunds),
+        ]);
+
+        let (status, body) = response_json(error.into_response()).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["kind"], serde_json::json!("multiple_errors"));
+        assert_eq!(
+            body["error"]["errors"],
+            serde_json::json!([
+                {"kind": "validation_error", "details": "item 1 amount must be positive"},
+                {
+                    "kind": "payment_error",
+                    "details": "insufficient funds",
+                    "code": "PAYMENT_INSUFFICIENT_FUNDS",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn xml_is_preferred_by_accept_quality() {
+        assert!(prefers_xml("application/xml"));
+        assert!(prefers_xml("text/xml, application/json;q=0.5"));
+        assert!(prefers_xml(
+            "application/json;q=0.1, application/problem+xml"
+        ));
+        assert!(!prefers_xml("application/json"));
+        assert!(!prefers_xml("application/xml;q=0.5, */*"));
+        assert!(!prefers_xml("application/xml, application/json"));
+        assert!(!prefers_xml("text/html"));
+    }
+
+    /// Returns content type and body of the error response to a request accepting `accept`.
+    async fn negotiated_error(accept: Option<&str>) -> (String, String) {
+        use axum::{Router, middleware, routing::get};
+        use tower::ServiceExt;
+
+        let router = Router::new()
+            .route(
+                "/",
+                get(|| async { Err::<(), _>(DefaultError::AppError(&InsufficientFunds)) }),
+            )
+            .layer(middleware::from_fn(request_scope));
+        let mut request = Request::get("/");
+        if let Some(accept) = accept {
+            request = request.header(http::header::ACCEPT, accept);
+        }
+
+        let response = router
+            .oneshot(request.body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let content_type = response.headers()[http::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        (content_type, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn error_format_follows_accept_header() {
+        let (content_type, body) = negotiated_error(Some("application/xml")).await;
+        assert_eq!(content_type, "application/xml");
+        assert_eq!(
+            body,
+            r#"<?xml version="1.0" encoding="UTF-8"?><error><kind>payment_error</kind><details>insufficient funds</details><code>PAYMENT_INSUFFICIENT_FUNDS</code></error>"#
+        );
+
+        for accept in [Some("application/json"), None] {
+            let (content_type, body) = negotiated_error(accept).await;
+            assert_eq!(content_type, "application/json", "{accept:?}");
+            assert_eq!(
+                json(&body)["error"]["kind"],
+                serde_json::json!("payment_error")
+            );
+        }
+    }
+
+    #[test]
+    fn xml_values_are_escaped() {
+        let mut body = String::new();
+
+        write_xml_element(&mut body, "details", "<a href=\"x\">'&'</a>\u{0}");
+
+        assert_eq!(
+            body,
+            "<details>&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;</details>"
+        );
+    }
+
+    #[test]
+    fn app_error_code_is_in_response() {
+        let info = ErrorInfo::from(&InsufficientFunds);
+
+        let (_, body) = format_error_info(ErrorFormat::Default, StatusCode::CONFLICT, info.clone());
+        assert_eq!(
+            json(&body)["error"]["code"],
+            serde_json::json!("PAYMENT_INSUFFICIENT_FUNDS")
+        );
+
+        let (content_type, body) =
+            format_error_info(ErrorFormat::Problem, StatusCode::CONFLICT, info);
+        assert_eq!(content_type, "application/problem+json");
+        assert_eq!(
+            json(&body)["code"],
+            serde_json::json!("PAYMENT_INSUFFICIENT_FUNDS")
+        );
+    }
+
+    #[test]
+    fn error_code_is_omitted_when_absent() {
+        let info = ErrorInfo::new("not_found", "user not found");
+
+        let (_, body) = format_error_info(ErrorFormat::Default, StatusCode::NOT_FOUND, info);
+
+        assert_eq!(
+            json(&body),
+            serde_json::json!({"error": {"kind": "not_found", "details": "user not found"}})
+        );
+    }
+
+    #[test]
+    fn problem_format_follows_rfc_7807() {
+        let info = ErrorInfo::new("not_found", "user not found");
+
+        let (content_type, body) =
+            format_error_info(ErrorFormat::Problem, StatusCode::NOT_FOUND, info);
+
+        assert_eq!(content_type, "application/problem+json");
+        assert_eq!(
+            json(&body),
+            serde_json::json!({
+                "type": "not_found",
+                "title": "not_found",
+                "status": 404,
+                "detail": "user not found",
+            })
+        );
+    }
+
+    #[test]
+    fn error_info_builder_sets_optional_fields() {
+        let field = FieldError {
+            field: "amount".to_owned(),
+            code: "range".to_owned(),
+            message: "must be positive".to_owned(),
+        };
+        let info = ErrorInfo::new("batch_error", "1 item failed")
+            .with_code("BATCH_FAILED")
+            .with_fields(vec![field])
+            .with_errors(vec![ErrorInfo::new("validation_error", "invalid item")]);
+
+        let (_, body) = format_error_info(ErrorFormat::Default, StatusCode::BAD_REQUEST, info);
+        let error = &json(&body)["error"];
+
+        assert_eq!(error["code"], serde_json::json!("BATCH_FAILED"));
+        assert_eq!(error["fields"][0]["field"], serde_json::json!("amount"));
+        assert_eq!(
+            error["errors"][0]["kind"],
+            serde_json::json!("validation_error")
+        );
     }
 }
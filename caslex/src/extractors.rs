@@ -1,11 +1,24 @@
+use axum::extract::MatchedPath;
 use http::Uri;
 
+/// Route label of requests no route matched, the raw path is never used as a label to keep
+/// metrics cardinality bounded.
+pub const UNMATCHED_ROUTE: &str = "<unmatched>";
+
 #[allow(dead_code)]
 #[inline]
 pub fn url_scheme(uri: &Uri) -> &str {
     uri.scheme_str().unwrap_or_default()
 }
 
+/// Returns the matched route template, e.g. `/users/{id}`, or [`UNMATCHED_ROUTE`].
+#[inline]
+pub fn matched_route<B>(req: &http::Request<B>) -> &str {
+    req.extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED_ROUTE, MatchedPath::as_str)
+}
+
 #[inline]
 pub fn user_agent<B>(req: &http::Request<B>) -> &str {
     req.headers()
@@ -0,0 +1,261 @@
+//! Contains gRPC health check server.
+//!
+//! Implements `Check` method of the standard [gRPC health checking protocol], the status is
+//! resolved by the same health checks as the readiness probe. The empty service name reports
+//! `SERVING` when all checks pass, the name of a registered check reports that check only and
+//! unknown names are rejected with `NOT_FOUND` code.
+//!
+//! The server starts it on `SERVER_GRPC_HEALTH_PORT` port when the env variable is set, the
+//! health server can be run as a separate process too.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::LazyLock;
+//!
+//! use caslex::{
+//!     grpc_health::GrpcHealthServer,
+//!     server::{Config, Process, Server},
+//! };
+//!
+//! static GRPC_HEALTH: LazyLock<GrpcHealthServer> =
+//!     LazyLock::new(|| GrpcHealthServer::new("127.0.0.1:50051", vec![]));
+//!
+//! # async fn run() {
+//! let processes: Vec<&'static dyn Process> = vec![&*GRPC_HEALTH];
+//! let result = Server::new(Config::parse())
+//!     .processes(&processes)
+//!     .run()
+//!     .await;
+//! # }
+//! ```
+//!
+//! [gRPC health checking protocol]: https://github.com/grpc/grpc/blob/master/doc/health-checking.md
+
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use axum::{Router, extract::Request, response::IntoResponse, routing::post};
+use tokio_util::sync::CancellationToken;
+use tonic::{Status, codec::ProstCodec, server::Grpc};
+
+use crate::{
+    health::{CheckStatus, HealthCheck, HealthChecks},
+    server::Process,
+};
+
+const CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct HealthCheckRequest {
+    #[prost(string, tag = "1")]
+    service: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct HealthCheckResponse {
+    #[prost(enumeration = "ServingStatus", tag = "1")]
+    status: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+enum ServingStatus {
+    Unknown = 0,
+    Serving = 1,
+    NotServing = 2,
+}
+
+/// Define gRPC health check server.
+pub struct GrpcHealthServer {
+    addr: String,
+    health_checks: HealthChecks,
+}
+
+impl GrpcHealthServer {
+    /// Creates server listening on `addr` and reporting status of the checks.
+    pub fn new(addr: impl Into<String>, checks: Vec<(String, Arc<dyn HealthCheck>)>) -> Self {
+        GrpcHealthServer {
+            addr: addr.into(),
+            health_checks: HealthChecks::new(checks),
+        }
+    }
+
+    pub(crate) fn with_health_checks(addr: String, health_checks: HealthChecks) -> Self {
+        GrpcHealthServer {
+            addr,
+            health_checks,
+        }
+    }
+
+    /// Serves the health service until the token is cancelled.
+    pub(crate) async fn serve(&self, token: CancellationToken) -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind(&self.addr)
+            .await
+            .map_err(|e| anyhow!("failed to bind to address: {e}"))?;
+
+        tracing::info!("listening grpc health server on {}", self.addr);
+
+        let health_checks = self.health_checks.clone();
+        let router = Router::new()
+            .route(
+                CHECK_PATH,
+                post(move |req: Request| check_handler(health_checks.clone(), req)),
+            )
+            .fallback(|| async { Status::unimplemented("").into_http::<axum::body::Body>() });
+
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(token.cancelled_owned())
+        .await
+        .map_err(|e| anyhow!("failed to start server on address {}: {e}", self.addr))
+    }
+}
+
+#[async_trait]
+impl Process for GrpcHealthServer {
+    async fn pre_run(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn run(&self, token: CancellationToken) -> anyhow::Result<()> {
+        self.serve(token).await
+    }
+}
+
+async fn check_handler(health_checks: HealthChecks, req: Request) -> impl IntoResponse {
+    let service = tower::service_fn(move |req: tonic::Request<HealthCheckRequest>| {
+        let health_checks = health_checks.clone();
+        async move {
+            let status = check_status(&health_checks, &req.get_ref().service).await?;
+            Ok(tonic::Response::new(HealthCheckResponse {
+                status: status as i32,
+            }))
+        }
+    });
+
+    Grpc::new(ProstCodec::<HealthCheckResponse, HealthCheckRequest>::default())
+        .unary(service, req)
+        .await
+}
+
+async fn check_status(
+    health_checks: &HealthChecks,
+    service: &str,
+) -> Result<ServingStatus, Status> {
+    let failed = if service.is_empty() {
+        health_checks
+            .run()
+            .await
+            .iter()
+            .any(|result| result.status == CheckStatus::Failed)
+    } else {
+        let check = health_checks
+            .get(service)
+            .ok_or_else(|| Status::not_found(format!("unknown service {service}")))?;
+        check.check().await.is_err()
+    };
+
+    Ok(if failed {
+        ServingStatus::NotServing
+    } else {
+        ServingStatus::Serving
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::uri::PathAndQuery;
+    use tonic::{Code, transport::Channel};
+
+    use super::*;
+
+    /// Health check failing with the error when it's set.
+    struct FixedCheck(Option<&'static str>);
+
+    #[async_trait]
+    impl HealthCheck for FixedCheck {
+        async fn check(&self) -> anyhow::Result<()> {
+            match self.0 {
+                Some(error) => Err(anyhow!(error)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    async fn check(channel: Channel, service: &str) -> Result<ServingStatus, Status> {
+        let mut client = tonic::client::Grpc::new(channel);
+        client.ready().await.unwrap();
+
+        let response = client
+            .unary(
+                tonic::Request::new(HealthCheckRequest {
+                    service: service.to_owned(),
+                }),
+                PathAndQuery::from_static(CHECK_PATH),
+                ProstCodec::<HealthCheckRequest, HealthCheckResponse>::default(),
+            )
+            .await?;
+
+        Ok(response.into_inner().status())
+    }
+
+    #[tokio::test]
+    async fn client_receives_status_of_checks() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = Arc::new(GrpcHealthServer::new(
+            addr.to_string(),
+            vec![
+                ("cache".to_owned(), Arc::new(FixedCheck(None)) as _),
+                (
+                    "db".to_owned(),
+                    Arc::new(FixedCheck(Some("connection refused"))) as _,
+                ),
+            ],
+        ));
+        let token = CancellationToken::new();
+        let handle = tokio::spawn({
+            let server = Arc::clone(&server);
+            let token = token.clone();
+            async move { server.serve(token).await }
+        });
+
+        let endpoint = Channel::from_shared(format!("http://{addr}")).unwrap();
+        // the server binds the listener in background
+        let mut channel = endpoint.connect().await;
+        for _ in 0..50 {
+            if channel.is_ok() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            channel = endpoint.connect().await;
+        }
+        let channel = channel.unwrap();
+
+        assert_eq!(
+            check(channel.clone(), "").await.unwrap(),
+            ServingStatus::NotServing
+        );
+        assert_eq!(
+            check(channel.clone(), "cache").await.unwrap(),
+            ServingStatus::Serving
+        );
+        assert_eq!(
+            check(channel.clone(), "db").await.unwrap(),
+            ServingStatus::NotServing
+        );
+        assert_eq!(
+            check(channel, "queue").await.unwrap_err().code(),
+            Code::NotFound
+        );
+
+        token.cancel();
+        handle.await.unwrap().unwrap();
+    }
+}
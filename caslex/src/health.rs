@@ -0,0 +1,173 @@
+//! Contains health checks used by the readiness probe.
+//!
+//! Registered checks are run on every `/readiness` request, the endpoint returns `503` with the
+//! list of failed checks when any of them fails. Until `pre_run` of all server processes is
+//! finished the readiness reports failed `startup` check, liveness isn't affected.
+//!
+//! `/readiness?verbose=true` returns JSON body with status and latency of every check even when
+//! all checks pass, e.g. `{ "status": "ok", "checks": [{ "name": "cache", "status": "ok",
+//! "latency_ms": 0.42 }] }`, the status code still reflects the overall status.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use async_trait::async_trait;
+//! use caslex::{
+//!     health::HealthCheck,
+//!     server::{Config, Server},
+//! };
+//!
+//! struct CacheCheck;
+//!
+//! #[async_trait]
+//! impl HealthCheck for CacheCheck {
+//!     async fn check(&self) -> anyhow::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # async fn run() {
+//! let config = Config::parse();
+//! let result = Server::new(config)
+//!     .health_check("cache", Arc::new(CacheCheck))
+//!     .run()
+//!     .await;
+//! # }
+//! ```
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::time::Instant;
+
+/// Define dependency health check trait.
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    async fn check(&self) -> anyhow::Result<()>;
+}
+
+/// Checks pool by running `SELECT 1` on a pooled connection.
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl HealthCheck for deadpool_postgres::Pool {
+    async fn check(&self) -> anyhow::Result<()> {
+        let client = self.get().await?;
+        client.simple_query("SELECT 1").await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct HealthChecks {
+    checks: Arc<Vec<(String, Arc<dyn HealthCheck>)>>,
+    started: Arc<AtomicBool>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CheckStatus {
+    Ok,
+    Failed,
+}
+
+#[derive(Serialize)]
+pub(crate) struct CheckResult {
+    name: String,
+    pub(crate) status: CheckStatus,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl CheckResult {
+    fn new(name: String, latency: Duration, error: Option<String>) -> Self {
+        CheckResult {
+            name,
+            status: match error {
+                Some(_) => CheckStatus::Failed,
+                None => CheckStatus::Ok,
+            },
+            // microsecond precision
+            latency_ms: latency.as_micros() as f64 / 1000.0,
+            error,
+        }
+    }
+}
+
+impl HealthChecks {
+    #[cfg(feature = "grpc-health")]
+    pub(crate) fn new(checks: Vec<(String, Arc<dyn HealthCheck>)>) -> Self {
+        Self::with_startup_gate(checks, Arc::new(AtomicBool::new(true)))
+    }
+
+    /// Creates checks failing until the `started` flag is set.
+    pub(crate) fn with_startup_gate(
+        checks: Vec<(String, Arc<dyn HealthCheck>)>,
+        started: Arc<AtomicBool>,
+    ) -> Self {
+        HealthChecks {
+            checks: Arc::new(checks),
+            started,
+        }
+    }
+
+    /// Returns the check registered with the name.
+    #[cfg(feature = "grpc-health")]
+    pub(crate) fn get(&self, name: &str) -> Option<Arc<dyn HealthCheck>> {
+        self.checks
+            .iter()
+            .find(|(check_name, _)| check_name == name)
+            .map(|(_, check)| check.clone())
+    }
+
+    /// Runs all checks concurrently and returns results of every check.
+    pub(crate) async fn run(&self) -> Vec<CheckResult> {
+        if !self.started.load(Ordering::Acquire) {
+            return vec![CheckResult::new(
+                "startup".to_owned(),
+                Duration::ZERO,
+                Some("processes pre run is not finished".to_owned()),
+            )];
+        }
+
+        let started = Instant::now();
+        let tasks: Vec<_> = self
+            .checks
+            .iter()
+            .map(|(name, check)| {
+                let check = check.clone();
+                let task = tokio::spawn(async move {
+                    let started = Instant::now();
+                    let result = check.check().await;
+                    (result, started.elapsed())
+                });
+                (name, task)
+            })
+            .collect();
+
+        let mut results = vec![];
+        for (name, task) in tasks {
+            let (error, latency) = match task.await {
+                Ok((Ok(()), latency)) => (None, latency),
+                Ok((Err(e), latency)) => (Some(e.to_string()), latency),
+                Err(e) => (
+                    Some(format!("health check panicked: {e}")),
+                    started.elapsed(),
+                ),
+            };
+
+            results.push(CheckResult::new(name.clone(), latency, error));
+        }
+
+        results
+    }
+}
@@ -115,6 +115,15 @@
 //! }
 //! ```
 //!
+//! # Metrics and tracing
+//!
+//! Requests are labeled by the matched route template, e.g. `/users/{id}`, never by the raw path:
+//! the `path` label of HTTP metrics and the `http.path` field of the `http_request` span are the
+//! same value. Requests no route matched, e.g. `404` responses, are labeled `<unmatched>`, so
+//! metrics cardinality stays bounded.
+//!
+//! Application metrics registered with [`metrics`] helpers are served along with the built-in ones.
+//!
 //! # Examples
 //!
 //! The caslex repo contains a number of [examples] that show how to put all the pieces together.
@@ -129,15 +138,30 @@
 //! Name | Description | Default?
 //! ---|---|---
 //! `auth` | Enables auth middleware | No
+//! `postgres` | Enables postgres errors conversion | No
+//! `migrations` | Enables postgres migration runner process | No
+//! `swagger-ui` | Enables Swagger UI docs | No
+//! `rapidoc` | Enables RapiDoc docs | No
+//! `grpc-health` | Enables gRPC health check server | No
+//! `multipart` | Enables multipart upload extractor | No
+//! `nats` | Enables NATS message sink of the broker publisher | No
+//! `redis` | Enables Redis idempotency store | No
 //!
 //! [feature flags]: https://doc.rust-lang.org/cargo/reference/features.html#the-features-section
 //! [examples]: https://github.com/mkbeh/caslex/tree/main/examples
 
 mod extractors;
-mod metrics;
 mod swagger;
 mod trace;
 
+pub mod broker;
 pub mod errors;
+#[cfg(feature = "grpc-health")]
+pub mod grpc_health;
+pub mod health;
+pub mod metrics;
 pub mod middlewares;
+#[cfg(feature = "multipart")]
+pub mod multipart;
 pub mod server;
+pub mod sse;
@@ -1,18 +1,43 @@
 //! Provides prometheus plug-in metrics for Axum server.
 //!
-//! This module tracks the following metrics under the following names:
+//! This module tracks the following metrics under the following names, where `path` is the matched
+//! route template (`<unmatched>` when no route matched) and `status` is the status code class
+//! (`2xx`, `4xx`, ...):
 //!     - http_requests_total{method={"method"},path={"path"},status={"status"}}
 //!     - http_request_duration_sum{method={"method"},path={"path"},status={"status"}}
 //!     - http_request_duration_count{method={"method"},path={"path"},status={"status"}}
 //!     - http_request_duration_bucket{method={"method"},path={"path"},status={"status"},le={" le"}}
 //!     - http_request_size{method={"method"},path={"path"},status={"status"}}
 //!     - http_response_size{method={"method"},path={"path"},status={"status"}}
+//!     - http_handler_panics_total{path={"path"}}
+//!
+//! Application metrics registered with the helpers below or in [`registry`] are served by the
+//! same metrics endpoint.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use caslex::metrics;
+//!
+//! let orders = metrics::register_counter_vec(
+//!     "orders_created_total",
+//!     "Total number of created orders.",
+//!     &["channel"],
+//! )
+//! .unwrap();
+//!
+//! orders.with_label_values(&["web"]).inc();
+//! ```
 
-use std::clone::Clone;
+use std::{
+    clone::Clone,
+    sync::{Arc, OnceLock},
+};
 
+use anyhow::anyhow;
 use axum::{
     body::{Bytes, HttpBody},
-    extract::MatchedPath,
+    extract::State,
     middleware::Next,
 };
 use axum_core::{
@@ -22,25 +47,41 @@ use axum_core::{
 use http::header::CONTENT_TYPE;
 use http_body_util::Full;
 use lazy_static::lazy_static;
+pub use prometheus::{Counter, CounterVec, Gauge, GaugeVec, Opts, Registry};
 use prometheus::{
-    CounterVec, Encoder, GaugeVec, HistogramVec, TextEncoder, register_counter_vec,
-    register_gauge_vec, register_histogram_vec,
+    Encoder, HistogramOpts, HistogramVec, TextEncoder, register_counter_vec, register_gauge_vec,
 };
 use tokio::time::Instant;
 
+use crate::extractors;
+
+static BUCKETS: OnceLock<Vec<f64>> = OnceLock::new();
+
+tokio::task_local! {
+    static REQUEST_PATH: String;
+}
+
 lazy_static! {
+    // registered without unwrap, it's used by the panic handler which must not panic itself
+    static ref HTTP_PANIC_COUNTER: Option<CounterVec> = register_counter_vec!(
+        "http_handler_panics_total",
+        "Total number of panics in HTTP handlers.",
+        &["path"]
+    )
+    .ok();
     static ref HTTP_COUNTER: CounterVec = register_counter_vec!(
         "http_requests_total",
         "Total number of HTTP requests made.",
         &["method", "path", "status"]
     )
     .unwrap();
-    static ref HTTP_REQ_HISTOGRAM: HistogramVec = register_histogram_vec!(
-        "http_request_duration",
-        "The HTTP request latencies in milliseconds.",
-        &["method", "path", "status"]
-    )
-    .unwrap();
+    static ref HTTP_REQ_HISTOGRAM: HistogramVec = {
+        // buckets can't be set after the histogram is created
+        let buckets = BUCKETS.get_or_init(|| prometheus::DEFAULT_BUCKETS.to_vec());
+        let histogram = duration_histogram(buckets.clone()).unwrap();
+        prometheus::register(Box::new(histogram.clone())).unwrap();
+        histogram
+    };
     static ref HTTP_REQ_BODY_GAUGE: GaugeVec = register_gauge_vec!(
         "http_request_size",
         "The metrics HTTP request sizes in bytes.",
@@ -55,13 +96,109 @@ lazy_static! {
     .unwrap();
 }
 
-pub async fn metrics_handler(req: Request, next: Next) -> impl IntoResponse {
-    let start = Instant::now();
+/// Returns registry served by the metrics endpoint, the built-in HTTP metrics are registered in it.
+pub fn registry() -> &'static Registry {
+    prometheus::default_registry()
+}
 
-    let path = match req.extensions().get::<MatchedPath>() {
-        Some(matched_path) => matched_path.as_str().to_owned(),
-        _ => req.uri().path().to_owned(),
-    };
+/// Registers counter, fails if a metric with the same name is registered.
+pub fn register_counter(name: &str, help: &str) -> anyhow::Result<Counter> {
+    let counter = Counter::new(name, help)?;
+    registry().register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+/// Registers counter partitioned by `labels`, fails if a metric with the same name is registered.
+pub fn register_counter_vec(name: &str, help: &str, labels: &[&str]) -> anyhow::Result<CounterVec> {
+    let counter = CounterVec::new(Opts::new(name, help), labels)?;
+    registry().register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+/// Registers gauge, fails if a metric with the same name is registered.
+pub fn register_gauge(name: &str, help: &str) -> anyhow::Result<Gauge> {
+    let gauge = Gauge::new(name, help)?;
+    registry().register(Box::new(gauge.clone()))?;
+    Ok(gauge)
+}
+
+/// Registers gauge partitioned by `labels`, fails if a metric with the same name is registered.
+pub fn register_gauge_vec(name: &str, help: &str, labels: &[&str]) -> anyhow::Result<GaugeVec> {
+    let gauge = GaugeVec::new(Opts::new(name, help), labels)?;
+    registry().register(Box::new(gauge.clone()))?;
+    Ok(gauge)
+}
+
+/// Sets request duration histogram buckets, must be called before the first request is tracked.
+///
+/// Buckets must be positive and strictly increasing. Returns error when other buckets are already
+/// in use, i.e. set before or defaults used by a tracked request.
+pub(crate) fn set_buckets(buckets: Vec<f64>) -> anyhow::Result<()> {
+    set_buckets_in(&BUCKETS, buckets)
+}
+
+fn set_buckets_in(cell: &OnceLock<Vec<f64>>, buckets: Vec<f64>) -> anyhow::Result<()> {
+    if buckets.is_empty() {
+        return Err(anyhow!("metrics buckets must not be empty"));
+    }
+
+    if let Some(bucket) = buckets.iter().find(|v| !v.is_finite() || **v <= 0.0) {
+        return Err(anyhow!(
+            "metrics buckets must be positive numbers, got {bucket}"
+        ));
+    }
+
+    if let Some((prev, next)) = buckets
+        .iter()
+        .zip(buckets.iter().skip(1))
+        .find(|(prev, next)| prev >= next)
+    {
+        return Err(anyhow!(
+            "metrics buckets must be strictly increasing, got {next} after {prev}"
+        ));
+    }
+
+    let current = cell.get_or_init(|| buckets.clone());
+    if *current != buckets {
+        return Err(anyhow!(
+            "metrics buckets are already set to {current:?}, can't set {buckets:?}"
+        ));
+    }
+
+    Ok(())
+}
+
+fn duration_histogram(buckets: Vec<f64>) -> prometheus::Result<HistogramVec> {
+    HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_duration",
+            "The HTTP request latencies in seconds.",
+        )
+        .buckets(buckets),
+        &["method", "path", "status"],
+    )
+}
+
+/// Tracks request metrics, requests to `exclude_paths` routes are skipped.
+pub(crate) async fn metrics_handler(
+    State(exclude_paths): State<Arc<[String]>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let path = extractors::matched_route(&req).to_owned();
+
+    // keep path available for the panic handler
+    if exclude_paths.contains(&path) {
+        return REQUEST_PATH.scope(path, next.run(req)).await;
+    }
+
+    REQUEST_PATH
+        .scope(path.clone(), track_request(path, req, next))
+        .await
+}
+
+async fn track_request(path: String, req: Request, next: Next) -> Response {
+    let start = Instant::now();
 
     let method = req.method().clone();
     let req_body_size = req.body().size_hint().lower();
@@ -69,7 +206,7 @@ pub async fn metrics_handler(req: Request, next: Next) -> impl IntoResponse {
     let response = next.run(req).await;
 
     let latency = start.elapsed().as_secs_f64();
-    let status = response.status().as_u16().to_string();
+    let status = format!("{}xx", response.status().as_u16() / 100);
     let resp_body_size = response.body().size_hint().lower();
 
     let labels = &[method.as_str(), path.as_str(), status.as_str()];
@@ -88,7 +225,21 @@ pub async fn metrics_handler(req: Request, next: Next) -> impl IntoResponse {
     response
 }
 
-pub async fn prometheus_handler() -> impl IntoResponse {
+/// Increments handler panics counter labeled by the current request path.
+pub(crate) fn record_panic() {
+    let path = REQUEST_PATH
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| extractors::UNMATCHED_ROUTE.to_owned());
+
+    if let Some(Ok(counter)) = HTTP_PANIC_COUNTER
+        .as_ref()
+        .map(|counter| counter.get_metric_with_label_values(&[path.as_str()]))
+    {
+        counter.inc();
+    }
+}
+
+pub(crate) async fn prometheus_handler() -> impl IntoResponse {
     let mut buffer = vec![];
     let encoder = TextEncoder::new();
     let metric_families = prometheus::gather();
@@ -101,3 +252,94 @@ pub async fn prometheus_handler() -> impl IntoResponse {
         .unwrap()
         .into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn invalid_buckets_are_rejected() {
+        let cell = OnceLock::new();
+
+        assert!(set_buckets_in(&cell, vec![]).is_err());
+        assert!(set_buckets_in(&cell, vec![0.0, 1.0]).is_err());
+        assert!(set_buckets_in(&cell, vec![0.5, f64::NAN]).is_err());
+        assert!(set_buckets_in(&cell, vec![1.0, 0.5]).is_err());
+        assert!(set_buckets_in(&cell, vec![0.5, 0.5]).is_err());
+        assert!(cell.get().is_none());
+    }
+
+    #[test]
+    fn buckets_in_use_are_not_replaced() {
+        let cell = OnceLock::new();
+
+        set_buckets_in(&cell, vec![0.1, 1.0]).unwrap();
+        set_buckets_in(&cell, vec![0.1, 1.0]).unwrap();
+        let error = set_buckets_in(&cell, vec![0.5, 2.0]).unwrap_err();
+
+        assert!(error.to_string().contains("already set"), "{error}");
+        assert_eq!(cell.get(), Some(&vec![0.1, 1.0]));
+    }
+
+    #[test]
+    fn buckets_set_before_defaults_are_used_are_rejected() {
+        let cell = OnceLock::new();
+        cell.get_or_init(|| prometheus::DEFAULT_BUCKETS.to_vec());
+
+        assert!(set_buckets_in(&cell, vec![0.1, 1.0]).is_err());
+    }
+
+    #[test]
+    fn custom_buckets_are_emitted_as_le_labels() {
+        let registry = Registry::new();
+        let histogram = duration_histogram(vec![0.05, 0.25, 2.5]).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+
+        histogram
+            .with_label_values(&["GET", "/users/{id}", "2xx"])
+            .observe(0.1);
+
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut buffer)
+            .unwrap();
+        let metrics = String::from_utf8(buffer).unwrap();
+
+        for le in ["0.05", "0.25", "2.5", "+Inf"] {
+            assert!(metrics.contains(&format!("le=\"{le}\"")), "{metrics}");
+        }
+        assert!(!metrics.contains("le=\"0.005\""), "{metrics}");
+    }
+
+    #[tokio::test]
+    async fn custom_metrics_are_scraped_from_metrics_endpoint() {
+        let orders = register_counter_vec(
+            "test_orders_created_total",
+            "Total number of created orders.",
+            &["channel"],
+        )
+        .unwrap();
+        orders.with_label_values(&["web"]).inc_by(3.0);
+        let queue = register_gauge("test_queue_depth", "Depth of the queue.").unwrap();
+        queue.set(7.0);
+        // names are unique within the registry
+        assert!(register_counter("test_queue_depth", "Duplicate.").is_err());
+
+        let router = Router::new().route("/metrics", get(prometheus_handler));
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let metrics = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(
+            metrics.contains("test_orders_created_total{channel=\"web\"} 3"),
+            "{metrics}"
+        );
+        assert!(metrics.contains("test_queue_depth 7"), "{metrics}");
+    }
+}
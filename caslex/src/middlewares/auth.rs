@@ -11,25 +11,95 @@
 //!     // will be error before enter the body
 //! }
 //! ```
+//!
+//! Use optional claims when the endpoint is available for anonymous users too
+//!
+//! ```rust,no_run
+//! use caslex::middlewares::auth::OptionalClaims;
+//!
+//! async fn optional_handler(OptionalClaims(claims): OptionalClaims) {
+//!     // claims is `None` when the `Authorization` header is absent
+//! }
+//! ```
+//!
+//! Require a scope from the space-delimited `scope` claim, requests without it are rejected with
+//! `403` and `auth_insufficient_scope` kind
+//!
+//! ```rust,no_run
+//! use axum::middleware;
+//! use caslex::middlewares::auth::{RequireScope, Scope, require_scope};
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! struct Admin;
+//!
+//! impl Scope for Admin {
+//!     const SCOPE: &'static str = "admin";
+//! }
+//!
+//! async fn admin_handler(RequireScope(claims, _): RequireScope<Admin>) {}
+//!
+//! // or check the scope for all routes of the router
+//! let router: OpenApiRouter =
+//!     OpenApiRouter::new().layer(middleware::from_fn_with_state("admin", require_scope));
+//! ```
+//!
+//! The token is read from the `Authorization: Bearer <token>` header by default, the header name
+//! and scheme are configured by `AUTH_HEADER_NAME` and `AUTH_SCHEME` env variables, the server
+//! fails to start with invalid header name and requests are rejected without it. Set empty
+//! `AUTH_SCHEME` when the header value is the token itself, e.g. `X-Access-Token: <token>`.
+//!
+//! Set `JWT_COOKIE_NAME` env variable to read the token from the cookie when the auth header is
+//! absent, the header takes precedence when both are present.
 
-use std::{collections::HashMap, error::Error as StdError, fmt, fmt::Display, sync::LazyLock};
+use std::{
+    collections::HashMap, env, error::Error as StdError, fmt, fmt::Display, marker::PhantomData,
+    sync::LazyLock,
+};
 
-use axum_core::{RequestPartsExt, extract::FromRequestParts};
-use axum_extra::{
-    TypedHeader,
-    headers::{Authorization, authorization::Bearer},
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
 };
+use axum_core::{extract::FromRequestParts, response::Response};
+use axum_extra::extract::CookieJar;
 use caslex_extra::security::jwt;
-use http::{StatusCode, request::Parts};
-use jsonwebtoken::errors::ErrorKind;
+use http::{HeaderName, StatusCode, header::AUTHORIZATION, request::Parts};
+use jsonwebtoken::{TokenData, errors::ErrorKind};
 use serde::{Deserialize, Serialize};
 
 use crate::errors::{AppError, DefaultError};
 
+static AUTH_HEADER: LazyLock<Result<HeaderName, String>> =
+    LazyLock::new(|| match env::var("AUTH_HEADER_NAME") {
+        Ok(name) => parse_auth_header(&name),
+        Err(_) => Ok(AUTHORIZATION),
+    });
+
+static AUTH_SCHEME: LazyLock<String> =
+    LazyLock::new(|| env::var("AUTH_SCHEME").unwrap_or_else(|_| "Bearer".to_owned()));
+
+static COOKIE_NAME: LazyLock<Option<String>> = LazyLock::new(|| {
+    env::var("JWT_COOKIE_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+});
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: u64,
+    /// Space-delimited scopes granted to the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+impl Claims {
+    /// Returns true if the `scope` claim contains the scope.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope
+            .as_deref()
+            .is_some_and(|scopes| scopes.split_whitespace().any(|s| s == scope))
+    }
 }
 
 impl<S> FromRequestParts<S> for Claims
@@ -39,26 +109,163 @@ where
     type Rejection = DefaultError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let wrapper = DefaultError::AppError;
-
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| wrapper(&AuthError::InvalidToken))?;
-
-        let token_data = match jwt::decode_token::<Claims>(bearer.token()) {
-            Ok(data) => data,
-            Err(err) => match err.kind() {
-                ErrorKind::ExpiredSignature => Err(wrapper(&AuthError::ExpiredSignature))?,
-                ErrorKind::InvalidToken => Err(wrapper(&AuthError::InvalidToken))?,
-                ErrorKind::InvalidSignature => Err(wrapper(&AuthError::InvalidSignature))?,
-                ErrorKind::Json(_) => Err(wrapper(&AuthError::InvalidClaims))?,
-                _ => Err(wrapper(&AuthError::InvalidToken))?,
-            },
-        };
+        let token = extract_token(parts).unwrap_or(Err(&AuthError::InvalidToken));
+
+        decode_claims(token, jwt::decode_token)
+    }
+}
+
+/// Decodes claims from the extracted token with `decode`, maps decoding errors to [`AuthError`].
+fn decode_claims(
+    token: Result<String, &'static AuthError>,
+    decode: impl FnOnce(&str) -> jsonwebtoken::errors::Result<TokenData<Claims>>,
+) -> Result<Claims, DefaultError> {
+    let wrapper = DefaultError::AppError;
+
+    let token = token.map_err(|e| wrapper(e))?;
+
+    let token_data = match decode(&token) {
+        Ok(data) => data,
+        Err(err) => match err.kind() {
+            ErrorKind::ExpiredSignature => Err(wrapper(&AuthError::ExpiredSignature))?,
+            ErrorKind::InvalidToken => Err(wrapper(&AuthError::InvalidToken))?,
+            ErrorKind::InvalidSignature => Err(wrapper(&AuthError::InvalidSignature))?,
+            ErrorKind::Json(_)
+            | ErrorKind::InvalidAudience
+            | ErrorKind::InvalidIssuer
+            | ErrorKind::MissingRequiredClaim(_) => Err(wrapper(&AuthError::InvalidClaims))?,
+            _ => Err(wrapper(&AuthError::InvalidToken))?,
+        },
+    };
+
+    Ok(token_data.claims)
+}
+
+fn parse_auth_header(name: &str) -> Result<HeaderName, String> {
+    name.parse()
+        .map_err(|e| format!("Invalid AUTH_HEADER_NAME {name:?}: {e}"))
+}
+
+/// Returns the auth header name, error when `AUTH_HEADER_NAME` is invalid.
+pub(crate) fn auth_header() -> anyhow::Result<&'static HeaderName> {
+    AUTH_HEADER.as_ref().map_err(|e| anyhow::anyhow!("{e}"))
+}
+
+/// Returns token from the auth header or the `JWT_COOKIE_NAME` cookie, `None` when both are
+/// absent.
+fn extract_token(parts: &Parts) -> Option<Result<String, &'static AuthError>> {
+    // the server doesn't start with invalid header name, reject when used without it
+    let Ok(auth_header) = auth_header() else {
+        return Some(Err(&AuthError::InvalidToken));
+    };
 
-        Ok(token_data.claims)
+    extract_token_from(parts, auth_header, &AUTH_SCHEME, COOKIE_NAME.as_deref())
+}
+
+fn extract_token_from(
+    parts: &Parts,
+    header: &HeaderName,
+    scheme: &str,
+    cookie: Option<&str>,
+) -> Option<Result<String, &'static AuthError>> {
+    if let Some(value) = parts.headers.get(header) {
+        return Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| strip_scheme(value, scheme))
+                .map(|token| token.trim().to_owned())
+                .filter(|token| !token.is_empty())
+                .ok_or(&AuthError::InvalidToken),
+        );
+    }
+
+    CookieJar::from_headers(&parts.headers)
+        .get(cookie?)
+        .map(|cookie| Ok(cookie.value().to_owned()))
+}
+
+fn strip_scheme<'v>(value: &'v str, scheme: &str) -> Option<&'v str> {
+    if scheme.is_empty() {
+        return Some(value);
+    }
+
+    value
+        .split_once(' ')
+        .filter(|(value_scheme, _)| value_scheme.eq_ignore_ascii_case(scheme))
+        .map(|(_, token)| token)
+}
+
+/// Define optional claims.
+///
+/// Contains `None` when the auth header and the `JWT_COOKIE_NAME` cookie are absent,
+/// otherwise rejects the same way as [`Claims`].
+#[derive(Debug)]
+pub struct OptionalClaims(pub Option<Claims>);
+
+impl<S> FromRequestParts<S> for OptionalClaims
+where
+    S: Send + Sync,
+{
+    type Rejection = DefaultError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        decode_optional_claims(extract_token(parts), jwt::decode_token).map(OptionalClaims)
+    }
+}
+
+/// Decodes claims when the token is present, `None` otherwise.
+fn decode_optional_claims(
+    token: Option<Result<String, &'static AuthError>>,
+    decode: impl FnOnce(&str) -> jsonwebtoken::errors::Result<TokenData<Claims>>,
+) -> Result<Option<Claims>, DefaultError> {
+    token.map(|token| decode_claims(token, decode)).transpose()
+}
+
+/// Define scope required by [`RequireScope`].
+pub trait Scope: Send + Sync {
+    const SCOPE: &'static str;
+}
+
+/// Define claims extractor requiring the scope.
+///
+/// Rejects the same way as [`Claims`] and with [`AuthError::InsufficientScope`] when the `scope`
+/// claim lacks [`Scope::SCOPE`].
+#[derive(Debug)]
+pub struct RequireScope<S: Scope>(pub Claims, pub PhantomData<S>);
+
+impl<S, T> FromRequestParts<S> for RequireScope<T>
+where
+    S: Send + Sync,
+    T: Scope,
+{
+    type Rejection = DefaultError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = Claims::from_request_parts(parts, state).await?;
+
+        require_claims_scope(claims, T::SCOPE).map(|claims| RequireScope(claims, PhantomData))
+    }
+}
+
+/// Rejects requests which claims lack the scope passed as the middleware state.
+pub async fn require_scope(
+    State(scope): State<&'static str>,
+    claims: Claims,
+    req: Request,
+    next: Next,
+) -> Result<Response, DefaultError> {
+    require_claims_scope(claims, scope)?;
+
+    Ok(next.run(req).await)
+}
+
+fn require_claims_scope(claims: Claims, scope: &str) -> Result<Claims, DefaultError> {
+    if !claims.has_scope(scope) {
+        return Err(DefaultError::AppError(&AuthError::InsufficientScope));
     }
+
+    Ok(claims)
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -70,6 +277,7 @@ pub enum AuthError {
     InvalidSignature,
     InvalidClaims,
     ExpiredSignature,
+    InsufficientScope,
 }
 
 impl StdError for AuthError {}
@@ -178,5 +386,250 @@ static AUTH_ERRORS: LazyLock<HashMap<AuthError, FullError>> = LazyLock::new(|| {
         },
     );
 
+    map.insert(
+        AuthError::InsufficientScope,
+        FullError {
+            code: StatusCode::FORBIDDEN,
+            kind: "auth_insufficient_scope".to_owned(),
+            details: "insufficient scope".to_owned(),
+        },
+    );
+
     map
 });
+
+#[cfg(test)]
+mod tests {
+    use http::Request;
+    use jsonwebtoken::{
+        Algorithm, DecodingKey, EncodingKey, Header, Validation, get_current_timestamp,
+    };
+
+    use super::*;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn token(exp: u64) -> String {
+        let claims = Claims {
+            sub: "123".to_owned(),
+            exp,
+            scope: None,
+        };
+        jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap()
+    }
+
+    fn decode(token: &str) -> jsonwebtoken::errors::Result<TokenData<Claims>> {
+        jsonwebtoken::decode(
+            token,
+            &DecodingKey::from_secret(SECRET),
+            &Validation::new(Algorithm::HS256),
+        )
+    }
+
+    fn optional_claims(headers: &[(&str, &str)]) -> Result<Option<Claims>, DefaultError> {
+        let token = extract_token_from(&parts(headers), &AUTHORIZATION, "Bearer", None);
+        decode_optional_claims(token, decode)
+    }
+
+    fn error_kind(error: DefaultError) -> String {
+        match error {
+            DefaultError::AppError(error) => error.kind(),
+            error => panic!("unexpected error: {error:?}"),
+        }
+    }
+
+    fn parts(headers: &[(&str, &str)]) -> Parts {
+        let mut request = Request::builder();
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn token_is_read_from_custom_header() {
+        let header = parse_auth_header("X-Access-Token").unwrap();
+        let parts = parts(&[("x-access-token", "abc"), ("authorization", "Bearer xyz")]);
+
+        let token = extract_token_from(&parts, &header, "", None);
+
+        assert_eq!(token, Some(Ok("abc".to_owned())));
+    }
+
+    #[test]
+    fn token_is_read_from_custom_header_with_scheme() {
+        let header = parse_auth_header("X-Api-Auth").unwrap();
+
+        let token = extract_token_from(
+            &parts(&[("x-api-auth", "token abc")]),
+            &header,
+            "Token",
+            None,
+        );
+        assert_eq!(token, Some(Ok("abc".to_owned())));
+
+        let token = extract_token_from(
+            &parts(&[("x-api-auth", "Bearer abc")]),
+            &header,
+            "Token",
+            None,
+        );
+        assert_eq!(token, Some(Err(&AuthError::InvalidToken)));
+    }
+
+    #[test]
+    fn default_header_is_ignored_with_custom_header() {
+        let header = parse_auth_header("X-Access-Token").unwrap();
+
+        let token = extract_token_from(
+            &parts(&[("authorization", "Bearer xyz")]),
+            &header,
+            "",
+            None,
+        );
+
+        assert_eq!(token, None);
+    }
+
+    #[test]
+    fn optional_claims_are_none_without_header() {
+        assert!(optional_claims(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn optional_claims_are_decoded_from_valid_token() {
+        let header = format!("Bearer {}", token(get_current_timestamp() + 60));
+
+        let claims = optional_claims(&[("authorization", &header)]).unwrap();
+
+        assert_eq!(claims.unwrap().sub, "123");
+    }
+
+    #[test]
+    fn optional_claims_reject_expired_token() {
+        let header = format!("Bearer {}", token(get_current_timestamp() - 120));
+
+        let error = optional_claims(&[("authorization", &header)]).unwrap_err();
+
+        assert_eq!(error_kind(error), "auth_expired_signature");
+    }
+
+    #[test]
+    fn optional_claims_reject_malformed_token() {
+        let error = optional_claims(&[("authorization", "Bearer not-a-jwt")]).unwrap_err();
+        assert_eq!(error_kind(error), "auth_invalid_token");
+
+        let error = optional_claims(&[("authorization", "Basic abc")]).unwrap_err();
+        assert_eq!(error_kind(error), "auth_invalid_token");
+    }
+
+    #[test]
+    fn invalid_header_name_is_an_error() {
+        assert!(parse_auth_header("X Access Token").is_err());
+        assert!(parse_auth_header("").is_err());
+    }
+
+    fn cookie_claims(headers: &[(&str, &str)]) -> Result<Claims, DefaultError> {
+        let token = extract_token_from(&parts(headers), &AUTHORIZATION, "Bearer", Some("jwt"))
+            .unwrap_or(Err(&AuthError::InvalidToken));
+        decode_claims(token, decode)
+    }
+
+    #[test]
+    fn token_is_read_from_cookie() {
+        let cookie = format!("theme=dark; jwt={}", token(get_current_timestamp() + 60));
+
+        let claims = cookie_claims(&[("cookie", &cookie)]).unwrap();
+
+        assert_eq!(claims.sub, "123");
+    }
+
+    #[test]
+    fn token_is_read_from_header_without_cookie() {
+        let header = format!("Bearer {}", token(get_current_timestamp() + 60));
+
+        let claims = cookie_claims(&[("authorization", &header)]).unwrap();
+
+        assert_eq!(claims.sub, "123");
+    }
+
+    #[test]
+    fn header_takes_precedence_over_cookie() {
+        let cookie = format!("jwt={}", token(get_current_timestamp() + 60));
+
+        let token = extract_token_from(
+            &parts(&[("cookie", &cookie), ("authorization", "Bearer abc")]),
+            &AUTHORIZATION,
+            "Bearer",
+            Some("jwt"),
+        );
+
+        assert_eq!(token, Some(Ok("abc".to_owned())));
+    }
+
+    #[test]
+    fn invalid_cookie_token_is_rejected() {
+        let cookie = format!("jwt={}", token(get_current_timestamp() - 120));
+        let error = cookie_claims(&[("cookie", &cookie)]).unwrap_err();
+        assert_eq!(error_kind(error), "auth_expired_signature");
+
+        let error = cookie_claims(&[("cookie", "jwt=not-a-jwt")]).unwrap_err();
+        assert_eq!(error_kind(error), "auth_invalid_token");
+
+        let error = cookie_claims(&[("cookie", "session=abc")]).unwrap_err();
+        assert_eq!(error_kind(error), "auth_invalid_token");
+    }
+
+    fn scoped_claims(scope: Option<&str>) -> Claims {
+        Claims {
+            sub: "123".to_owned(),
+            exp: get_current_timestamp() + 60,
+            scope: scope.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn scope_is_matched_as_whole_word() {
+        let claims = scoped_claims(Some("read:orders  admin"));
+
+        assert!(claims.has_scope("admin"));
+        assert!(claims.has_scope("read:orders"));
+        assert!(!claims.has_scope("read"));
+        assert!(!claims.has_scope("admin read:orders"));
+        assert!(!scoped_claims(None).has_scope("admin"));
+    }
+
+    #[test]
+    fn allowed_scope_passes() {
+        let claims = require_claims_scope(scoped_claims(Some("read admin")), "admin").unwrap();
+
+        assert_eq!(claims.sub, "123");
+    }
+
+    #[test]
+    fn denied_scope_is_forbidden() {
+        for scope in [Some("read write"), Some(""), None] {
+            let error = require_claims_scope(scoped_claims(scope), "admin").unwrap_err();
+
+            let DefaultError::AppError(error) = error else {
+                panic!("unexpected error: {error:?}");
+            };
+            assert_eq!(error.status(), StatusCode::FORBIDDEN);
+            assert_eq!(error.kind(), "auth_insufficient_scope");
+        }
+    }
+
+    #[test]
+    fn audience_and_issuer_errors_are_invalid_claims() {
+        for kind in [ErrorKind::InvalidAudience, ErrorKind::InvalidIssuer] {
+            let error = decode_claims(Ok("token".to_owned()), |_| Err(kind.into())).unwrap_err();
+
+            assert_eq!(error_kind(error), "auth_invalid_claims");
+        }
+    }
+}
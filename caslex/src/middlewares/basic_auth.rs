@@ -0,0 +1,152 @@
+//! Contains HTTP Basic auth middleware.
+//!
+//! Requests without `Authorization: Basic` header or with wrong credentials receive `401` error
+//! response with `unauthorized` kind and `WWW-Authenticate` challenge. Credentials are compared
+//! in constant time.
+//!
+//! The server protects the metrics listener with it when `METRICS_BASIC_AUTH_USER` and
+//! `METRICS_BASIC_AUTH_PASSWORD` env variables are set, the middleware can be applied to a
+//! separate router too.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use axum::middleware;
+//! use caslex::middlewares::basic_auth::{BasicAuth, basic_auth_handler};
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! let auth = Arc::new(BasicAuth::new("admin", "secret"));
+//! let router: OpenApiRouter =
+//!     OpenApiRouter::new().layer(middleware::from_fn_with_state(auth, basic_auth_handler));
+//! ```
+
+use std::{hint::black_box, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::headers::{Authorization, HeaderMapExt, authorization::Basic};
+use http::{HeaderValue, StatusCode, header::WWW_AUTHENTICATE};
+
+use crate::errors::error_response;
+
+const CHALLENGE: HeaderValue =
+    HeaderValue::from_static("Basic realm=\"restricted\", charset=\"UTF-8\"");
+
+/// Define expected Basic auth credentials.
+pub struct BasicAuth {
+    user: String,
+    password: String,
+}
+
+impl BasicAuth {
+    /// Creates credentials requests are checked against.
+    pub fn new(user: impl Into<String>, password: impl Into<String>) -> Self {
+        BasicAuth {
+            user: user.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Returns true if credentials match, both are compared to avoid leaking which one is wrong.
+    fn verify(&self, user: &str, password: &str) -> bool {
+        let user_matches = constant_time_eq(self.user.as_bytes(), user.as_bytes());
+        let password_matches = constant_time_eq(self.password.as_bytes(), password.as_bytes());
+
+        user_matches & password_matches
+    }
+}
+
+/// Compares byte strings in time depending on the expected length only.
+fn constant_time_eq(expected: &[u8], actual: &[u8]) -> bool {
+    let mut diff = u8::from(expected.len() != actual.len());
+    for (i, byte) in expected.iter().enumerate() {
+        diff |= byte ^ actual.get(i).copied().unwrap_or_default();
+    }
+
+    black_box(diff) == 0
+}
+
+/// Rejects requests without valid Basic auth credentials.
+pub async fn basic_auth_handler(
+    State(auth): State<Arc<BasicAuth>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let authorized = req
+        .headers()
+        .typed_get::<Authorization<Basic>>()
+        .is_some_and(|credentials| auth.verify(credentials.username(), credentials.password()));
+
+    if !authorized {
+        let mut response = error_response(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "invalid or missing credentials",
+        );
+        response.headers_mut().insert(WWW_AUTHENTICATE, CHALLENGE);
+        return response;
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn byte_strings_are_compared() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+        assert!(!constant_time_eq(b"secret", b"secre"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+
+    async fn call(credentials: Option<(&str, &str)>) -> Response {
+        let router = Router::new()
+            .route("/metrics", get(|| async { "metrics" }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(BasicAuth::new("admin", "secret")),
+                basic_auth_handler,
+            ));
+
+        let mut request = Request::get("/metrics").body(Body::empty()).unwrap();
+        if let Some((user, password)) = credentials {
+            request
+                .headers_mut()
+                .typed_insert(Authorization::basic(user, password));
+        }
+
+        router.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn correct_credentials_are_accepted() {
+        let response = call(Some(("admin", "secret"))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn wrong_or_missing_credentials_are_challenged() {
+        for credentials in [Some(("admin", "wrong")), Some(("root", "secret")), None] {
+            let response = call(credentials).await;
+
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "{credentials:?}"
+            );
+            assert_eq!(response.headers()[WWW_AUTHENTICATE], CHALLENGE);
+        }
+    }
+}
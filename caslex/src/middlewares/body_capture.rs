@@ -0,0 +1,199 @@
+//! Contains body capture middleware.
+//!
+//! Records request and response bodies as `http.request_body` and `http.response_body` fields of
+//! the request trace span, which is handy for debugging webhook integrations. Only `text/*` and
+//! JSON bodies are recorded, bodies longer than the max size are truncated. Binary, streaming and
+//! bodies larger than 1MiB aren't buffered and recorded as `<skipped: ...>` marker.
+//!
+//! The capture is disabled by default, the server enables it for all routes with
+//! `SERVER_TRACE_BODY_ENABLED` env variable, the middleware can be applied to separate routes too.
+//! Captured bodies may contain sensitive data, don't enable it for all routes in production.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use axum::{middleware, routing::post};
+//! use caslex::middlewares::body_capture::{BodyCapture, body_capture_handler};
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! async fn webhook_handler(body: String) {}
+//!
+//! // capture up to 4KiB of webhook bodies
+//! let router: OpenApiRouter = OpenApiRouter::new().route(
+//!     "/webhook",
+//!     post(webhook_handler).layer(middleware::from_fn_with_state(
+//!         BodyCapture::new(4096),
+//!         body_capture_handler,
+//!     )),
+//! );
+//! ```
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::{HeaderMap, StatusCode, header::CONTENT_TYPE};
+use tracing::Span;
+
+use crate::errors::error_response;
+
+/// Bodies larger than this size aren't buffered.
+const MAX_BUFFERED_SIZE: u64 = 1 << 20;
+
+/// Define body capture settings.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyCapture {
+    max_size: usize,
+}
+
+impl BodyCapture {
+    /// Creates settings recording up to `max_size` bytes of a body.
+    pub fn new(max_size: usize) -> Self {
+        BodyCapture { max_size }
+    }
+
+    /// Records the body in the span field, returns the body to pass further.
+    async fn record(
+        &self,
+        span: &Span,
+        field: &'static str,
+        headers: &HeaderMap,
+        body: Body,
+    ) -> Result<Body, axum::Error> {
+        if body.size_hint().exact() == Some(0) {
+            return Ok(body);
+        }
+        if !is_textual(headers) {
+            span.record(field, "<skipped: binary>");
+            return Ok(body);
+        }
+
+        match body.size_hint().exact() {
+            Some(size) if size <= MAX_BUFFERED_SIZE => {}
+            Some(_) => {
+                span.record(field, "<skipped: too large>");
+                return Ok(body);
+            }
+            None => {
+                span.record(field, "<skipped: streaming>");
+                return Ok(body);
+            }
+        }
+
+        let bytes = axum::body::to_bytes(body, usize::MAX).await?;
+        span.record(field, self.truncate(&bytes));
+
+        Ok(Body::from(bytes))
+    }
+
+    fn truncate(&self, bytes: &Bytes) -> String {
+        match bytes.get(..self.max_size) {
+            Some(head) if head.len() < bytes.len() => {
+                format!("{}...<truncated>", String::from_utf8_lossy(head))
+            }
+            _ => String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+}
+
+/// Records request and response bodies in the current span.
+pub async fn body_capture_handler(
+    State(capture): State<BodyCapture>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let span = Span::current();
+
+    let (parts, body) = req.into_parts();
+    let body = match capture
+        .record(&span, "http.request_body", &parts.headers, body)
+        .await
+    {
+        Ok(body) => body,
+        Err(e) => {
+            return error_response(StatusCode::BAD_REQUEST, "invalid_request", &e.to_string());
+        }
+    };
+
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let (parts, body) = response.into_parts();
+    match capture
+        .record(&span, "http.response_body", &parts.headers, body)
+        .await
+    {
+        Ok(body) => Response::from_parts(parts, body),
+        Err(e) => {
+            tracing::warn!("failed to read response body: {e}");
+            Response::from_parts(parts, Body::empty())
+        }
+    }
+}
+
+fn is_textual(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    mime.starts_with("text/") || mime == "application/json" || mime.ends_with("+json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(content_type: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn only_text_and_json_bodies_are_textual() {
+        assert!(is_textual(&headers("application/json; charset=utf-8")));
+        assert!(is_textual(&headers("application/problem+json")));
+        assert!(is_textual(&headers("Text/Plain")));
+        assert!(!is_textual(&headers("application/octet-stream")));
+        assert!(!is_textual(&headers("image/png")));
+        assert!(!is_textual(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn long_body_is_truncated() {
+        let capture = BodyCapture::new(4);
+
+        assert_eq!(
+            capture.truncate(&Bytes::from("abcdef")),
+            "abcd...<truncated>"
+        );
+        assert_eq!(capture.truncate(&Bytes::from("abcd")), "abcd");
+        assert_eq!(capture.truncate(&Bytes::from("ab")), "ab");
+    }
+
+    #[tokio::test]
+    async fn skipped_body_is_passed_unread() {
+        let capture = BodyCapture::new(16);
+        let span = Span::none();
+
+        let body = capture
+            .record(
+                &span,
+                "http.request_body",
+                &headers("image/png"),
+                Body::from("png"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(axum::body::to_bytes(body, usize::MAX).await.unwrap(), "png");
+    }
+}
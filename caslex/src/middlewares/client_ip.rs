@@ -0,0 +1,195 @@
+//! Contains client IP extractor.
+//!
+//! The client IP is taken from the peer address of the connection. Set `TRUST_PROXY_HEADERS=true`
+//! env variable when the server is behind a reverse proxy, then the left-most address of
+//! `X-Forwarded-For` or `Forwarded` header is used, `X-Forwarded-For` takes precedence when both
+//! are present. Don't enable it for servers reachable directly, clients can spoof these headers.
+//!
+//! The client IP is recorded as `client.ip` field of the request trace span.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use caslex::middlewares::client_ip::ClientIp;
+//!
+//! async fn audit_handler(ClientIp(ip): ClientIp) {
+//!     tracing::info!("request from {ip}");
+//! }
+//! ```
+
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::LazyLock,
+};
+
+use anyhow::anyhow;
+use axum::extract::ConnectInfo;
+use axum_core::extract::FromRequestParts;
+use http::{Extensions, HeaderMap, header::FORWARDED, request::Parts};
+
+use crate::errors::DefaultError;
+
+static X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+static TRUST_PROXY_HEADERS: LazyLock<bool> = LazyLock::new(|| {
+    env::var("TRUST_PROXY_HEADERS")
+        .map(|v| v.parse().expect("Invalid TRUST_PROXY_HEADERS"))
+        .unwrap_or(false)
+});
+
+/// Define client IP extractor.
+///
+/// Rejects with internal error when the server isn't run with connect info and proxy headers are
+/// absent or untrusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = DefaultError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        resolve(&parts.headers, &parts.extensions)
+            .map(ClientIp)
+            .ok_or_else(|| DefaultError::Other(anyhow!("client ip is unavailable")))
+    }
+}
+
+/// Returns client IP of the request, see the module docs for the resolution order.
+pub fn client_ip<B>(req: &http::Request<B>) -> Option<IpAddr> {
+    resolve(req.headers(), req.extensions())
+}
+
+fn resolve(headers: &HeaderMap, extensions: &Extensions) -> Option<IpAddr> {
+    resolve_with(headers, extensions, *TRUST_PROXY_HEADERS)
+}
+
+fn resolve_with(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trust_proxy_headers: bool,
+) -> Option<IpAddr> {
+    if trust_proxy_headers && let Some(ip) = forwarded_ip(headers) {
+        return Some(ip);
+    }
+
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+fn forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    if let Some(value) = headers.get(X_FORWARDED_FOR).and_then(|v| v.to_str().ok()) {
+        return value.split(',').next().and_then(parse_ip);
+    }
+
+    // Forwarded: for=192.0.2.60;proto=http, for="[2001:db8::1]:4711"
+    headers
+        .get(FORWARDED)
+        .and_then(|v| v.to_str().ok())?
+        .split(',')
+        .next()?
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| key.eq_ignore_ascii_case("for"))
+        .and_then(|(_, value)| parse_ip(value.trim_matches('"')))
+}
+
+/// Parses IP address with optional port, IPv6 address with port must be bracketed.
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+
+    value
+        .parse::<IpAddr>()
+        .or_else(|_| value.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| value.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: &str = "10.0.0.1:40000";
+
+    fn resolve_request(
+        headers: &[(&str, &str)],
+        peer: Option<&str>,
+        trust: bool,
+    ) -> Option<IpAddr> {
+        let mut request = http::Request::builder();
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let mut request = request.body(()).unwrap();
+        if let Some(peer) = peer {
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+        }
+
+        resolve_with(request.headers(), request.extensions(), trust)
+    }
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    #[test]
+    fn peer_address_is_used_for_direct_connection() {
+        assert_eq!(resolve_request(&[], Some(PEER), false), ip("10.0.0.1"));
+        assert_eq!(resolve_request(&[], Some(PEER), true), ip("10.0.0.1"));
+        assert_eq!(resolve_request(&[], None, false), None);
+    }
+
+    #[test]
+    fn untrusted_forwarded_headers_are_ignored() {
+        let headers = [
+            ("x-forwarded-for", "203.0.113.7"),
+            ("forwarded", "for=198.51.100.2"),
+        ];
+
+        assert_eq!(resolve_request(&headers, Some(PEER), false), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn left_most_forwarded_address_is_used() {
+        let headers = [("x-forwarded-for", " 203.0.113.7, 10.0.0.2")];
+        assert_eq!(
+            resolve_request(&headers, Some(PEER), true),
+            ip("203.0.113.7")
+        );
+
+        let headers = [(
+            "forwarded",
+            r#"for="[2001:db8::1]:4711";proto=http, for=10.0.0.2"#,
+        )];
+        assert_eq!(resolve_request(&headers, None, true), ip("2001:db8::1"));
+
+        let headers = [("forwarded", "proto=https;For=198.51.100.2:8080")];
+        assert_eq!(resolve_request(&headers, None, true), ip("198.51.100.2"));
+    }
+
+    #[test]
+    fn x_forwarded_for_takes_precedence() {
+        let headers = [
+            ("forwarded", "for=198.51.100.2"),
+            ("x-forwarded-for", "203.0.113.7"),
+        ];
+
+        assert_eq!(
+            resolve_request(&headers, Some(PEER), true),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn invalid_forwarded_address_falls_back_to_peer() {
+        let headers = [("x-forwarded-for", "unknown")];
+
+        assert_eq!(resolve_request(&headers, Some(PEER), true), ip("10.0.0.1"));
+    }
+}
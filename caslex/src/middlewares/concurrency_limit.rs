@@ -0,0 +1,55 @@
+//! Contains concurrency limit middleware.
+//!
+//! Requests exceeding the limit of concurrently handled requests are shed instead of queueing,
+//! they receive `503` error response with `overloaded` kind.
+//!
+//! The server enables it with `SERVER_MAX_CONCURRENT_REQUESTS` env variable, the middleware can be
+//! applied to a separate router too.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use axum::middleware;
+//! use caslex::middlewares::concurrency_limit::concurrency_limit_handler;
+//! use tokio::sync::Semaphore;
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! // handle up to 100 requests at once
+//! let limit = Arc::new(Semaphore::new(100));
+//! let router: OpenApiRouter = OpenApiRouter::new().layer(middleware::from_fn_with_state(
+//!     limit,
+//!     concurrency_limit_handler,
+//! ));
+//! ```
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::StatusCode;
+use tokio::sync::Semaphore;
+
+use crate::errors::error_response;
+
+/// Rejects requests when all semaphore permits are taken, the permit is held until the response
+/// is returned.
+pub async fn concurrency_limit_handler(
+    State(limit): State<Arc<Semaphore>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Ok(_permit) = limit.try_acquire() else {
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "overloaded",
+            "server is overloaded",
+        );
+    };
+
+    next.run(req).await
+}
@@ -0,0 +1,144 @@
+//! Contains request context middleware.
+//!
+//! [`context_layer`] builds a per-request value (e.g. resolved tenant or feature flags) from the
+//! request and stores it in the request extensions before handlers run, [`RequestContext`]
+//! extracts it. Handlers behind routers missing the layer are rejected with internal error.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use axum::routing::get;
+//! use caslex::{
+//!     middlewares::context::{RequestContext, context_layer},
+//!     server::{Config, Server},
+//! };
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! #[derive(Clone)]
+//! struct Tenant(String);
+//!
+//! async fn tenant_handler(RequestContext(Tenant(tenant)): RequestContext<Tenant>) -> String {
+//!     tenant
+//! }
+//!
+//! # async fn run() {
+//! let router: OpenApiRouter = OpenApiRouter::new().route("/tenant", get(tenant_handler));
+//!
+//! let result = Server::new(Config::parse())
+//!     .router(router)
+//!     .layer(context_layer(|req| {
+//!         Tenant(
+//!             req.headers()
+//!                 .get("x-tenant")
+//!                 .and_then(|v| v.to_str().ok())
+//!                 .unwrap_or("default")
+//!                 .to_owned(),
+//!         )
+//!     }))
+//!     .run()
+//!     .await;
+//! # }
+//! ```
+
+use std::any::type_name;
+
+use anyhow::anyhow;
+use axum::extract::{FromRequestParts, Request};
+use http::request::Parts;
+use tower::util::MapRequestLayer;
+
+use crate::errors::DefaultError;
+
+/// Returns layer inserting the output of `factory` into request extensions.
+pub fn context_layer<T, F>(factory: F) -> MapRequestLayer<impl Fn(Request) -> Request + Clone>
+where
+    T: Clone + Send + Sync + 'static,
+    F: Fn(&Request) -> T + Clone + Send + Sync + 'static,
+{
+    MapRequestLayer::new(move |mut req: Request| {
+        let context = factory(&req);
+        req.extensions_mut().insert(context);
+        req
+    })
+}
+
+/// Define extractor of the value inserted by [`context_layer`].
+///
+/// Rejects with internal error when the value is missing.
+#[derive(Debug, Clone)]
+pub struct RequestContext<T>(pub T);
+
+impl<S, T> FromRequestParts<S> for RequestContext<T>
+where
+    S: Send + Sync,
+    T: Clone + Send + Sync + 'static,
+{
+    type Rejection = DefaultError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<T>()
+            .cloned()
+            .map(RequestContext)
+            .ok_or_else(|| {
+                DefaultError::Other(anyhow!("request context `{}` is missing", type_name::<T>()))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, response::Response, routing::get};
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Tenant(String);
+
+    async fn tenant_handler(RequestContext(Tenant(tenant)): RequestContext<Tenant>) -> String {
+        tenant
+    }
+
+    async fn call(router: Router, request: Request) -> Response {
+        router.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn context_is_read_by_handler() {
+        let router = Router::new()
+            .route("/tenant", get(tenant_handler))
+            .layer(context_layer(|req| {
+                Tenant(
+                    req.headers()
+                        .get("x-tenant")
+                        .and_then(|v| v.to_str().ok())
+                        .unwrap_or("default")
+                        .to_owned(),
+                )
+            }));
+
+        let request = Request::get("/tenant")
+            .header("x-tenant", "acme")
+            .body(Body::empty())
+            .unwrap();
+        let response = call(router, request).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "acme");
+    }
+
+    #[tokio::test]
+    async fn missing_context_is_internal_error() {
+        let router = Router::new().route("/tenant", get(tenant_handler));
+
+        let response = call(router, Request::get("/tenant").body(Body::empty()).unwrap()).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
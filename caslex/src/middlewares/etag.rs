@@ -0,0 +1,189 @@
+//! Contains ETag middleware.
+//!
+//! Successful `GET` and `HEAD` responses are buffered and get a strong `ETag` header computed from
+//! the body hash, responses setting `ETag` themselves keep it. Requests with `If-None-Match`
+//! header matching the tag receive `304 Not Modified` without the body.
+//!
+//! Buffering and hashing costs on every response, so the middleware is opt-in per route and
+//! shouldn't wrap streaming responses.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use axum::{middleware, routing::get};
+//! use caslex::middlewares::etag::etag_handler;
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! async fn catalog_handler() -> &'static str {
+//!     "catalog"
+//! }
+//!
+//! let router: OpenApiRouter = OpenApiRouter::new().route(
+//!     "/catalog",
+//!     get(catalog_handler).layer(middleware::from_fn(etag_handler)),
+//! );
+//! ```
+
+use std::hash::{DefaultHasher, Hasher};
+
+use axum::{
+    body::{self, Body},
+    extract::Request,
+    middleware::Next,
+    response::Response,
+};
+use http::{
+    HeaderMap, HeaderValue, Method, StatusCode,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+};
+
+use crate::errors::error_response;
+
+/// Sets `ETag` header and answers matching `If-None-Match` requests with `304`.
+pub async fn etag_handler(req: Request, next: Next) -> Response {
+    if !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return next.run(req).await;
+    }
+
+    let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "unhandled_error",
+                &e.to_string(),
+            );
+        }
+    };
+
+    let etag = match parts.headers.get(ETAG) {
+        Some(etag) => etag.clone(),
+        None => {
+            let etag = body_etag(&bytes);
+            parts.headers.insert(ETAG, etag.clone());
+            etag
+        }
+    };
+
+    if if_none_match.is_some_and(|value| matches_etag(&value, &etag)) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        remove_content_headers(&mut parts.headers);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+fn body_etag(body: &[u8]) -> HeaderValue {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(body);
+
+    HeaderValue::try_from(format!("\"{:016x}\"", hasher.finish()))
+        .expect("hex etag is a valid header value")
+}
+
+/// Returns true if any tag of `If-None-Match` list matches, tags are compared weakly as RFC 9110
+/// requires for this header.
+fn matches_etag(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+    let etag = etag.to_str().map(opaque_tag).unwrap_or_default();
+
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || opaque_tag(tag) == etag)
+}
+
+fn opaque_tag(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+fn remove_content_headers(headers: &mut HeaderMap) {
+    headers.remove(CONTENT_LENGTH);
+    headers.remove(CONTENT_TYPE);
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn router() -> Router {
+        Router::new()
+            .route(
+                "/catalog",
+                get(|| async { "catalog" }).post(|| async { "created" }),
+            )
+            .layer(middleware::from_fn(etag_handler))
+    }
+
+    async fn get_catalog(if_none_match: Option<&HeaderValue>) -> Response {
+        let mut request = Request::get("/catalog").body(Body::empty()).unwrap();
+        if let Some(value) = if_none_match {
+            request.headers_mut().insert(IF_NONE_MATCH, value.clone());
+        }
+
+        router().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn matching_if_none_match_is_not_modified() {
+        let response = get_catalog(None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[ETAG].clone();
+
+        let response = get_catalog(Some(&etag)).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag);
+        assert!(!response.headers().contains_key(CONTENT_TYPE));
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stale_etag_gets_full_response() {
+        let response = get_catalog(Some(&HeaderValue::from_static("\"stale\""))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "catalog");
+    }
+
+    #[tokio::test]
+    async fn non_get_responses_have_no_etag() {
+        let request = Request::post("/catalog").body(Body::empty()).unwrap();
+
+        let response = router().oneshot(request).await.unwrap();
+
+        assert!(!response.headers().contains_key(ETAG));
+    }
+
+    #[test]
+    fn if_none_match_lists_are_compared_weakly() {
+        let etag = HeaderValue::from_static("\"abc\"");
+
+        for value in ["\"abc\"", "W/\"abc\"", "\"x\", \"abc\"", "*"] {
+            assert!(
+                matches_etag(&HeaderValue::from_static(value), &etag),
+                "{value}"
+            );
+        }
+        assert!(!matches_etag(&HeaderValue::from_static("\"abcd\""), &etag));
+    }
+}
@@ -0,0 +1,436 @@
+//! Contains idempotency key middleware.
+//!
+//! `POST`, `PUT`, `PATCH` and `DELETE` requests with `Idempotency-Key` header are executed once
+//! per key within the TTL, the first response is stored and replayed for retries with the same
+//! key, method and path. Replayed responses carry `Idempotent-Replayed: true` header. Retries
+//! arriving while the first request is in flight receive `409` error response with
+//! `idempotency_key_in_use` kind.
+//!
+//! Server error responses aren't stored, the key is released so the request can be retried. The
+//! key is released as well when the request is cancelled before the response is stored, e.g.
+//! the client disconnects.
+//!
+//! Responses are kept by an [`IdempotencyStore`], [`MemoryIdempotencyStore`] serves a single
+//! instance, `RedisIdempotencyStore` is shared by instances and available with `redis` feature.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::{sync::Arc, time::Duration};
+//!
+//! use axum::{middleware, routing::post};
+//! use caslex::middlewares::idempotency::{
+//!     Idempotency, MemoryIdempotencyStore, idempotency_handler,
+//! };
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! async fn charge_handler() -> &'static str {
+//!     "charged"
+//! }
+//!
+//! // retries within a day get the first response
+//! let idempotency = Arc::new(Idempotency::new(
+//!     MemoryIdempotencyStore::default(),
+//!     Duration::from_secs(24 * 60 * 60),
+//! ));
+//! let router: OpenApiRouter = OpenApiRouter::new().route(
+//!     "/charges",
+//!     post(charge_handler).layer(middleware::from_fn_with_state(
+//!         idempotency,
+//!         idempotency_handler,
+//!     )),
+//! );
+//! ```
+
+#[cfg(feature = "redis")]
+pub mod redis;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::{self, Body},
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::{HeaderName, HeaderValue, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::errors::error_response;
+
+const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Max length of idempotency key.
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Entries count after which expired entries are evicted.
+const MAX_EXPIRED_ENTRIES: usize = 10_000;
+
+/// Define response stored for idempotency key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredResponse {
+    /// Response status code.
+    pub status: u16,
+    /// Response headers.
+    pub headers: Vec<(String, Vec<u8>)>,
+    /// Response body.
+    pub body: Vec<u8>,
+}
+
+/// Define state of idempotency key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IdempotencyState {
+    /// Request with the key is being handled.
+    InFlight,
+    /// Request with the key is handled.
+    Completed(StoredResponse),
+}
+
+/// Define storage of idempotency keys.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Marks the key in flight for `ttl` if it's absent, returns the current state otherwise.
+    async fn reserve(&self, key: &str, ttl: Duration) -> anyhow::Result<Option<IdempotencyState>>;
+
+    /// Stores the response of the key for `ttl`.
+    async fn complete(
+        &self,
+        key: &str,
+        response: StoredResponse,
+        ttl: Duration,
+    ) -> anyhow::Result<()>;
+
+    /// Removes the key.
+    async fn release(&self, key: &str) -> anyhow::Result<()>;
+}
+
+/// Define in-memory idempotency store of a single server instance.
+#[derive(Default)]
+pub struct MemoryIdempotencyStore {
+    entries: Mutex<HashMap<String, (IdempotencyState, Instant)>>,
+}
+
+#[async_trait]
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn reserve(&self, key: &str, ttl: Duration) -> anyhow::Result<Option<IdempotencyState>> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+        if entries.len() >= MAX_EXPIRED_ENTRIES {
+            entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+
+        match entries.get(key) {
+            Some((state, expires_at)) if *expires_at > now => Ok(Some(state.clone())),
+            _ => {
+                entries.insert(key.to_owned(), (IdempotencyState::InFlight, now + ttl));
+                Ok(None)
+            }
+        }
+    }
+
+    async fn complete(
+        &self,
+        key: &str,
+        response: StoredResponse,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(
+            key.to_owned(),
+            (IdempotencyState::Completed(response), Instant::now() + ttl),
+        );
+
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> anyhow::Result<()> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(key);
+
+        Ok(())
+    }
+}
+
+/// Define idempotency middleware state.
+pub struct Idempotency {
+    store: Arc<dyn IdempotencyStore>,
+    ttl: Duration,
+}
+
+impl Idempotency {
+    /// Creates middleware state keeping responses in `store` for `ttl`.
+    pub fn new(store: impl IdempotencyStore + 'static, ttl: Duration) -> Self {
+        Idempotency {
+            store: Arc::new(store),
+            ttl,
+        }
+    }
+}
+
+/// Releases the key unless the response is stored, runs on cancelled requests too.
+struct Reservation {
+    store: Arc<dyn IdempotencyStore>,
+    key: Option<String>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            if let Err(e) = store.release(&key).await {
+                tracing::error!("failed to release idempotency key: {e}");
+            }
+        });
+    }
+}
+
+/// Executes requests once per idempotency key and replays the stored response for retries.
+pub async fn idempotency_handler(
+    State(idempotency): State<Arc<Idempotency>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !matches!(
+        *req.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    ) {
+        return next.run(req).await;
+    }
+
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(req).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LENGTH => {
+            format!("{} {} {key}", req.method(), req.uri().path())
+        }
+        _ => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_idempotency_key",
+                &format!("idempotency key must be 1 to {MAX_KEY_LENGTH} visible ascii characters"),
+            );
+        }
+    };
+
+    match idempotency.store.reserve(&key, idempotency.ttl).await {
+        Ok(None) => {}
+        Ok(Some(IdempotencyState::InFlight)) => {
+            return error_response(
+                StatusCode::CONFLICT,
+                "idempotency_key_in_use",
+                "request with the same idempotency key is in progress",
+            );
+        }
+        Ok(Some(IdempotencyState::Completed(stored))) => return replay(stored),
+        Err(e) => {
+            tracing::error!("failed to reserve idempotency key: {e}");
+            return error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "idempotency_unavailable",
+                "idempotency store is unavailable",
+            );
+        }
+    }
+
+    let mut reservation = Reservation {
+        store: idempotency.store.clone(),
+        key: Some(key.clone()),
+    };
+
+    let response = next.run(req).await;
+    if response.status().is_server_error() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "unhandled_error",
+                &e.to_string(),
+            );
+        }
+    };
+
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        headers: parts
+            .headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+            .collect(),
+        body: bytes.to_vec(),
+    };
+
+    match idempotency
+        .store
+        .complete(&key, stored, idempotency.ttl)
+        .await
+    {
+        // the stored response replaces the in-flight mark
+        Ok(()) => reservation.key = None,
+        Err(e) => tracing::error!("failed to store idempotent response: {e}"),
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+
+    let headers = response.headers_mut();
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::from_bytes(&value))
+        {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{Router, middleware, routing::post};
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Counts handled charges, the handler waits for `release` when it's set.
+    #[derive(Clone, Default)]
+    struct Charges {
+        handled: Arc<AtomicUsize>,
+        entered: Arc<Notify>,
+        release: Option<Arc<Notify>>,
+    }
+
+    fn router(charges: Charges, ttl: Duration) -> Router {
+        let idempotency = Arc::new(Idempotency::new(MemoryIdempotencyStore::default(), ttl));
+
+        Router::new()
+            .route(
+                "/charges",
+                post(move || async move {
+                    let charge = charges.handled.fetch_add(1, Ordering::SeqCst) + 1;
+                    charges.entered.notify_one();
+                    if let Some(release) = &charges.release {
+                        release.notified().await;
+                    }
+                    format!("charge {charge}")
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                idempotency,
+                idempotency_handler,
+            ))
+    }
+
+    fn charge_request(key: &str) -> Request {
+        Request::post("/charges")
+            .header(IDEMPOTENCY_KEY_HEADER, key)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn body_text(response: Response) -> String {
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn retry_with_same_key_is_replayed() {
+        let charges = Charges::default();
+        let router = router(charges.clone(), Duration::from_secs(60));
+
+        let first = router.clone().oneshot(charge_request("a")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert!(!first.headers().contains_key(IDEMPOTENT_REPLAYED_HEADER));
+        assert_eq!(body_text(first).await, "charge 1");
+
+        let replayed = router.clone().oneshot(charge_request("a")).await.unwrap();
+        assert_eq!(replayed.status(), StatusCode::OK);
+        assert_eq!(replayed.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+        assert_eq!(body_text(replayed).await, "charge 1");
+
+        let other = router.oneshot(charge_request("b")).await.unwrap();
+        assert_eq!(body_text(other).await, "charge 2");
+        assert_eq!(charges.handled.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_duplicate_is_conflict() {
+        let release = Arc::new(Notify::new());
+        let charges = Charges {
+            release: Some(release.clone()),
+            ..Charges::default()
+        };
+        let router = router(charges.clone(), Duration::from_secs(60));
+
+        let first = tokio::spawn(router.clone().oneshot(charge_request("a")));
+        charges.entered.notified().await;
+
+        let duplicate = router.oneshot(charge_request("a")).await.unwrap();
+        release.notify_one();
+
+        assert_eq!(duplicate.status(), StatusCode::CONFLICT);
+        assert!(
+            body_text(duplicate)
+                .await
+                .contains("idempotency_key_in_use")
+        );
+        let first = first.await.unwrap().unwrap();
+        assert_eq!(body_text(first).await, "charge 1");
+        assert_eq!(charges.handled.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_key_is_executed_again() {
+        let charges = Charges::default();
+        let router = router(charges.clone(), Duration::from_millis(10));
+
+        router.clone().oneshot(charge_request("a")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let response = router.oneshot(charge_request("a")).await.unwrap();
+
+        assert_eq!(body_text(response).await, "charge 2");
+    }
+
+    #[tokio::test]
+    async fn invalid_key_is_rejected() {
+        let router = router(Charges::default(), Duration::from_secs(60));
+
+        let response = router
+            .oneshot(charge_request(&"k".repeat(MAX_KEY_LENGTH + 1)))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(
+            body_text(response)
+                .await
+                .contains("invalid_idempotency_key")
+        );
+    }
+}
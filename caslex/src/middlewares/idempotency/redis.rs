@@ -0,0 +1,128 @@
+//! Contains Redis idempotency store.
+//!
+//! Keys are stored with `idempotency:` prefix and expire with the TTL, so the store is shared by
+//! server instances. Build the pool with [`caslex_extra::storages::redis_pool`].
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use deadpool_redis::{Pool, redis::AsyncCommands};
+
+use super::{IdempotencyState, IdempotencyStore, StoredResponse};
+
+const KEY_PREFIX: &str = "idempotency:";
+
+/// Define idempotency store backed by Redis.
+pub struct RedisIdempotencyStore {
+    pool: Pool,
+}
+
+impl RedisIdempotencyStore {
+    /// Creates store using connections of the pool.
+    pub fn new(pool: Pool) -> Self {
+        RedisIdempotencyStore { pool }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for RedisIdempotencyStore {
+    async fn reserve(&self, key: &str, ttl: Duration) -> anyhow::Result<Option<IdempotencyState>> {
+        let mut conn = self.pool.get().await?;
+        let key = format!("{KEY_PREFIX}{key}");
+        let in_flight = serde_json::to_string(&IdempotencyState::InFlight)?;
+
+        // the key may expire between SET and GET, then it's reserved on the next attempt
+        for _ in 0..2 {
+            let reserved: bool = deadpool_redis::redis::cmd("SET")
+                .arg(&key)
+                .arg(&in_flight)
+                .arg("NX")
+                .arg("PX")
+                .arg(ttl_millis(ttl))
+                .query_async::<_, Option<String>>(&mut conn)
+                .await?
+                .is_some();
+            if reserved {
+                return Ok(None);
+            }
+
+            let state: Option<String> = conn.get(&key).await?;
+            if let Some(state) = state {
+                return Ok(Some(serde_json::from_str(&state)?));
+            }
+        }
+
+        Ok(Some(IdempotencyState::InFlight))
+    }
+
+    async fn complete(
+        &self,
+        key: &str,
+        response: StoredResponse,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+        let state = serde_json::to_string(&IdempotencyState::Completed(response))?;
+
+        conn.pset_ex::<_, _, ()>(format!("{KEY_PREFIX}{key}"), state, ttl_millis(ttl))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+        conn.del::<_, ()>(format!("{KEY_PREFIX}{key}")).await?;
+
+        Ok(())
+    }
+}
+
+fn ttl_millis(ttl: Duration) -> usize {
+    usize::try_from(ttl.as_millis())
+        .unwrap_or(usize::MAX)
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use caslex_extra::storages::redis_pool::{Config, build_pool_from_config};
+    use clap::Parser;
+
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "requires redis, set REDIS_* environment variables"]
+    async fn key_is_reserved_completed_and_released() {
+        let pool = build_pool_from_config(Config::parse_from(["caslex-test"]))
+            .await
+            .unwrap();
+        let store = RedisIdempotencyStore::new(pool);
+        let key = "POST /charges caslex-test";
+        let ttl = Duration::from_secs(60);
+        store.release(key).await.unwrap();
+
+        assert!(store.reserve(key, ttl).await.unwrap().is_none());
+        assert!(matches!(
+            store.reserve(key, ttl).await.unwrap(),
+            Some(IdempotencyState::InFlight)
+        ));
+
+        let response = StoredResponse {
+            status: 201,
+            headers: vec![],
+            body: b"charged".to_vec(),
+        };
+        store.complete(key, response, ttl).await.unwrap();
+        let Some(IdempotencyState::Completed(stored)) = store.reserve(key, ttl).await.unwrap()
+        else {
+            panic!("response is stored");
+        };
+        assert_eq!(stored.status, 201);
+        assert_eq!(stored.body, b"charged");
+
+        store.release(key).await.unwrap();
+        assert!(store.reserve(key, ttl).await.unwrap().is_none());
+        store.release(key).await.unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+//! Contains IP filter middleware.
+//!
+//! Requests are allowed or denied by the client IP matched against CIDR allow and deny lists,
+//! both IPv4 and IPv6 networks are supported and a plain address matches itself only. Deny list
+//! takes precedence, a non-empty allow list admits only the matched clients, empty lists allow
+//! all clients. Denied requests receive `403` error response with `ip_denied` kind.
+//!
+//! The client IP is resolved as [`ClientIp`](super::client_ip::ClientIp), set
+//! `TRUST_PROXY_HEADERS=true` env variable to take it from `X-Forwarded-For` header behind a
+//! reverse proxy.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use axum::middleware;
+//! use caslex::middlewares::ip_filter::{IpFilter, ip_filter_handler};
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! // admin endpoints are reachable from the internal networks only
+//! let filter = Arc::new(IpFilter::new(["10.0.0.0/8", "fd00::/8"], ["10.0.13.0/24"]).unwrap());
+//! let admin_router: OpenApiRouter =
+//!     OpenApiRouter::new().layer(middleware::from_fn_with_state(filter, ip_filter_handler));
+//! ```
+
+use std::{net::IpAddr, sync::Arc};
+
+use anyhow::Context;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::StatusCode;
+use ipnet::IpNet;
+
+use crate::{errors::error_response, middlewares::client_ip::client_ip};
+
+/// Define CIDR allow and deny lists.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl IpFilter {
+    /// Creates filter from CIDR networks or plain addresses, fails on invalid entries.
+    pub fn new(
+        allow: impl IntoIterator<Item = impl AsRef<str>>,
+        deny: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> anyhow::Result<Self> {
+        Ok(IpFilter {
+            allow: parse_networks(allow)?,
+            deny: parse_networks(deny)?,
+        })
+    }
+
+    /// Returns true if the client IP passes the filter.
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of dual-stack listeners come as IPv4-mapped IPv6 addresses
+        let ip = ip.to_canonical();
+
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+
+    fn is_allow_all(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+}
+
+fn parse_networks(
+    networks: impl IntoIterator<Item = impl AsRef<str>>,
+) -> anyhow::Result<Vec<IpNet>> {
+    networks
+        .into_iter()
+        .map(|network| {
+            let network = network.as_ref().trim();
+            network
+                .parse::<IpNet>()
+                .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+                .with_context(|| format!("invalid network {network}"))
+        })
+        .collect()
+}
+
+/// Rejects requests of clients not passing the filter, requests with unknown client IP are
+/// rejected unless the filter allows all clients.
+pub async fn ip_filter_handler(
+    State(filter): State<Arc<IpFilter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let allowed = match client_ip(&req) {
+        Some(ip) => filter.is_allowed(ip),
+        None => filter.is_allow_all(),
+    };
+
+    if !allowed {
+        return error_response(StatusCode::FORBIDDEN, "ip_denied", "ip address is denied");
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{Router, body::Body, extract::ConnectInfo, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn ips_are_matched_against_networks() {
+        let filter =
+            IpFilter::new(["10.0.0.0/8", "fd00::/8", "192.0.2.1"], ["10.0.13.0/24"]).unwrap();
+
+        assert!(filter.is_allowed(ip("10.1.2.3")));
+        assert!(filter.is_allowed(ip("fd12::1")));
+        assert!(filter.is_allowed(ip("192.0.2.1")));
+        assert!(filter.is_allowed(ip("::ffff:10.1.2.3")));
+        assert!(!filter.is_allowed(ip("10.0.13.7")));
+        assert!(!filter.is_allowed(ip("192.0.2.2")));
+        assert!(!filter.is_allowed(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn empty_lists_allow_all() {
+        let filter = IpFilter::default();
+
+        assert!(filter.is_allow_all());
+        assert!(filter.is_allowed(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn invalid_network_is_rejected() {
+        let err = IpFilter::new(["10.0.0.0/33"], Vec::<&str>::new()).unwrap_err();
+
+        assert_eq!(err.to_string(), "invalid network 10.0.0.0/33");
+    }
+
+    async fn status_of(filter: IpFilter, peer: Option<&str>) -> StatusCode {
+        let router = Router::new().route("/admin", get(|| async { "ok" })).layer(
+            middleware::from_fn_with_state(Arc::new(filter), ip_filter_handler),
+        );
+
+        let mut request = Request::get("/admin").body(Body::empty()).unwrap();
+        if let Some(peer) = peer {
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::new(ip(peer), 40000)));
+        }
+
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn clients_out_of_range_are_forbidden() {
+        let filter = || IpFilter::new(["10.0.0.0/8"], Vec::<&str>::new()).unwrap();
+
+        assert_eq!(status_of(filter(), Some("10.0.0.5")).await, StatusCode::OK);
+        assert_eq!(
+            status_of(filter(), Some("198.51.100.1")).await,
+            StatusCode::FORBIDDEN
+        );
+        // unknown client IP passes the allow-all filter only
+        assert_eq!(status_of(filter(), None).await, StatusCode::FORBIDDEN);
+        assert_eq!(status_of(IpFilter::default(), None).await, StatusCode::OK);
+    }
+}
@@ -2,3 +2,13 @@
 
 #[cfg(feature = "auth")]
 pub mod auth;
+pub mod basic_auth;
+pub mod body_capture;
+pub mod client_ip;
+pub mod concurrency_limit;
+pub mod context;
+pub mod etag;
+pub mod idempotency;
+pub mod ip_filter;
+pub mod rate_limit;
+pub mod request_id;
@@ -0,0 +1,189 @@
+//! Contains rate limit middleware.
+//!
+//! Requests are throttled with a token bucket per client, the client is identified by the
+//! configured header value or by the peer IP address. Rejected requests receive `429` error
+//! response with `rate_limited` kind and `Retry-After` header.
+//!
+//! The server enables it with `SERVER_RATE_LIMIT_PER_SECOND` env variable, the middleware can be
+//! applied to a separate router too.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use axum::middleware;
+//! use caslex::middlewares::rate_limit::{RateLimiter, rate_limit_handler};
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! // 10 requests per second with bursts up to 20 requests
+//! let limiter = Arc::new(RateLimiter::new(10, 20, None));
+//! let router: OpenApiRouter =
+//!     OpenApiRouter::new().layer(middleware::from_fn_with_state(limiter, rate_limit_handler));
+//! ```
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::{HeaderName, HeaderValue, StatusCode, header::RETRY_AFTER};
+use tokio::time::Instant;
+
+use crate::errors::error_response;
+
+/// Buckets count after which idle buckets are evicted.
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// Define token bucket rate limiter.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    key_header: Option<HeaderName>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Creates limiter allowing `rate` requests per second with bursts up to `burst` requests,
+    /// clients are identified by `key_header` value if passed and present in request.
+    pub fn new(rate: u32, burst: u32, key_header: Option<HeaderName>) -> Self {
+        RateLimiter {
+            rate: f64::from(rate.max(1)),
+            burst: f64::from(burst.max(1)),
+            key_header,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for the key, returns seconds to wait until the next token on failure.
+    fn acquire(&self, key: String) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_IDLE_BUCKETS {
+            let full_after = self.burst / self.rate;
+            buckets.retain(|_, b| now.duration_since(b.updated).as_secs_f64() < full_after);
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = elapsed.mul_add(self.rate, bucket.tokens).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(((1.0 - bucket.tokens) / self.rate).ceil() as u64)
+    }
+
+    fn key(&self, req: &Request) -> String {
+        if let Some(value) = self
+            .key_header
+            .as_ref()
+            .and_then(|name| req.headers().get(name))
+            .and_then(|v| v.to_str().ok())
+        {
+            return value.to_owned();
+        }
+
+        match req.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => addr.ip().to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Rejects requests exceeding the limiter rate.
+pub async fn rate_limit_handler(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let retry_after = match limiter.acquire(limiter.key(&req)) {
+        Ok(()) => return next.run(req).await,
+        Err(retry_after) => retry_after,
+    };
+
+    let mut response = error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        "rate_limited",
+        "too many requests",
+    );
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(retry_after.max(1)));
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const CLIENT_HEADER: HeaderName = HeaderName::from_static("x-client-id");
+
+    fn limited_router(rate: u32, burst: u32) -> Router {
+        let limiter = Arc::new(RateLimiter::new(rate, burst, Some(CLIENT_HEADER)));
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(limiter, rate_limit_handler))
+    }
+
+    async fn call(router: &Router, client: &str) -> Response {
+        let request = Request::get("/")
+            .header(CLIENT_HEADER, client)
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_burst_are_rejected() {
+        let router = limited_router(1, 2);
+
+        assert_eq!(call(&router, "a").await.status(), StatusCode::OK);
+        assert_eq!(call(&router, "a").await.status(), StatusCode::OK);
+
+        let response = call(&router, "a").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "1");
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["kind"], "rate_limited");
+    }
+
+    #[tokio::test]
+    async fn clients_are_limited_separately() {
+        let router = limited_router(1, 1);
+
+        assert_eq!(call(&router, "a").await.status(), StatusCode::OK);
+        assert_eq!(
+            call(&router, "a").await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(call(&router, "b").await.status(), StatusCode::OK);
+    }
+}
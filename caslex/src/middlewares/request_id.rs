@@ -0,0 +1,53 @@
+//! Contains request id middleware.
+//!
+//! The server sets UUID v4 `x-request-id` header for requests coming without it, the id is
+//! recorded in the request span and propagated to the response.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use caslex::middlewares::request_id::RequestId;
+//!
+//! async fn handler(RequestId(request_id): RequestId) -> String {
+//!     request_id
+//! }
+//! ```
+
+use axum::{extract::FromRequestParts, response::Response};
+use http::{HeaderName, StatusCode, request::Parts};
+use tower_http::request_id::{MakeRequestUuid, SetRequestIdLayer};
+
+use crate::errors::error_response;
+
+pub(crate) const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Define request id extractor.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl<S> FromRequestParts<S> for RequestId
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| RequestId(v.to_owned()))
+            .ok_or_else(|| {
+                error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "missing_request_id",
+                    "request id is missing",
+                )
+            })
+    }
+}
+
+/// Sets `x-request-id` header when request lacks it.
+pub(crate) fn set_request_id_layer() -> SetRequestIdLayer<MakeRequestUuid> {
+    SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid)
+}
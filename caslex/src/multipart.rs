@@ -0,0 +1,333 @@
+//! Contains multipart upload extractor.
+//!
+//! [`Multipart`] streams `multipart/form-data` fields and enforces per-field and total size
+//! limits while reading chunks, nothing is buffered unless [`Field::bytes`] or [`Field::text`]
+//! is called. The limits are taken from [`MultipartLimits`] request extension, set it with
+//! `Extension` layer for a route or call [`Multipart::with_limits`] in the handler. The whole
+//! request body is bounded by `SERVER_MAX_BODY_SIZE` server config as well.
+//!
+//! Fields are read with [`MultipartError`] failures which convert into
+//! [`DefaultError::MultipartRejection`], exceeded limits are rendered as `413` with
+//! `payload_too_large` kind, other failures as `400` with `multipart_rejection` kind.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use axum::{Extension, routing::post};
+//! use caslex::{
+//!     errors::DefaultError,
+//!     multipart::{Multipart, MultipartLimits},
+//! };
+//! use utoipa_axum::router::OpenApiRouter;
+//!
+//! async fn upload_handler(mut multipart: Multipart) -> Result<String, DefaultError> {
+//!     let mut uploaded = 0;
+//!     while let Some(mut field) = multipart.next_field().await? {
+//!         while let Some(chunk) = field.chunk().await? {
+//!             uploaded += chunk.len();
+//!         }
+//!     }
+//!     Ok(format!("uploaded {uploaded} bytes"))
+//! }
+//!
+//! // up to 1MiB per file and 4MiB per request
+//! let router: OpenApiRouter = OpenApiRouter::new().route(
+//!     "/upload",
+//!     post(upload_handler).layer(Extension(MultipartLimits::new(1 << 20, 4 << 20))),
+//! );
+//! ```
+
+use std::string::FromUtf8Error;
+
+use axum::{
+    body::Bytes,
+    extract::{
+        FromRequest, Request,
+        multipart::{self, MultipartRejection},
+    },
+};
+use http::{HeaderMap, StatusCode};
+use thiserror::Error;
+
+use crate::errors::DefaultError;
+
+/// Default per-field size limit.
+pub const DEFAULT_FIELD_LIMIT: usize = 1 << 20;
+
+/// Define multipart upload failure.
+#[derive(Error, Debug)]
+pub enum MultipartError {
+    /// Request isn't a valid `multipart/form-data` request.
+    #[error(transparent)]
+    Rejection(#[from] MultipartRejection),
+
+    /// Multipart body can't be parsed or read.
+    #[error(transparent)]
+    Stream(#[from] multipart::MultipartError),
+
+    /// Field is larger than the per-field limit.
+    #[error("field `{name}` exceeds limit of {limit} bytes")]
+    FieldTooLarge { name: String, limit: usize },
+
+    /// Fields are larger than the total limit.
+    #[error("multipart body exceeds limit of {limit} bytes")]
+    TooLarge { limit: usize },
+
+    /// Field read as text isn't valid UTF-8.
+    #[error("field isn't valid utf-8: {0}")]
+    InvalidText(#[from] FromUtf8Error),
+}
+
+impl MultipartError {
+    /// Returns status code of the failure.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MultipartError::Rejection(rejection) => rejection.status(),
+            MultipartError::Stream(e) => e.status(),
+            MultipartError::FieldTooLarge { .. } | MultipartError::TooLarge { .. } => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            MultipartError::InvalidText(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Define multipart size limits.
+#[derive(Debug, Clone, Copy)]
+pub struct MultipartLimits {
+    field_limit: usize,
+    total_limit: usize,
+}
+
+impl MultipartLimits {
+    /// Creates limits of a single field and of all fields in bytes.
+    pub fn new(field_limit: usize, total_limit: usize) -> Self {
+        MultipartLimits {
+            field_limit,
+            total_limit,
+        }
+    }
+}
+
+impl Default for MultipartLimits {
+    /// Limits fields to [`DEFAULT_FIELD_LIMIT`], the total size is bounded by the server body
+    /// limit only.
+    fn default() -> Self {
+        MultipartLimits::new(DEFAULT_FIELD_LIMIT, usize::MAX)
+    }
+}
+
+/// Define `multipart/form-data` extractor enforcing [`MultipartLimits`].
+pub struct Multipart {
+    inner: multipart::Multipart,
+    limits: MultipartLimits,
+    total_size: usize,
+}
+
+impl<S> FromRequest<S> for Multipart
+where
+    S: Send + Sync,
+{
+    type Rejection = DefaultError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let limits = req
+            .extensions()
+            .get::<MultipartLimits>()
+            .copied()
+            .unwrap_or_default();
+
+        let inner = multipart::Multipart::from_request(req, state)
+            .await
+            .map_err(MultipartError::from)?;
+
+        Ok(Multipart {
+            inner,
+            limits,
+            total_size: 0,
+        })
+    }
+}
+
+impl Multipart {
+    /// Replaces limits taken from the request.
+    pub fn with_limits(mut self, limits: MultipartLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Yields the next field if available, the previous field is skipped when it isn't read.
+    pub async fn next_field(&mut self) -> Result<Option<Field<'_>>, MultipartError> {
+        let field = self.inner.next_field().await?;
+
+        Ok(field.map(|inner| Field {
+            inner,
+            limits: self.limits,
+            size: 0,
+            total_size: &mut self.total_size,
+        }))
+    }
+}
+
+/// Define single field of multipart upload.
+pub struct Field<'a> {
+    inner: multipart::Field<'a>,
+    limits: MultipartLimits,
+    size: usize,
+    total_size: &'a mut usize,
+}
+
+impl Field<'_> {
+    /// Returns field name from `Content-Disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// Returns file name from `Content-Disposition` header.
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    /// Returns field content type.
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type()
+    }
+
+    /// Returns field headers.
+    pub fn headers(&self) -> &HeaderMap {
+        self.inner.headers()
+    }
+
+    /// Streams the next chunk of the field, fails once a limit is exceeded.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, MultipartError> {
+        let Some(chunk) = self.inner.chunk().await? else {
+            return Ok(None);
+        };
+
+        self.size = self.size.saturating_add(chunk.len());
+        *self.total_size = self.total_size.saturating_add(chunk.len());
+
+        if self.size > self.limits.field_limit {
+            return Err(MultipartError::FieldTooLarge {
+                name: self.name().unwrap_or_default().to_owned(),
+                limit: self.limits.field_limit,
+            });
+        }
+        if *self.total_size > self.limits.total_limit {
+            return Err(MultipartError::TooLarge {
+                limit: self.limits.total_limit,
+            });
+        }
+
+        Ok(Some(chunk))
+    }
+
+    /// Reads the whole field into memory.
+    pub async fn bytes(mut self) -> Result<Bytes, MultipartError> {
+        let mut buf = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            buf.extend_from_slice(&chunk);
+        }
+
+        Ok(Bytes::from(buf))
+    }
+
+    /// Reads the whole field into memory as UTF-8 text.
+    pub async fn text(self) -> Result<String, MultipartError> {
+        let bytes = self.bytes().await?;
+
+        Ok(String::from_utf8(bytes.into())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Extension, Router, body::Body, response::Response, routing::post};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn upload_handler(mut multipart: Multipart) -> Result<String, DefaultError> {
+        let mut uploaded = Vec::new();
+        while let Some(field) = multipart.next_field().await? {
+            let name = field.file_name().unwrap_or_default().to_owned();
+            let text = field.text().await?;
+            uploaded.push(format!("{name}={text}"));
+        }
+        Ok(uploaded.join(","))
+    }
+
+    /// Uploads files as `(file name, content)` pairs with the limits.
+    async fn upload(limits: MultipartLimits, files: &[(&str, &str)]) -> Response {
+        let mut body = String::new();
+        for (file_name, content) in files {
+            body.push_str(&format!(
+                "--BOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; \
+                 filename=\"{file_name}\"\r\nContent-Type: text/plain\r\n\r\n{content}\r\n"
+            ));
+        }
+        body.push_str("--BOUNDARY--\r\n");
+
+        let router = Router::new()
+            .route("/upload", post(upload_handler))
+            .layer(Extension(limits));
+        let request = Request::post("/upload")
+            .header(
+                http::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=BOUNDARY",
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        router.oneshot(request).await.unwrap()
+    }
+
+    async fn body_text(response: Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn small_files_are_uploaded() {
+        let response = upload(
+            MultipartLimits::new(16, 32),
+            &[("a.txt", "hello"), ("b.txt", "world")],
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, "a.txt=hello,b.txt=world");
+    }
+
+    #[tokio::test]
+    async fn file_over_field_limit_is_rejected() {
+        let response = upload(MultipartLimits::new(4, 32), &[("a.txt", "hello")]).await;
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(body_text(response).await.contains("payload_too_large"));
+    }
+
+    #[tokio::test]
+    async fn files_over_total_limit_are_rejected() {
+        let response = upload(
+            MultipartLimits::new(16, 8),
+            &[("a.txt", "hello"), ("b.txt", "world")],
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn non_multipart_request_is_rejected() {
+        let router = Router::new().route("/upload", post(upload_handler));
+        let request = Request::post("/upload").body(Body::from("hello")).unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(body_text(response).await.contains("multipart_rejection"));
+    }
+}
@@ -1,35 +1,80 @@
 //! Contains HTTP server.
 
 use std::{
-    any::Any, borrow::Cow, fmt::Display, iter::once, net::SocketAddr, sync::LazyLock,
+    any::Any,
+    borrow::Cow,
+    convert::Infallible,
+    fmt::Display,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
     time::Duration,
 };
 
 use anyhow::anyhow;
 use async_trait::async_trait;
 use axum::{
-    Router,
-    http::{HeaderName, StatusCode, header::AUTHORIZATION},
+    Extension, Json, Router,
+    body::Body,
+    extract::{ConnectInfo, DefaultBodyLimit, Query, Request, State},
+    handler::Handler,
+    http::{HeaderName, HeaderValue, Method, StatusCode, header::AUTHORIZATION},
     middleware,
-    routing::get,
+    response::IntoResponse,
+    routing::{Route, get, put},
 };
 use axum_core::response::Response;
 use bytes::Bytes;
-use clap::Parser;
+use caslex_extra::{
+    closer,
+    hooks::{self, PanicHookOptions},
+    log_level, sampling,
+};
+use clap::{Parser, ValueEnum};
 use http::header;
 use http_body_util::Full;
-use tokio::{signal, time::timeout};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    signal,
+    sync::{Semaphore, watch},
+    task::JoinSet,
+    time::timeout,
+};
 use tokio_util::sync::CancellationToken;
+use tower::{Layer, Service, ServiceExt, util::option_layer};
 use tower_http::{
-    catch_panic::CatchPanicLayer, compression::CompressionLayer,
-    propagate_header::PropagateHeaderLayer, sensitive_headers::SetSensitiveRequestHeadersLayer,
-    timeout::TimeoutLayer,
+    catch_panic::CatchPanicLayer,
+    compression::{
+        CompressionLayer, Predicate,
+        predicate::{NotForContentType, SizeAbove},
+    },
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
+    decompression::RequestDecompressionLayer,
+    limit::RequestBodyLimitLayer,
+    propagate_header::PropagateHeaderLayer,
+    sensitive_headers::SetSensitiveRequestHeadersLayer,
 };
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-    errors::{ErrorInfo, ErrorResponse},
-    metrics, swagger, trace,
+    errors::{self, ErrorFormat, error_body, error_response, log_error, set_error_format},
+    health::{CheckStatus, HealthCheck, HealthChecks},
+    metrics,
+    middlewares::{
+        basic_auth::{self, BasicAuth},
+        body_capture::{self, BodyCapture},
+        concurrency_limit,
+        rate_limit::{self, RateLimiter},
+        request_id::{self, REQUEST_ID_HEADER},
+    },
+    swagger,
+    trace::{self, REDACTED},
 };
 
 /// Define server config.
@@ -41,15 +86,198 @@ pub struct Config {
     /// Server port. Env variable name: `SERVER_PORT`.
     #[arg(long, env = "SERVER_PORT", default_value = "9000")]
     pub port: String,
+    /// Server unix domain socket path, the application server listens on it instead of host and
+    /// port when passed. Env variable name: `SERVER_UDS_PATH`.
+    #[arg(long, env = "SERVER_UDS_PATH")]
+    pub uds_path: Option<PathBuf>,
+    /// Server base path, e.g. `/api/v1`, routes and OpenAPI docs are served under it and OpenAPI
+    /// paths are prefixed with it. Env variable name: `SERVER_BASE_PATH`.
+    #[arg(long, env = "SERVER_BASE_PATH", value_parser = parse_base_path)]
+    pub base_path: Option<String>,
+    /// Server base path toggle of health probes and metrics mounted on the application router,
+    /// they are served without the base path when disabled. Env variable name:
+    /// `SERVER_BASE_PATH_PROBES`.
+    #[arg(long, env = "SERVER_BASE_PATH_PROBES", default_value = "false")]
+    pub base_path_probes: bool,
     /// Server metrics port. Env variable name: `SERVER_METRICS_PORT`.
     #[arg(long, env = "SERVER_METRICS_PORT", default_value = "9007")]
     pub metrics_port: String,
-    /// Server request timeout. Env variable name: `SERVER_REQUEST_TIMEOUT`.
+    /// Server metrics listener toggle, `/metrics` is mounted on the application router instead
+    /// when disabled. Env variable name: `SERVER_METRICS_ENABLED`.
+    #[arg(long, env = "SERVER_METRICS_ENABLED", default_value = "true")]
+    pub metrics_enabled: bool,
+    /// Server metrics listener requirement, the server fails to start when the metrics listener
+    /// can't be bound, otherwise it starts without it. Env variable name:
+    /// `SERVER_METRICS_REQUIRED`.
+    #[arg(long, env = "SERVER_METRICS_REQUIRED", default_value = "false")]
+    pub metrics_required: bool,
+    /// Server metrics endpoint path, the path is excluded from tracing and metrics. Env variable
+    /// name: `SERVER_METRICS_PATH`.
+    #[arg(long, env = "SERVER_METRICS_PATH", default_value = "/metrics")]
+    pub metrics_path: String,
+    /// Server gRPC health check port, the gRPC health server is disabled when omitted. Env
+    /// variable name: `SERVER_GRPC_HEALTH_PORT`.
+    #[cfg(feature = "grpc-health")]
+    #[arg(long, env = "SERVER_GRPC_HEALTH_PORT")]
+    pub grpc_health_port: Option<String>,
+    /// Server request timeout, bounds producing the response head only. Streaming response bodies
+    /// and upgrade requests, e.g. WebSocket, are exempt. Env variable name:
+    /// `SERVER_REQUEST_TIMEOUT`.
     #[arg(long, env = "SERVER_REQUEST_TIMEOUT", default_value = "10s")]
     pub request_timeout: humantime::Duration,
     /// Server OpenAPI docs path. Env variable name: `SERVER_DOCS_URL`.
     #[arg(long, env = "SERVER_DOCS_URL", default_value = "/docs")]
     pub docs_url: String,
+    /// Server OpenAPI docs UI toggle, requests to the docs path get `404` when disabled. Env
+    /// variable name: `SERVER_DOCS_ENABLED`.
+    #[arg(long, env = "SERVER_DOCS_ENABLED", default_value = "true")]
+    pub docs_enabled: bool,
+    /// Server OpenAPI docs UI, one of `scalar`, `swagger-ui` (`swagger-ui` feature), `rapidoc`
+    /// (`rapidoc` feature) or `none`. Env variable name: `SERVER_DOCS_UI`.
+    #[arg(long, env = "SERVER_DOCS_UI", value_enum, default_value_t = DocsUi::Scalar)]
+    pub docs_ui: DocsUi,
+    /// Server raw OpenAPI spec toggle. Env variable name: `SERVER_OPENAPI_JSON_ENABLED`.
+    #[arg(long, env = "SERVER_OPENAPI_JSON_ENABLED", default_value = "true")]
+    pub openapi_json_enabled: bool,
+    /// Server raw OpenAPI spec path, the spec is served as YAML when `Accept` header requests
+    /// YAML. Env variable name: `SERVER_OPENAPI_JSON_PATH`.
+    #[arg(
+        long,
+        env = "SERVER_OPENAPI_JSON_PATH",
+        default_value = "/openapi.json"
+    )]
+    pub openapi_json_path: String,
+    /// Server maximum request body size, plain bytes or with `KiB`, `MiB`, `GiB` suffixes. Env
+    /// variable name: `SERVER_MAX_BODY_SIZE`.
+    #[arg(long, env = "SERVER_MAX_BODY_SIZE", default_value = "2MiB", value_parser = parse_byte_size)]
+    pub max_body_size: usize,
+    /// Server response compression toggle. Env variable name: `SERVER_COMPRESSION_ENABLED`.
+    #[arg(long, env = "SERVER_COMPRESSION_ENABLED", default_value = "true")]
+    pub compression_enabled: bool,
+    /// Server response compression algorithms, comma-separated list of `gzip` and `deflate`. Env
+    /// variable name: `SERVER_COMPRESSION_ALGORITHMS`.
+    #[arg(
+        long,
+        env = "SERVER_COMPRESSION_ALGORITHMS",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "gzip"
+    )]
+    pub compression_algorithms: Vec<CompressionAlgorithm>,
+    /// Server response compression min size, smaller responses are sent uncompressed. Sizes above
+    /// 64KiB are capped. Env variable name: `SERVER_COMPRESSION_MIN_SIZE`.
+    #[arg(long, env = "SERVER_COMPRESSION_MIN_SIZE", default_value = "32B", value_parser = parse_byte_size)]
+    pub compression_min_size: usize,
+    /// Server response content types excluded from compression in addition to images, gRPC and
+    /// SSE, comma-separated list of prefixes, e.g. `application/zip`. Env variable name:
+    /// `SERVER_COMPRESSION_EXCLUDE_CONTENT_TYPES`.
+    #[arg(
+        long,
+        env = "SERVER_COMPRESSION_EXCLUDE_CONTENT_TYPES",
+        value_delimiter = ','
+    )]
+    pub compression_exclude_content_types: Vec<String>,
+    /// Server gzip and deflate request bodies decompression toggle, the body size limit applies to
+    /// decompressed bodies. Encoded bodies are passed to handlers as is when disabled. Env
+    /// variable name: `SERVER_REQUEST_DECOMPRESSION_ENABLED`.
+    #[arg(
+        long,
+        env = "SERVER_REQUEST_DECOMPRESSION_ENABLED",
+        default_value = "false"
+    )]
+    pub request_decompression_enabled: bool,
+    /// Server CORS allowed origins, comma-separated list or `*`. CORS is disabled when empty.
+    /// Env variable name: `SERVER_CORS_ALLOW_ORIGINS`.
+    #[arg(long, env = "SERVER_CORS_ALLOW_ORIGINS", value_delimiter = ',')]
+    pub cors_allow_origins: Vec<HeaderValue>,
+    /// Server CORS allowed methods, comma-separated list or `*`. Env variable name:
+    /// `SERVER_CORS_ALLOW_METHODS`.
+    #[arg(
+        long,
+        env = "SERVER_CORS_ALLOW_METHODS",
+        value_delimiter = ',',
+        default_value = "*"
+    )]
+    pub cors_allow_methods: Vec<Method>,
+    /// Server CORS allowed headers, comma-separated list or `*`. Env variable name:
+    /// `SERVER_CORS_ALLOW_HEADERS`.
+    #[arg(
+        long,
+        env = "SERVER_CORS_ALLOW_HEADERS",
+        value_delimiter = ',',
+        default_value = "*"
+    )]
+    pub cors_allow_headers: Vec<HeaderName>,
+    /// Server graceful shutdown timeout, in-flight requests are dropped after it. Env variable
+    /// name: `SERVER_SHUTDOWN_TIMEOUT`.
+    #[arg(long, env = "SERVER_SHUTDOWN_TIMEOUT", default_value = "30s")]
+    pub shutdown_timeout: humantime::Duration,
+    /// Server route paths excluded from tracing and metrics, comma-separated list, paths match
+    /// with and without the base path. Env variable name: `SERVER_TELEMETRY_EXCLUDE_PATHS`.
+    #[arg(
+        long,
+        env = "SERVER_TELEMETRY_EXCLUDE_PATHS",
+        value_delimiter = ',',
+        default_value = "/liveness,/readiness,/metrics"
+    )]
+    pub telemetry_exclude_paths: Vec<String>,
+    /// Server query params which values are replaced with `***` in traces, comma-separated
+    /// case-insensitive list. Env variable name: `SERVER_TELEMETRY_REDACT_QUERY_PARAMS`.
+    #[arg(
+        long,
+        env = "SERVER_TELEMETRY_REDACT_QUERY_PARAMS",
+        value_delimiter = ',',
+        default_value = "token,access_token,password,secret,api_key"
+    )]
+    pub telemetry_redact_query_params: Vec<String>,
+    /// Server request and response bodies capture in traces toggle, applies to all routes. Env
+    /// variable name: `SERVER_TRACE_BODY_ENABLED`.
+    #[arg(long, env = "SERVER_TRACE_BODY_ENABLED", default_value = "false")]
+    pub trace_body_enabled: bool,
+    /// Server captured body max size, longer bodies are truncated. Env variable name:
+    /// `SERVER_TRACE_BODY_MAX_SIZE`.
+    #[arg(long, env = "SERVER_TRACE_BODY_MAX_SIZE", default_value = "4KiB", value_parser = parse_byte_size)]
+    pub trace_body_max_size: usize,
+    /// Server request duration histogram buckets in seconds, comma-separated list of positive
+    /// strictly increasing numbers. Prometheus default buckets are used when empty. Env variable
+    /// name: `SERVER_METRICS_BUCKETS`.
+    #[arg(long, env = "SERVER_METRICS_BUCKETS", value_delimiter = ',')]
+    pub metrics_buckets: Vec<f64>,
+    /// Server rate limit in requests per second for a single client, rate limiting is disabled
+    /// when zero. Env variable name: `SERVER_RATE_LIMIT_PER_SECOND`.
+    #[arg(long, env = "SERVER_RATE_LIMIT_PER_SECOND", default_value = "0")]
+    pub rate_limit_per_second: u32,
+    /// Server rate limit burst size, the rate limit value is used when omitted. Env variable name:
+    /// `SERVER_RATE_LIMIT_BURST`.
+    #[arg(long, env = "SERVER_RATE_LIMIT_BURST")]
+    pub rate_limit_burst: Option<u32>,
+    /// Server rate limit header identifying a client, the client IP address is used when omitted
+    /// or the header is absent. Env variable name: `SERVER_RATE_LIMIT_HEADER`.
+    #[arg(long, env = "SERVER_RATE_LIMIT_HEADER")]
+    pub rate_limit_header: Option<HeaderName>,
+    /// Server max concurrently handled requests, excess requests get `503` instead of queueing.
+    /// Unlimited when zero. Env variable name: `SERVER_MAX_CONCURRENT_REQUESTS`.
+    #[arg(long, env = "SERVER_MAX_CONCURRENT_REQUESTS", default_value = "0")]
+    pub max_concurrent_requests: usize,
+    /// Server error response format. Env variable name: `SERVER_ERROR_FORMAT`.
+    #[arg(long, env = "SERVER_ERROR_FORMAT", value_enum, default_value_t = ErrorFormat::Default)]
+    pub error_format: ErrorFormat,
+    /// Metrics listener Basic auth user, health probes stay public. Metrics are served without
+    /// auth when omitted, `/config` endpoint is served only with auth. Env variable name:
+    /// `METRICS_BASIC_AUTH_USER`.
+    #[arg(
+        long,
+        env = "METRICS_BASIC_AUTH_USER",
+        requires = "metrics_basic_auth_password"
+    )]
+    pub metrics_basic_auth_user: Option<String>,
+    /// Metrics listener Basic auth password. Env variable name: `METRICS_BASIC_AUTH_PASSWORD`.
+    #[arg(
+        long,
+        env = "METRICS_BASIC_AUTH_PASSWORD",
+        requires = "metrics_basic_auth_user"
+    )]
+    pub metrics_basic_auth_password: Option<String>,
 }
 
 impl Config {
@@ -57,6 +285,17 @@ impl Config {
         Config::try_parse().expect("Parsing configuration failed.")
     }
 
+    /// Parses config from TOML file, env variables take precedence over the file values. See
+    /// [`caslex_extra::config`] for the file format.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Config> {
+        caslex_extra::config::from_file(path)
+    }
+
+    /// Parses config from TOML reader, env variables take precedence over the reader values.
+    pub fn from_reader(reader: impl std::io::Read) -> anyhow::Result<Config> {
+        caslex_extra::config::from_reader(reader)
+    }
+
     fn get_addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
@@ -64,6 +303,312 @@ impl Config {
     fn get_metrics_addr(&self) -> String {
         format!("{}:{}", self.host, self.metrics_port)
     }
+
+    fn get_base_path(&self) -> Option<&str> {
+        self.base_path.as_deref().filter(|path| !path.is_empty())
+    }
+
+    fn with_base_path(&self, path: &str) -> String {
+        format!("{}{path}", self.get_base_path().unwrap_or_default())
+    }
+
+    #[cfg(feature = "grpc-health")]
+    fn get_grpc_health_addr(&self) -> Option<String> {
+        self.grpc_health_port
+            .as_ref()
+            .map(|port| format!("{}:{port}", self.host))
+    }
+
+    fn get_cors_layer(&self) -> Option<CorsLayer> {
+        const WILDCARD: &str = "*";
+
+        if self.cors_allow_origins.is_empty() {
+            return None;
+        }
+
+        let origins = if self.cors_allow_origins.iter().any(|v| v == WILDCARD) {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(self.cors_allow_origins.clone())
+        };
+
+        let methods = if self.cors_allow_methods.iter().any(|v| v == WILDCARD) {
+            AllowMethods::any()
+        } else {
+            AllowMethods::list(self.cors_allow_methods.clone())
+        };
+
+        let headers = if self.cors_allow_headers.iter().any(|v| v == WILDCARD) {
+            AllowHeaders::any()
+        } else {
+            AllowHeaders::list(self.cors_allow_headers.clone())
+        };
+
+        Some(
+            CorsLayer::new()
+                .allow_origin(origins)
+                .allow_methods(methods)
+                .allow_headers(headers),
+        )
+    }
+
+    fn get_telemetry_exclude_paths(&self) -> Arc<[String]> {
+        let mut paths = self.telemetry_exclude_paths.clone();
+        // never count metrics scrapes
+        if !paths.contains(&self.metrics_path) {
+            paths.push(self.metrics_path.clone());
+        }
+        if let Some(base_path) = self.get_base_path() {
+            let prefixed: Vec<_> = paths
+                .iter()
+                .map(|path| format!("{base_path}{path}"))
+                .collect();
+            paths.extend(prefixed);
+        }
+        paths.into()
+    }
+
+    fn get_rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+        if self.rate_limit_per_second == 0 {
+            return None;
+        }
+
+        Some(Arc::new(RateLimiter::new(
+            self.rate_limit_per_second,
+            self.rate_limit_burst.unwrap_or(self.rate_limit_per_second),
+            self.rate_limit_header.clone(),
+        )))
+    }
+
+    fn get_compression(&self) -> CompressionOptions {
+        let enabled = |algorithm| {
+            self.compression_enabled && self.compression_algorithms.contains(&algorithm)
+        };
+
+        CompressionOptions {
+            gzip: enabled(CompressionAlgorithm::Gzip),
+            deflate: enabled(CompressionAlgorithm::Deflate),
+            min_size: u16::try_from(self.compression_min_size).unwrap_or(u16::MAX),
+            exclude_content_types: self.compression_exclude_content_types.clone().into(),
+        }
+    }
+
+    fn get_metrics_basic_auth(&self) -> Option<Arc<BasicAuth>> {
+        let user = self.metrics_basic_auth_user.as_ref()?;
+        let password = self.metrics_basic_auth_password.as_ref()?;
+
+        Some(Arc::new(BasicAuth::new(user, password)))
+    }
+
+    /// Returns config as JSON served by `/config` endpoint of the metrics listener, values of
+    /// fields missing in [`CONFIG_SAFE_FIELDS`] are replaced with `***`.
+    fn get_redacted_json(&self) -> serde_json::Value {
+        // destructured, so new fields aren't exposed unnoticed
+        let Config {
+            host,
+            port,
+            uds_path,
+            base_path,
+            base_path_probes,
+            metrics_port,
+            metrics_enabled,
+            metrics_required,
+            metrics_path,
+            #[cfg(feature = "grpc-health")]
+            grpc_health_port,
+            request_timeout,
+            docs_url,
+            docs_enabled,
+            docs_ui,
+            openapi_json_enabled,
+            openapi_json_path,
+            max_body_size,
+            compression_enabled,
+            compression_algorithms,
+            compression_min_size,
+            compression_exclude_content_types,
+            request_decompression_enabled,
+            cors_allow_origins,
+            cors_allow_methods,
+            cors_allow_headers,
+            shutdown_timeout,
+            telemetry_exclude_paths,
+            telemetry_redact_query_params,
+            trace_body_enabled,
+            trace_body_max_size,
+            metrics_buckets,
+            rate_limit_per_second,
+            rate_limit_burst,
+            rate_limit_header,
+            max_concurrent_requests,
+            error_format,
+            metrics_basic_auth_user,
+            metrics_basic_auth_password,
+        } = self;
+        #[cfg(feature = "grpc-health")]
+        let grpc_health_port = grpc_health_port.as_deref();
+        #[cfg(not(feature = "grpc-health"))]
+        let grpc_health_port: Option<&str> = None;
+
+        let config = json!({
+            "host": host,
+            "port": port,
+            "uds_path": uds_path.as_ref().map(|path| path.display().to_string()),
+            "base_path": base_path,
+            "base_path_probes": base_path_probes,
+            "metrics_port": metrics_port,
+            "metrics_enabled": metrics_enabled,
+            "metrics_required": metrics_required,
+            "metrics_path": metrics_path,
+            "grpc_health_port": grpc_health_port,
+            "request_timeout": request_timeout.to_string(),
+            "docs_url": docs_url,
+            "docs_enabled": docs_enabled,
+            "docs_ui": value_enum_name(docs_ui),
+            "openapi_json_enabled": openapi_json_enabled,
+            "openapi_json_path": openapi_json_path,
+            "max_body_size": max_body_size,
+            "compression_enabled": compression_enabled,
+            "compression_algorithms": compression_algorithms
+                .iter()
+                .map(value_enum_name)
+                .collect::<Vec<_>>(),
+            "compression_min_size": compression_min_size,
+            "compression_exclude_content_types": compression_exclude_content_types,
+            "request_decompression_enabled": request_decompression_enabled,
+            "cors_allow_origins": cors_allow_origins
+                .iter()
+                .map(|origin| String::from_utf8_lossy(origin.as_bytes()))
+                .collect::<Vec<_>>(),
+            "cors_allow_methods": cors_allow_methods
+                .iter()
+                .map(Method::as_str)
+                .collect::<Vec<_>>(),
+            "cors_allow_headers": cors_allow_headers
+                .iter()
+                .map(HeaderName::as_str)
+                .collect::<Vec<_>>(),
+            "shutdown_timeout": shutdown_timeout.to_string(),
+            "telemetry_exclude_paths": telemetry_exclude_paths,
+            "telemetry_redact_query_params": telemetry_redact_query_params,
+            "trace_body_enabled": trace_body_enabled,
+            "trace_body_max_size": trace_body_max_size,
+            "metrics_buckets": metrics_buckets,
+            "rate_limit_per_second": rate_limit_per_second,
+            "rate_limit_burst": rate_limit_burst,
+            "rate_limit_header": rate_limit_header.as_ref().map(HeaderName::as_str),
+            "max_concurrent_requests": max_concurrent_requests,
+            "error_format": value_enum_name(error_format),
+            "metrics_basic_auth_user": metrics_basic_auth_user,
+            "metrics_basic_auth_password": metrics_basic_auth_password,
+        });
+
+        redact_config(config)
+    }
+
+    fn get_concurrency_limit(&self) -> Option<Arc<Semaphore>> {
+        (self.max_concurrent_requests > 0)
+            .then(|| Arc::new(Semaphore::new(self.max_concurrent_requests)))
+    }
+
+    fn get_body_capture(&self) -> Option<BodyCapture> {
+        self.trace_body_enabled
+            .then(|| BodyCapture::new(self.trace_body_max_size))
+    }
+}
+
+/// Returns base path without trailing slash, empty for the root path.
+fn parse_base_path(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if !value.starts_with('/') {
+        return Err("base path must start with /".to_owned());
+    }
+
+    Ok(value.trim_end_matches('/').to_owned())
+}
+
+fn parse_byte_size(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => {
+            let (number, unit) = value.split_at(idx);
+            let multiplier = match unit.trim() {
+                "B" => 1,
+                "KB" => 1_000,
+                "KiB" => 1 << 10,
+                "MB" => 1_000_000,
+                "MiB" => 1 << 20,
+                "GB" => 1_000_000_000,
+                "GiB" => 1 << 30,
+                unit => return Err(format!("unknown size unit: {unit}")),
+            };
+            (number, multiplier)
+        }
+        None => (value, 1),
+    };
+
+    number
+        .parse::<usize>()
+        .map_err(|e| format!("invalid size: {e}"))?
+        .checked_mul(multiplier)
+        .ok_or_else(|| "size is too large".to_owned())
+}
+
+/// Define OpenAPI docs UI mounted at the docs path.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocsUi {
+    /// Scalar API reference.
+    #[default]
+    Scalar,
+    /// Swagger UI.
+    #[cfg(feature = "swagger-ui")]
+    SwaggerUi,
+    /// RapiDoc.
+    #[cfg(feature = "rapidoc")]
+    #[value(name = "rapidoc")]
+    RapiDoc,
+    /// No docs UI, the raw spec route is still served.
+    None,
+}
+
+/// Define response compression algorithm.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    Gzip,
+    Deflate,
+}
+
+/// Define response compression settings.
+#[derive(Debug, Clone)]
+struct CompressionOptions {
+    gzip: bool,
+    deflate: bool,
+    min_size: u16,
+    exclude_content_types: Arc<[String]>,
+}
+
+impl CompressionOptions {
+    fn layer(&self) -> CompressionLayer<impl Predicate + use<>> {
+        let exclude_content_types = self.exclude_content_types.clone();
+        let predicate = SizeAbove::new(self.min_size)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE)
+            .and(move |_, _, headers: &http::HeaderMap, _: &_| {
+                let content_type = headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default();
+                !exclude_content_types
+                    .iter()
+                    .any(|v| content_type.starts_with(v.as_str()))
+            });
+
+        CompressionLayer::new()
+            .gzip(self.gzip)
+            .deflate(self.deflate)
+            .compress_when(predicate)
+    }
 }
 
 /// Define background process trait.
@@ -71,20 +616,109 @@ impl Config {
 pub trait Process: Send + Sync {
     async fn pre_run(&self) -> anyhow::Result<()>;
     async fn run(&self, token: CancellationToken) -> anyhow::Result<()>;
+
+    /// Restart policy applied when `run` returns error before shutdown.
+    fn restart_policy(&self) -> RestartPolicy {
+        RestartPolicy::Never
+    }
+
+    /// Process name reported by `/config` endpoint of the metrics listener, the type name by
+    /// default.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Applies pending migrations in `pre_run`, so the server accepts traffic only after the database
+/// schema is up to date. Pre run is limited to 60 seconds.
+#[cfg(feature = "migrations")]
+#[async_trait]
+impl Process for caslex_extra::storages::postgres_migrations::Migrator {
+    async fn pre_run(&self) -> anyhow::Result<()> {
+        self.migrate().await.map(|_| ())
+    }
+
+    async fn run(&self, token: CancellationToken) -> anyhow::Result<()> {
+        token.cancelled().await;
+        Ok(())
+    }
+}
+
+/// Define background process restart policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Never restart failed process.
+    Never,
+    /// Restart failed process at most `max_retries` times, the delay between restarts starts at
+    /// `backoff` and doubles after each restart.
+    OnFailure { max_retries: u32, backoff: Duration },
 }
 
 /// Define HTTP server.
 pub struct Server<'a> {
     addr: String,
+    uds_path: Option<PathBuf>,
+    base_path: Option<String>,
+    base_path_probes: bool,
     metrics_addr: String,
+    metrics_enabled: bool,
+    metrics_required: bool,
+    metrics_path: String,
+    metrics_basic_auth: Option<Arc<BasicAuth>>,
+    #[cfg(feature = "grpc-health")]
+    grpc_health_addr: Option<String>,
+    metrics_on_main_router: bool,
     request_timeout: Duration,
-    docs_url: String,
+    shutdown_timeout: Duration,
+    docs: swagger::DocsOptions,
+    max_body_size: usize,
+    compression: CompressionOptions,
+    request_decompression: bool,
+    cors: Option<CorsLayer>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    concurrency_limit: Option<Arc<Semaphore>>,
+    telemetry_exclude_paths: Arc<[String]>,
+    telemetry_redact_query_params: Arc<[String]>,
+    body_capture: Option<BodyCapture>,
+    health_checks: Vec<(String, Arc<dyn HealthCheck>)>,
+    started: Arc<AtomicBool>,
+    metrics_buckets: Option<Vec<f64>>,
+    layers: Vec<RouterFn>,
+    not_found_handler: Option<RouterFn>,
+    method_not_allowed_handler: Option<RouterFn>,
+    on_reload: Option<Arc<dyn Fn() + Send + Sync>>,
+    on_startup: Mutex<Option<LifecycleHook>>,
+    on_shutdown: Mutex<Option<LifecycleHook>>,
+    bind_state: watch::Sender<BindState>,
+    shutdown: CancellationToken,
     router: Option<OpenApiRouter>,
     processes: Option<&'a Vec<&'static dyn Process>>,
+    config: serde_json::Value,
+}
+
+type RouterFn = Box<dyn Fn(Router) -> Router + Send + Sync>;
+
+type LifecycleHook = Box<dyn FnOnce() + Send>;
+
+/// Define addresses of bound listeners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundAddrs {
+    /// Application server address, `None` when the server listens on unix socket.
+    pub app: Option<SocketAddr>,
+    /// Metrics server address, `None` when the metrics listener is disabled or failed to bind.
+    pub metrics: Option<SocketAddr>,
+}
+
+#[derive(Clone, Copy)]
+enum BindState {
+    Pending,
+    Bound(BoundAddrs),
+    Failed,
 }
 
 macro_rules! server_method {
-    ($name:ident, $ty:ty) => {
+    ($(#[$meta:meta])* $name:ident, $ty:ty) => {
+        $(#[$meta])*
         pub fn $name(mut self, $name: $ty) -> Self {
             self.$name = Some($name);
             self
@@ -97,6 +731,263 @@ enum ServerKind {
     Metrics,
 }
 
+/// Define server with bound listeners, created by [`Server::bind`].
+pub struct BoundServer<'s, 'a> {
+    server: &'s Server<'a>,
+    app_listener: AppListener,
+    metrics_listener: Option<tokio::net::TcpListener>,
+    addrs: BoundAddrs,
+    shutdown: CancellationToken,
+}
+
+impl BoundServer<'_, '_> {
+    /// Returns addresses of the bound listeners.
+    pub fn addrs(&self) -> BoundAddrs {
+        self.addrs
+    }
+
+    /// Returns token starting graceful shutdown when cancelled, as the shutdown signal does.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Serves the listeners and runs processes until shutdown, see [`Server::run`].
+    pub async fn serve(self) -> anyhow::Result<()> {
+        const PROCESS_PRE_RUN_TIMEOUT: Duration = Duration::from_secs(60);
+
+        let BoundServer {
+            server,
+            app_listener,
+            metrics_listener,
+            shutdown,
+            ..
+        } = self;
+
+        // processes are cancelled after the servers are drained, not along with them
+        let processes_shutdown = CancellationToken::new();
+
+        let app_server = async |listener| match listener {
+            AppListener::Tcp(listener) => {
+                server
+                    .serve_tcp(
+                        listener,
+                        server.setup_router(),
+                        ServerKind::Application,
+                        shutdown.clone(),
+                    )
+                    .await
+            }
+            #[cfg(unix)]
+            AppListener::Unix(listener, path) => {
+                server
+                    .serve_unix(
+                        listener,
+                        &path,
+                        server.setup_router(),
+                        ServerKind::Application,
+                        shutdown.clone(),
+                    )
+                    .await
+            }
+        };
+
+        let metrics_server = async |listener| {
+            // metrics are served by the application server when the listener is disabled
+            let Some(listener) = listener else {
+                return Ok(());
+            };
+
+            server
+                .serve_tcp(
+                    listener,
+                    get_metrics_router(
+                        server.get_health_checks(),
+                        &server.metrics_path,
+                        server.metrics_basic_auth.clone(),
+                        server.get_config(),
+                    ),
+                    ServerKind::Metrics,
+                    shutdown.clone(),
+                )
+                .await
+        };
+
+        let grpc_health_server = async {
+            #[cfg(feature = "grpc-health")]
+            if let Some(addr) = server.grpc_health_addr.clone() {
+                return crate::grpc_health::GrpcHealthServer::with_health_checks(
+                    addr,
+                    server.get_health_checks(),
+                )
+                .serve(shutdown.clone())
+                .await;
+            }
+
+            Ok(())
+        };
+
+        let processes = match server.processes {
+            Some(processes) => processes,
+            _ => &vec![],
+        };
+
+        // servers are started along with pre run of processes, readiness fails until it's finished
+        let servers = async {
+            let result = async {
+                call_hook(&server.on_startup);
+
+                tokio::try_join!(
+                    app_server(app_listener),
+                    metrics_server(metrics_listener),
+                    grpc_health_server
+                )
+            }
+            .await
+            .map_err(|e| anyhow!("Failed to bootstrap server. Reason: {:?}", e));
+            // stop the pre run when a server failed
+            shutdown.cancel();
+            result
+        };
+
+        let startup = async {
+            // pre run processes
+            let pre_run = async {
+                let tasks: Vec<_> = processes
+                    .iter()
+                    .map(|p| {
+                        tokio::spawn(timeout(PROCESS_PRE_RUN_TIMEOUT, async {
+                            p.pre_run().await
+                        }))
+                    })
+                    .collect();
+
+                for task in tasks {
+                    match task.await? {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => return Err(anyhow!("error while pre run process: {}", e)),
+                        Err(e) => return Err(anyhow!("error while pre run process: {}", e)),
+                    }
+                }
+
+                Ok(())
+            };
+
+            tokio::select! {
+                result = pre_run => if let Err(e) = result {
+                    // drain the servers started along with the pre run
+                    shutdown.cancel();
+                    return Err(e);
+                },
+                // don't start processes when the server is shut down during the pre run
+                _ = shutdown.cancelled() => return Ok(vec![]),
+            }
+
+            // disable failure in the custom panic hook when there is a panic,
+            // because we can't handle the panic in the panic middleware (exit(1) trouble)
+            hooks::setup_panic_hook_with(PanicHookOptions::default().exit(false));
+
+            server.started.store(true, Ordering::Release);
+
+            // run processes
+            Ok::<_, anyhow::Error>(
+                processes
+                    .iter()
+                    .map(|p| tokio::spawn(supervise_process(*p, processes_shutdown.clone())))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let shutdown_started = async {
+            shutdown.cancelled().await;
+            call_hook(&server.on_shutdown);
+        };
+
+        // servers are drained first, so in-flight requests can still use the processes
+        let (servers_result, mut startup_result, ()) =
+            tokio::join!(servers, startup, shutdown_started);
+
+        // then processes are cancelled
+        processes_shutdown.cancel();
+
+        if let Ok(runnable_tasks) = &mut startup_result {
+            for task in runnable_tasks.drain(..) {
+                match task.await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => tracing::error!("Failed to shutdown processes. Reason: {:?}", e),
+                    Err(e) => tracing::error!("Failed to shutdown processes. Reason: {:?}", e),
+                }
+            }
+        }
+
+        // and closer callbacks are run last
+        closer::cleanup_resources_async().await;
+
+        startup_result?;
+        servers_result.map(|_| ())
+    }
+}
+
+/// Define bound application listener.
+enum AppListener {
+    Tcp(tokio::net::TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, PathBuf),
+}
+
+impl AppListener {
+    fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            AppListener::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            AppListener::Unix(..) => None,
+        }
+    }
+}
+
+async fn bind_tcp(addr: &str, server_kind: ServerKind) -> anyhow::Result<tokio::net::TcpListener> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow!("failed to bind to address: {e}"))?;
+
+    tracing::info!(
+        "listening {server_kind} server on {}",
+        listener.local_addr()?
+    );
+
+    Ok(listener)
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &Path, server_kind: ServerKind) -> anyhow::Result<AppListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // remove socket file left by the previous run, other files are kept to fail on bind
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(path)
+            .map_err(|e| anyhow!("failed to remove stale unix socket: {e}"))?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|e| anyhow!("failed to bind to unix socket: {e}"))?;
+
+    tracing::info!("listening {server_kind} server on unix:{}", path.display());
+
+    Ok(AppListener::Unix(listener, path.to_path_buf()))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &Path, _server_kind: ServerKind) -> anyhow::Result<AppListener> {
+    Err(anyhow!("unix sockets are not supported on this platform"))
+}
+
+/// Calls the hook if it's set and not called yet.
+fn call_hook(hook: &Mutex<Option<LifecycleHook>>) {
+    let hook = hook.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(hook) = hook {
+        hook();
+    }
+}
+
 impl Display for ServerKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
@@ -109,132 +1000,760 @@ impl Display for ServerKind {
 impl<'a> Server<'a> {
     server_method!(router, OpenApiRouter);
     server_method!(processes, &'a Vec<&'static dyn Process>);
+    server_method!(
+        /// Sets request duration histogram buckets in seconds, overrides `SERVER_METRICS_BUCKETS`.
+        /// Buckets must be positive and strictly increasing, otherwise `run` returns error.
+        metrics_buckets,
+        Vec<f64>
+    );
 
     pub fn new(cfg: Config) -> Self {
+        set_error_format(cfg.error_format);
+
         Server {
             addr: cfg.get_addr(),
+            uds_path: cfg.uds_path.clone(),
+            base_path: cfg.get_base_path().map(str::to_owned),
+            base_path_probes: cfg.base_path_probes,
             metrics_addr: cfg.get_metrics_addr(),
+            metrics_enabled: cfg.metrics_enabled,
+            metrics_required: cfg.metrics_required,
+            metrics_path: cfg.metrics_path.clone(),
+            metrics_basic_auth: cfg.get_metrics_basic_auth(),
+            #[cfg(feature = "grpc-health")]
+            grpc_health_addr: cfg.get_grpc_health_addr(),
+            metrics_on_main_router: false,
+            cors: cfg.get_cors_layer(),
+            rate_limiter: cfg.get_rate_limiter(),
+            concurrency_limit: cfg.get_concurrency_limit(),
+            compression: cfg.get_compression(),
+            request_decompression: cfg.request_decompression_enabled,
+            telemetry_exclude_paths: cfg.get_telemetry_exclude_paths(),
+            telemetry_redact_query_params: cfg.telemetry_redact_query_params.clone().into(),
+            body_capture: cfg.get_body_capture(),
             request_timeout: cfg.request_timeout.into(),
-            docs_url: cfg.docs_url,
+            shutdown_timeout: cfg.shutdown_timeout.into(),
+            docs: swagger::DocsOptions {
+                docs_url: cfg.with_base_path(&cfg.docs_url),
+                docs_enabled: cfg.docs_enabled,
+                docs_ui: cfg.docs_ui,
+                openapi_json_enabled: cfg.openapi_json_enabled,
+                openapi_json_path: cfg.with_base_path(&cfg.openapi_json_path),
+                info: None,
+                servers: None,
+                security_schemes: vec![],
+                security: None,
+            },
+            max_body_size: cfg.max_body_size,
+            health_checks: vec![],
+            started: Arc::new(AtomicBool::new(false)),
+            metrics_buckets: (!cfg.metrics_buckets.is_empty()).then(|| cfg.metrics_buckets.clone()),
+            layers: vec![],
+            not_found_handler: None,
+            method_not_allowed_handler: None,
+            on_reload: None,
+            on_startup: Mutex::new(None),
+            on_shutdown: Mutex::new(None),
+            bind_state: watch::Sender::new(BindState::Pending),
+            shutdown: CancellationToken::new(),
             router: None,
             processes: None,
+            config: cfg.get_redacted_json(),
+        }
+    }
+
+    /// Sets application router with the state shared by its handlers, the state is applied to the
+    /// router here so routes and layers can't be added after it by mistake.
+    ///
+    /// Handlers extract the whole state or its parts, parts require [`axum::extract::FromRef`]
+    /// implementations, derive them with `#[derive(Clone, FromRef)]` on the state struct.
+    ///
+    /// ```rust,no_run
+    /// use axum::extract::{FromRef, State};
+    /// use caslex::server::{Config, Server};
+    /// use utoipa_axum::router::OpenApiRouter;
+    ///
+    /// #[derive(Clone, FromRef)]
+    /// struct AppState {
+    ///     greeting: String,
+    ///     retries: u32,
+    /// }
+    ///
+    /// async fn handler(State(greeting): State<String>) -> String {
+    ///     greeting
+    /// }
+    ///
+    /// # async fn run() {
+    /// let state = AppState {
+    ///     greeting: "hello".to_owned(),
+    ///     retries: 3,
+    /// };
+    /// let router = OpenApiRouter::new().route("/", axum::routing::get(handler));
+    ///
+    /// let result = Server::new(Config::parse())
+    ///     .router_with_state(router, state)
+    ///     .run()
+    ///     .await;
+    /// # }
+    /// ```
+    pub fn router_with_state<S>(mut self, router: OpenApiRouter<S>, state: S) -> Self
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        self.router = Some(router.with_state(state));
+        self
+    }
+
+    /// Adds tower layer to the application router.
+    ///
+    /// Custom layers wrap route handlers inside the built-in layers, so requests pass request id,
+    /// metrics, timeout, tracing and other built-in layers before reaching them. Layers added later
+    /// wrap the ones added earlier, as with [`Router::layer`].
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.layers
+            .push(Box::new(move |router: Router| router.layer(layer.clone())));
+        self
+    }
+
+    /// Sets handler of requests which don't match any route, replaces the built-in JSON error.
+    pub fn not_found_handler<H, T>(mut self, handler: H) -> Self
+    where
+        H: Handler<T, ()> + Sync,
+        T: 'static,
+    {
+        self.not_found_handler = Some(Box::new(move |router: Router| {
+            router.fallback(handler.clone())
+        }));
+        self
+    }
+
+    /// Sets handler of requests which match a route but not its methods, replaces the built-in
+    /// JSON error.
+    pub fn method_not_allowed_handler<H, T>(mut self, handler: H) -> Self
+    where
+        H: Handler<T, ()> + Sync,
+        T: 'static,
+    {
+        self.method_not_allowed_handler = Some(Box::new(move |router: Router| {
+            router.method_not_allowed_fallback(handler.clone())
+        }));
+        self
+    }
+
+    /// Mounts metrics endpoint on the application router in addition to the metrics server.
+    pub fn expose_metrics_on_main_router(mut self, expose: bool) -> Self {
+        self.metrics_on_main_router = expose;
+        self
+    }
+
+    /// Sets OpenAPI title, description and version, the crate metadata is used by default.
+    pub fn openapi_info(mut self, info: utoipa::openapi::Info) -> Self {
+        self.docs.info = Some(info);
+        self
+    }
+
+    /// Sets OpenAPI servers used by the docs UI requests, `http://localhost:9000` by default.
+    pub fn openapi_servers(mut self, servers: Vec<utoipa::openapi::Server>) -> Self {
+        self.docs.servers = Some(servers);
+        self
+    }
+
+    /// Adds OpenAPI security scheme, replaces the built-in bearer `token` scheme when named
+    /// `token`.
+    pub fn openapi_security_scheme(
+        mut self,
+        name: impl Into<String>,
+        scheme: utoipa::openapi::security::SecurityScheme,
+    ) -> Self {
+        self.docs.security_schemes.push((name.into(), scheme));
+        self
+    }
+
+    /// Sets OpenAPI security requirements applied to all operations by default.
+    pub fn openapi_security(
+        mut self,
+        security: Vec<utoipa::openapi::security::SecurityRequirement>,
+    ) -> Self {
+        self.docs.security = Some(security);
+        self
+    }
+
+    /// Sets callback called on `SIGHUP` signal instead of shutting down, e.g. to re-read log
+    /// level. Unix only, the callback is never called on other platforms.
+    pub fn on_reload(mut self, on_reload: impl Fn() + Send + Sync + 'static) -> Self {
+        self.on_reload = Some(Arc::new(on_reload));
+        self
+    }
+
+    /// Sets callback called once the application and metrics listeners are bound, before the
+    /// servers accept connections.
+    pub fn on_startup(mut self, on_startup: impl FnOnce() + Send + 'static) -> Self {
+        *self.on_startup.get_mut().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(on_startup));
+        self
+    }
+
+    /// Sets callback called once the shutdown is started, while the servers are drained and
+    /// before processes are cancelled.
+    pub fn on_shutdown(mut self, on_shutdown: impl FnOnce() + Send + 'static) -> Self {
+        *self
+            .on_shutdown
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner()) = Some(Box::new(on_shutdown));
+        self
+    }
+
+    /// Waits until [`run`](Self::run) binds the listeners and returns their addresses, e.g. to
+    /// learn ports assigned for port `0`. Returns `None` when binding failed.
+    pub async fn bound_addrs(&self) -> Option<BoundAddrs> {
+        let mut receiver = self.bind_state.subscribe();
+        let state = receiver
+            .wait_for(|state| !matches!(state, BindState::Pending))
+            .await
+            .ok()?;
+
+        match *state {
+            BindState::Bound(addrs) => Some(addrs),
+            _ => None,
         }
     }
 
-    /// Pre run and run background processes if passed and start application and metrics server.
+    /// Returns token starting graceful shutdown of the running server when cancelled, the same way
+    /// as `SIGINT` or `SIGTERM` signal does.
+    ///
+    /// Lets apps managing their own lifecycle and tests stop [`run`](Self::run). Cancelling the
+    /// token before the server is run makes it shut down right after startup.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Registers dependency health check run by the readiness endpoint.
+    pub fn health_check(mut self, name: impl Into<String>, check: Arc<dyn HealthCheck>) -> Self {
+        self.health_checks.push((name.into(), check));
+        self
+    }
+
+    /// Start application and metrics server, pre run and run background processes if passed.
+    ///
+    /// Servers accept connections during the pre run, the readiness probe fails until the pre run
+    /// of all processes is finished. The [`on_startup`](Self::on_startup) callback is called once
+    /// the listeners are bound, their addresses are available with
+    /// [`bound_addrs`](Self::bound_addrs).
+    ///
+    /// On shutdown signal, cancelled [`shutdown_token`](Self::shutdown_token) or a startup failure
+    /// the [`on_shutdown`](Self::on_shutdown) callback is called and the servers stop accepting
+    /// connections and drain in-flight requests first, then processes are cancelled and awaited,
+    /// then callbacks of [`caslex_extra::closer`] are run. Handlers can use processes until the
+    /// response is sent.
+    ///
+    /// Same as [`bind`](Self::bind) followed by [`BoundServer::serve`].
     pub async fn run(&self) -> anyhow::Result<()> {
-        const PROCESS_PRE_RUN_TIMEOUT: Duration = Duration::from_secs(60);
-        static SHUTDOWN_TOKEN: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
+        self.bind().await?.serve().await
+    }
 
-        let app_server = self.bootstrap_server(
-            self.addr.clone(),
-            self.setup_router(),
-            ServerKind::Application,
-        );
+    /// Runs the server on the runtime of `handle`, blocking the current thread until shutdown.
+    ///
+    /// Listeners, connections, processes and background tasks are all spawned on that runtime
+    /// instead of the ambient one, so services can tune it explicitly, e.g. set worker threads.
+    /// The runtime should be multi-threaded, `current_thread` runtime can't drive IO from this
+    /// call, run the server with its `block_on` instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called from an asynchronous execution context.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use caslex::server::{Config, Server};
+    ///
+    /// fn main() -> anyhow::Result<()> {
+    ///     let runtime = tokio::runtime::Builder::new_multi_thread()
+    ///         .worker_threads(4)
+    ///         .enable_all()
+    ///         .build()?;
+    ///
+    ///     Server::new(Config::parse()).run_on(runtime.handle().clone())
+    /// }
+    /// ```
+    pub fn run_on(&self, handle: tokio::runtime::Handle) -> anyhow::Result<()> {
+        handle.block_on(self.run())
+    }
 
-        let metrics_server = self.bootstrap_server(
-            self.metrics_addr.clone(),
-            get_metrics_router(),
-            ServerKind::Metrics,
-        );
+    /// Binds application and metrics listeners, returns the handle serving them.
+    ///
+    /// The handle exposes bound addresses and the shutdown trigger, e.g. to send requests to the
+    /// server bound to port `0` in tests. When binding fails the shutdown path of
+    /// [`run`](Self::run) is taken. Invalid configuration, e.g. invalid `AUTH_HEADER_NAME` with
+    /// `auth` feature, fails before binding.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use caslex::server::{Config, Server};
+    ///
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let server = Server::new(Config::parse());
+    /// let bound = server.bind().await?;
+    /// let app_addr = bound.addrs().app;
+    /// let shutdown = bound.shutdown_token();
+    ///
+    /// let (result, ()) = tokio::join!(bound.serve(), async {
+    ///     // send requests to app_addr
+    ///     shutdown.cancel();
+    /// });
+    /// result
+    /// # }
+    /// ```
+    pub async fn bind(&self) -> anyhow::Result<BoundServer<'_, 'a>> {
+        if let Some(buckets) = self.metrics_buckets.clone() {
+            metrics::set_buckets(buckets)?;
+        }
+        #[cfg(feature = "auth")]
+        crate::middlewares::auth::auth_header()?;
 
-        let processes = match self.processes {
-            Some(processes) => processes,
-            _ => &vec![],
+        let (app_listener, metrics_listener) = match self.bind_listeners().await {
+            Ok(listeners) => listeners,
+            Err(e) => {
+                self.bind_state.send_replace(BindState::Failed);
+                call_hook(&self.on_shutdown);
+                closer::cleanup_resources_async().await;
+                return Err(anyhow!("Failed to bootstrap server. Reason: {:?}", e));
+            }
         };
 
-        // pre run processes
-        {
-            let tasks: Vec<_> = processes
-                .iter()
-                .map(|p| {
-                    tokio::spawn(timeout(PROCESS_PRE_RUN_TIMEOUT, async {
-                        p.pre_run().await
-                    }))
-                })
-                .collect();
+        let addrs = BoundAddrs {
+            app: app_listener.local_addr(),
+            metrics: metrics_listener
+                .as_ref()
+                .and_then(|listener| listener.local_addr().ok()),
+        };
+        self.bind_state.send_replace(BindState::Bound(addrs));
 
-            for task in tasks {
-                if let Err(e) = task.await? {
-                    return Err(anyhow!("error while pre run process: {}", e));
+        let shutdown = self.shutdown.child_token();
+        tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                tokio::select! {
+                    _ = shutdown_signal() => shutdown.cancel(),
+                    _ = shutdown.cancelled() => {},
                 }
             }
+        });
+        if let Some(on_reload) = self.on_reload.clone() {
+            tokio::spawn(reload_signal(on_reload, shutdown.clone()));
         }
 
-        // disable failure in the custom panic hook when there is a panic,
-        // because we can't handle the panic in the panic middleware (exit(1) trouble)
-        setup_panic_hook();
+        Ok(BoundServer {
+            server: self,
+            app_listener,
+            metrics_listener,
+            addrs,
+            shutdown,
+        })
+    }
 
-        {
-            // run processes
-            let runnable_tasks: Vec<_> = processes
-                .iter()
-                .map(|p| tokio::spawn(async { p.run(SHUTDOWN_TOKEN.clone()).await }))
-                .collect();
+    async fn bind_listeners(
+        &self,
+    ) -> anyhow::Result<(AppListener, Option<tokio::net::TcpListener>)> {
+        let app_listener = match &self.uds_path {
+            Some(path) => bind_unix(path, ServerKind::Application)?,
+            _ => AppListener::Tcp(bind_tcp(&self.addr, ServerKind::Application).await?),
+        };
+        let metrics_listener = match self.metrics_enabled {
+            true => match bind_tcp(&self.metrics_addr, ServerKind::Metrics).await {
+                Ok(listener) => Some(listener),
+                // the application keeps serving without metrics and probes of the listener
+                Err(e) if !self.metrics_required => {
+                    tracing::warn!("metrics server is not started: {e}");
+                    None
+                }
+                Err(e) => return Err(e),
+            },
+            false => None,
+        };
 
-            tokio::try_join!(app_server, metrics_server)
-                .map_err(|e| anyhow!("Failed to bootstrap server. Reason: {:?}", e))?;
+        Ok((app_listener, metrics_listener))
+    }
 
-            SHUTDOWN_TOKEN.cancel();
+    async fn serve_tcp(
+        &self,
+        listener: tokio::net::TcpListener,
+        router: Router,
+        server_kind: ServerKind,
+        shutdown: CancellationToken,
+    ) -> anyhow::Result<()> {
+        let addr = listener.local_addr()?.to_string();
 
-            for task in runnable_tasks {
-                if let Err(e) = task.await? {
-                    tracing::error!("Failed to shutdown processes. Reason: {:?}", e);
-                }
-            }
-        }
+        self.serve_connections(listener, router, &addr, server_kind, shutdown)
+            .await;
 
         Ok(())
     }
 
-    async fn bootstrap_server(
+    #[cfg(unix)]
+    async fn serve_unix(
         &self,
-        addr: String,
+        listener: tokio::net::UnixListener,
+        path: &Path,
         router: Router,
         server_kind: ServerKind,
+        shutdown: CancellationToken,
     ) -> anyhow::Result<()> {
-        let listener = tokio::net::TcpListener::bind(addr.clone())
-            .await
-            .map_err(|e| anyhow!("failed to bind to address: {e}"))?;
+        let addr = format!("unix:{}", path.display());
 
-        tracing::info!("listening {server_kind} server on {addr}");
+        self.serve_connections(listener, router, &addr, server_kind, shutdown)
+            .await;
 
-        axum::serve(
-            listener,
-            router.into_make_service_with_connect_info::<SocketAddr>(),
-        )
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .map_err(|e| anyhow!("failed to start server on address {addr}: {e}"))?;
+        if let Err(e) = std::fs::remove_file(path) {
+            tracing::warn!("failed to remove unix socket {addr}: {e}");
+        }
 
         Ok(())
     }
 
+    /// Serves connections until shutdown, then waits for them to finish in-flight requests.
+    /// Connections still alive after the shutdown timeout are aborted.
+    async fn serve_connections<L>(
+        &self,
+        mut listener: L,
+        router: Router,
+        addr: &str,
+        server_kind: ServerKind,
+        shutdown: CancellationToken,
+    ) where
+        L: axum::serve::Listener,
+        L::Addr: Clone + Sync,
+    {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let mut connections = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                (io, remote_addr) = listener.accept() => {
+                    connections.spawn(serve_connection(
+                        io,
+                        remote_addr,
+                        router.clone(),
+                        in_flight.clone(),
+                        shutdown.clone(),
+                    ));
+                }
+                // finished connections are reaped, so the set doesn't grow
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+        drop(listener);
+
+        let drain = async { while connections.join_next().await.is_some() {} };
+        if timeout(self.shutdown_timeout, drain).await.is_err() {
+            tracing::warn!(
+                "{server_kind} server {addr} shutdown timed out, {} requests still in flight, \
+                 aborting {} connections",
+                in_flight.load(Ordering::Relaxed),
+                connections.len()
+            );
+            connections.shutdown().await;
+        }
+    }
+
     fn setup_router(&self) -> Router {
-        let _router = match self.router.clone() {
-            Some(router) => router.merge(get_default_router()),
-            _ => get_default_router(),
+        let user_router = self.router.clone().unwrap_or_default();
+        let default_router = get_default_router(self.get_health_checks());
+        // Nested routes are prefixed in OpenAPI paths as well
+        let _router = match &self.base_path {
+            Some(base_path) if self.base_path_probes => {
+                OpenApiRouter::new().nest(base_path, user_router.merge(default_router))
+            }
+            Some(base_path) => OpenApiRouter::new()
+                .nest(base_path, user_router)
+                .merge(default_router),
+            _ => user_router.merge(default_router),
         };
 
-        trace::with_trace_layer(swagger::get_openapi_router(_router, self.docs_url.clone()))
-            // Fallback 404
-            .fallback(fallback_handler)
-            // Fallback 405
-            .method_not_allowed_fallback(fallback_handler_405)
+        let router = swagger::get_openapi_router(_router, &self.docs);
+        let router = if self.metrics_enabled && !self.metrics_on_main_router {
+            router
+        } else {
+            let metrics_path = match &self.base_path {
+                Some(base_path) if self.base_path_probes => {
+                    format!("{base_path}{}", self.metrics_path)
+                }
+                _ => self.metrics_path.clone(),
+            };
+            router.merge(with_metrics_auth(
+                Router::new().route(&metrics_path, get(metrics::prometheus_handler)),
+                self.metrics_basic_auth.clone(),
+            ))
+        };
+
+        // Custom layers go innermost, so built-in layers handle requests before them
+        let router = self
+            .layers
+            .iter()
+            .fold(router, |router, layer| layer(router));
+
+        // Fallback 404
+        let router = match &self.not_found_handler {
+            Some(not_found_handler) => not_found_handler(router),
+            _ => router.fallback(fallback_handler),
+        };
+        // Fallback 405
+        let router = match &self.method_not_allowed_handler {
+            Some(method_not_allowed_handler) => method_not_allowed_handler(router),
+            _ => router.method_not_allowed_fallback(fallback_handler_405),
+        };
+
+        // Bodies capture, runs inside the request span
+        let router = router.layer(option_layer(self.body_capture.map(|capture| {
+            middleware::from_fn_with_state(capture, body_capture::body_capture_handler)
+        })));
+        // Unmatched requests are traced too, labeled `<unmatched>`
+        let router = trace::with_trace_layer(
+            router,
+            self.telemetry_exclude_paths.clone(),
+            self.telemetry_redact_query_params.clone(),
+        );
+
+        let router = router
+            // Concurrency limiter, sheds requests instead of queueing so the request timeout
+            // applies to handled requests only
+            .layer(option_layer(self.concurrency_limit.clone().map(|limit| {
+                middleware::from_fn_with_state(limit, concurrency_limit::concurrency_limit_handler)
+            })))
+            // Rate limiter
+            .layer(option_layer(self.rate_limiter.clone().map(|limiter| {
+                middleware::from_fn_with_state(limiter, rate_limit::rate_limit_handler)
+            })))
             // Panic recovery handler
             .layer(CatchPanicLayer::custom(panic_handler))
             // Prometheus metrics tracker
-            .layer(middleware::from_fn(metrics::metrics_handler))
-            // Request timeout
-            .layer(TimeoutLayer::new(self.request_timeout))
+            .layer(middleware::from_fn_with_state(
+                self.telemetry_exclude_paths.clone(),
+                metrics::metrics_handler,
+            ))
+            // Request timeout, upgrade requests are exempt
+            .layer(middleware::from_fn_with_state(
+                self.request_timeout,
+                request_timeout_handler,
+            ))
+            // Limit request body size, replaces the axum default extractors limit
+            .layer(DefaultBodyLimit::disable())
+            .layer(RequestBodyLimitLayer::new(self.max_body_size))
+            .layer(middleware::map_response(payload_too_large_handler))
+            // Decompress request bodies, goes ahead of the body limit to limit decompressed size
+            .layer(
+                RequestDecompressionLayer::new()
+                    .gzip(self.request_decompression)
+                    .deflate(self.request_decompression)
+                    .pass_through_unaccepted(!self.request_decompression),
+            )
+            .layer(option_layer(
+                self.request_decompression
+                    .then(|| middleware::from_fn(request_decompression_handler)),
+            ))
             // Compress responses
-            .layer(CompressionLayer::new())
-            // Mark the `Authorization` request header as sensitive so it doesn't show in logs
-            .layer(SetSensitiveRequestHeadersLayer::new(once(AUTHORIZATION)))
+            .layer(self.compression.layer())
+            // Mark the auth request headers as sensitive so they don't show in logs
+            .layer(SetSensitiveRequestHeadersLayer::new(sensitive_headers()))
+            // Keep request id for error responses, goes ahead of the layers above so panic,
+            // timeout and body limit errors carry it too
+            .layer(middleware::from_fn(errors::request_scope))
             // Propagate headers from requests to responses
-            .layer(PropagateHeaderLayer::new(HeaderName::from_static(
-                "x-request-id",
-            )))
+            .layer(PropagateHeaderLayer::new(REQUEST_ID_HEADER))
+            // Generate request id if missing, goes ahead of the layers above to be available for
+            // them
+            .layer(request_id::set_request_id_layer());
+
+        // CORS goes outermost, so preflight requests never reach the 405 fallback
+        match self.cors.clone() {
+            Some(cors) => router.layer(cors),
+            _ => router,
+        }
+    }
+
+    fn get_health_checks(&self) -> HealthChecks {
+        HealthChecks::with_startup_gate(self.health_checks.clone(), self.started.clone())
+    }
+
+    fn get_config(&self) -> Arc<serde_json::Value> {
+        let processes: Vec<_> = self
+            .processes
+            .into_iter()
+            .flatten()
+            .map(|process| process.name())
+            .collect();
+
+        Arc::new(json!({ "config": self.config, "processes": processes }))
+    }
+}
+
+/// Config fields exposed by `/config` endpoint as is, other set fields are redacted.
+const CONFIG_SAFE_FIELDS: &[&str] = &[
+    "host",
+    "port",
+    "uds_path",
+    "base_path",
+    "base_path_probes",
+    "metrics_port",
+    "metrics_enabled",
+    "metrics_required",
+    "metrics_path",
+    "grpc_health_port",
+    "request_timeout",
+    "docs_url",
+    "docs_enabled",
+    "docs_ui",
+    "openapi_json_enabled",
+    "openapi_json_path",
+    "max_body_size",
+    "compression_enabled",
+    "compression_algorithms",
+    "compression_min_size",
+    "compression_exclude_content_types",
+    "request_decompression_enabled",
+    "cors_allow_origins",
+    "cors_allow_methods",
+    "cors_allow_headers",
+    "shutdown_timeout",
+    "telemetry_exclude_paths",
+    "telemetry_redact_query_params",
+    "trace_body_enabled",
+    "trace_body_max_size",
+    "metrics_buckets",
+    "rate_limit_per_second",
+    "rate_limit_burst",
+    "rate_limit_header",
+    "max_concurrent_requests",
+    "error_format",
+];
+
+/// Replaces values of set fields missing in [`CONFIG_SAFE_FIELDS`] with `***`, unset fields stay
+/// `null`.
+fn redact_config(mut config: serde_json::Value) -> serde_json::Value {
+    if let Some(fields) = config.as_object_mut() {
+        for (name, value) in fields.iter_mut() {
+            if !value.is_null() && !CONFIG_SAFE_FIELDS.contains(&name.as_str()) {
+                *value = json!(REDACTED);
+            }
+        }
+    }
+    config
+}
+
+fn value_enum_name(value: &impl ValueEnum) -> Option<String> {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_owned())
+}
+
+fn sensitive_headers() -> Vec<HeaderName> {
+    #[allow(unused_mut)]
+    let mut headers = vec![AUTHORIZATION];
+    #[cfg(feature = "auth")]
+    if let Ok(auth_header) = crate::middlewares::auth::auth_header()
+        && *auth_header != AUTHORIZATION
+    {
+        headers.push(auth_header.clone());
+    }
+    headers
+}
+
+async fn supervise_process(
+    process: &'static dyn Process,
+    token: CancellationToken,
+) -> anyhow::Result<()> {
+    let mut retries = 0;
+
+    loop {
+        let Err(e) = process.run(token.clone()).await else {
+            return Ok(());
+        };
+
+        match process.restart_policy() {
+            RestartPolicy::OnFailure {
+                max_retries,
+                backoff,
+            } if retries < max_retries && !token.is_cancelled() => {
+                let delay = backoff.saturating_mul(2u32.saturating_pow(retries));
+                retries += 1;
+
+                tracing::warn!(
+                    "Process failed, restarting in {delay:?} ({retries}/{max_retries}). Reason: {e:?}"
+                );
+
+                tokio::select! {
+                    _ = token.cancelled() => return Err(e),
+                    _ = tokio::time::sleep(delay) => {},
+                }
+            }
+            _ => return Err(e),
+        }
+    }
+}
+
+/// Counts requests of a server until their response head is produced.
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl InFlightGuard {
+    fn new(in_flight: Arc<AtomicUsize>) -> Self {
+        in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(in_flight)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Serves HTTP/1 and HTTP/2 requests of the connection, closes it gracefully on shutdown.
+async fn serve_connection<I, A>(
+    io: I,
+    remote_addr: A,
+    router: Router,
+    in_flight: Arc<AtomicUsize>,
+    shutdown: CancellationToken,
+) where
+    I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    A: Clone + Send + Sync + 'static,
+{
+    let service = hyper::service::service_fn(move |mut req: Request<hyper::body::Incoming>| {
+        req.extensions_mut()
+            .insert(ConnectInfo(remote_addr.clone()));
+        let guard = InFlightGuard::new(in_flight.clone());
+        let response = router.clone().oneshot(req.map(Body::new));
+
+        async move {
+            let response = response.await;
+            drop(guard);
+            response
+        }
+    });
+
+    let mut builder = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new());
+    // CONNECT protocol needed for HTTP/2 websockets
+    builder.http2().enable_connect_protocol();
+
+    let mut conn = pin!(builder.serve_connection_with_upgrades(TokioIo::new(io), service));
+    let result = tokio::select! {
+        result = conn.as_mut() => result,
+        _ = shutdown.cancelled() => {
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
+    };
+
+    if let Err(e) = result {
+        tracing::trace!("failed to serve connection: {e:#}");
     }
 }
 
@@ -268,30 +1787,158 @@ async fn shutdown_signal() {
     }
 }
 
-fn setup_panic_hook() {
-    std::panic::set_hook(Box::new(move |panic_info| {
-        // If the panic has a source location, record it as structured fields.
-        if let Some(location) = panic_info.location() {
-            tracing::error!(
-                message = %panic_info,
-                panic.file = location.file(),
-                panic.line = location.line(),
-                panic.column = location.column(),
-            );
-        } else {
-            tracing::error!(message = %panic_info);
+/// Calls `on_reload` on every `SIGHUP` until shutdown.
+#[cfg(unix)]
+async fn reload_signal(on_reload: Arc<dyn Fn() + Send + Sync>, shutdown: CancellationToken) {
+    let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::warn!("failed to install SIGHUP handler: {e}");
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                tracing::info!("reloading on SIGHUP");
+                on_reload();
+            },
+            _ = shutdown.cancelled() => return,
         }
-    }))
+    }
 }
 
-fn get_default_router() -> OpenApiRouter {
+#[cfg(not(unix))]
+async fn reload_signal(_on_reload: Arc<dyn Fn() + Send + Sync>, _shutdown: CancellationToken) {}
+
+fn get_default_router(health_checks: HealthChecks) -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(readiness))
         .routes(routes!(liveness))
+        .layer(Extension(health_checks))
+}
+
+/// Metrics listener router, admin endpoints are mounted only here to keep them off the public
+/// application listener. Metrics and admin endpoints are protected by Basic auth if configured,
+/// health probes stay public. Config endpoint is mounted only with Basic auth.
+fn get_metrics_router(
+    health_checks: HealthChecks,
+    metrics_path: &str,
+    basic_auth: Option<Arc<BasicAuth>>,
+    config: Arc<serde_json::Value>,
+) -> Router {
+    let mut router = Router::new()
+        .route(metrics_path, get(metrics::prometheus_handler))
+        .route(LOG_LEVEL_PATH, put(log_level_handler))
+        .route(SAMPLING_RATIO_PATH, put(sampling_ratio_handler));
+    if basic_auth.is_some() {
+        router = router
+            .route(CONFIG_PATH, get(config_handler))
+            .layer(Extension(config));
+    }
+    let protected = with_metrics_auth(router, basic_auth);
+
+    Router::from(get_default_router(health_checks)).merge(protected)
+}
+
+fn with_metrics_auth(router: Router, basic_auth: Option<Arc<BasicAuth>>) -> Router {
+    match basic_auth {
+        Some(auth) => router.route_layer(middleware::from_fn_with_state(
+            auth,
+            basic_auth::basic_auth_handler,
+        )),
+        None => router,
+    }
+}
+
+const LOG_LEVEL_PATH: &str = "/log-level";
+
+#[derive(Deserialize)]
+struct LogLevelRequest {
+    level: String,
+}
+
+/// Replaces log level of the tracing subscriber, accepts `{ "level": "debug" }` body.
+async fn log_level_handler(body: Bytes) -> Response {
+    if !log_level::is_log_level_reloadable() {
+        return error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "log_level_reload_unavailable",
+            "log level reload is not configured",
+        );
+    }
+
+    let request = match serde_json::from_slice::<LogLevelRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return error_response(StatusCode::BAD_REQUEST, "invalid_request", &e.to_string());
+        }
+    };
+
+    if let Err(e) = log_level::set_log_level(&request.level) {
+        return error_response(StatusCode::BAD_REQUEST, "invalid_log_level", &e.to_string());
+    }
+
+    tracing::info!("log level set to {}", request.level);
+    Json(json!({ "level": request.level })).into_response()
+}
+
+const SAMPLING_RATIO_PATH: &str = "/sampling-ratio";
+
+#[derive(Deserialize)]
+struct SamplingRatioRequest {
+    ratio: f64,
+}
+
+/// Replaces trace sampling ratio, accepts `{ "ratio": 0.5 }` body.
+async fn sampling_ratio_handler(body: Bytes) -> Response {
+    if !sampling::is_sampling_ratio_reloadable() {
+        return error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "sampling_ratio_reload_unavailable",
+            "sampling ratio reload is not configured",
+        );
+    }
+
+    let request = match serde_json::from_slice::<SamplingRatioRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return error_response(StatusCode::BAD_REQUEST, "invalid_request", &e.to_string());
+        }
+    };
+
+    if let Err(e) = sampling::set_sampling_ratio(request.ratio) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_sampling_ratio",
+            &e.to_string(),
+        );
+    }
+
+    tracing::info!("sampling ratio set to {}", request.ratio);
+    Json(json!({ "ratio": request.ratio })).into_response()
+}
+
+const CONFIG_PATH: &str = "/config";
+
+/// Returns resolved server config with secrets redacted and names of background processes.
+async fn config_handler(Extension(config): Extension<Arc<serde_json::Value>>) -> Response {
+    Json(config.as_ref()).into_response()
 }
 
-fn get_metrics_router() -> Router {
-    Router::from(get_default_router()).route("/metrics", get(metrics::prometheus_handler))
+#[derive(Deserialize)]
+struct ReadinessParams {
+    verbose: Option<String>,
+}
+
+impl ReadinessParams {
+    /// Returns true for `?verbose` and any value except `false` and `0`.
+    fn is_verbose(&self) -> bool {
+        self.verbose
+            .as_deref()
+            .is_some_and(|verbose| !matches!(verbose, "false" | "0"))
+    }
 }
 
 /// readiness
@@ -299,12 +1946,49 @@ fn get_metrics_router() -> Router {
     get,
     path = "/readiness",
     tag = "health",
+    params(
+        ("verbose" = Option<bool>, Query, description = "Report status and latency of every check")
+    ),
     responses(
-        (status = 200)
+        (status = 200),
+        (status = 503, description = "One or more health checks failed")
     )
 )]
-async fn readiness() -> (StatusCode, Cow<'static, str>) {
-    (StatusCode::OK, Cow::from("OK"))
+async fn readiness(
+    Extension(health_checks): Extension<HealthChecks>,
+    Query(params): Query<ReadinessParams>,
+) -> axum::response::Response {
+    let results = health_checks.run().await;
+    let healthy = results
+        .iter()
+        .all(|result| result.status == CheckStatus::Ok);
+
+    if params.is_verbose() {
+        let (status, body) = if healthy {
+            (StatusCode::OK, json!({ "status": "ok", "checks": results }))
+        } else {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                json!({ "status": "unavailable", "checks": results }),
+            )
+        };
+        return (status, Json(body)).into_response();
+    }
+
+    if healthy {
+        return (StatusCode::OK, Cow::from("OK")).into_response();
+    }
+
+    let failed: Vec<_> = results
+        .into_iter()
+        .filter(|result| result.status == CheckStatus::Failed)
+        .collect();
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({ "status": "unavailable", "failed_checks": failed })),
+    )
+        .into_response()
 }
 
 /// liveness
@@ -321,6 +2005,8 @@ async fn liveness() -> (StatusCode, Cow<'static, str>) {
 }
 
 fn panic_handler(err: Box<dyn Any + Send + 'static>) -> Response<Full<Bytes>> {
+    metrics::record_panic();
+
     let details = if let Some(s) = err.downcast_ref::<String>() {
         s.clone()
     } else if let Some(s) = err.downcast_ref::<&str>() {
@@ -328,50 +2014,1827 @@ fn panic_handler(err: Box<dyn Any + Send + 'static>) -> Response<Full<Bytes>> {
     } else {
         "Unknown panic message".to_owned()
     };
+    log_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "unhandled_error",
+        &details,
+    );
 
-    let body = ErrorResponse {
-        error: ErrorInfo {
-            kind: "unhandled_error".to_owned(),
-            details,
-        },
-    };
-    let body = serde_json::to_string(&body).unwrap();
+    let (content_type, body) = error_body(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "unhandled_error",
+        &details,
+    );
 
     Response::builder()
         .status(StatusCode::INTERNAL_SERVER_ERROR)
-        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_TYPE, content_type)
         .body(Full::from(body))
         .unwrap()
 }
 
+async fn request_timeout_handler(
+    State(request_timeout): State<Duration>,
+    req: Request,
+    next: middleware::Next,
+) -> axum::response::Response {
+    // upgraded connections outlive the request, don't cut their handshake
+    if is_upgrade_request(&req) {
+        return next.run(req).await;
+    }
+
+    match timeout(request_timeout, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => error_response(
+            StatusCode::REQUEST_TIMEOUT,
+            "request_timeout",
+            "request timed out",
+        ),
+    }
+}
+
+/// Returns true for HTTP/1.1 `Connection: Upgrade` and HTTP/2 extended `CONNECT` requests.
+fn is_upgrade_request(req: &Request) -> bool {
+    let has_upgrade_token = req
+        .headers()
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+
+    (has_upgrade_token && req.headers().contains_key(header::UPGRADE))
+        || req.method() == Method::CONNECT
+}
+
+async fn request_decompression_handler(
+    req: Request,
+    next: middleware::Next,
+) -> axum::response::Response {
+    if !req.headers().contains_key(header::CONTENT_ENCODING) {
+        return next.run(req).await;
+    }
+
+    let response = next.run(req).await;
+    let content_type = response.headers().get(header::CONTENT_TYPE);
+
+    // only replace responses produced by the decompression layer and the body extractors
+    match response.status() {
+        StatusCode::UNSUPPORTED_MEDIA_TYPE if content_type.is_none() => error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_content_encoding",
+            "unsupported content encoding",
+        ),
+        StatusCode::BAD_REQUEST
+            if content_type.is_some_and(|v| v.as_bytes().starts_with(b"text/plain")) =>
+        {
+            error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_body",
+                "failed to read request body",
+            )
+        }
+        _ => response,
+    }
+}
+
+async fn payload_too_large_handler(response: axum::response::Response) -> axum::response::Response {
+    // only replace plain text response produced by the body limit layer
+    let is_plain_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"text/plain"));
+
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || !is_plain_text {
+        return response;
+    }
+
+    error_response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "payload_too_large",
+        "payload too large",
+    )
+}
+
 async fn fallback_handler() -> Response<Full<Bytes>> {
-    let body = ErrorResponse {
-        error: ErrorInfo {
-            kind: "method_not_found".to_owned(),
-            details: "method not found".to_owned(),
-        },
-    };
-    let body = serde_json::to_string(&body).unwrap();
+    let (content_type, body) = error_body(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "method_not_found",
+        "method not found",
+    );
 
     Response::builder()
         .status(StatusCode::INTERNAL_SERVER_ERROR)
-        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_TYPE, content_type)
         .body(Full::from(body))
         .unwrap()
 }
 
 async fn fallback_handler_405() -> Response<Full<Bytes>> {
-    let body = ErrorResponse {
-        error: ErrorInfo {
-            kind: "method_not_allowed".to_owned(),
-            details: "method not allowed".to_owned(),
-        },
-    };
-    let body = serde_json::to_string(&body).unwrap();
+    let (content_type, body) = error_body(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "method_not_allowed",
+        "method not allowed",
+    );
 
     Response::builder()
         .status(StatusCode::INTERNAL_SERVER_ERROR)
-        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_TYPE, content_type)
         .body(Full::from(body))
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use axum_extra::headers::{Authorization, HeaderMapExt};
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::middlewares::request_id::RequestId;
+
+    fn test_config() -> Config {
+        let mut config =
+            Config::try_parse_from(["caslex", "--port", "0", "--metrics-port", "0"]).unwrap();
+        config.metrics_enabled = false;
+        config
+    }
+
+    /// Serves the server until `client` called with the application address returns, the client
+    /// starts shutdown with the token.
+    async fn serve_with<F, Fut, T>(server: &Server<'_>, client: F) -> (anyhow::Result<()>, T)
+    where
+        F: FnOnce(SocketAddr, CancellationToken) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let bound = server.bind().await.unwrap();
+        let addr = bound.addrs().app.unwrap();
+        let shutdown = bound.shutdown_token();
+
+        tokio::join!(bound.serve(), client(addr, shutdown))
+    }
+
+    async fn call(server: &Server<'_>, request: Request) -> Response {
+        server.setup_router().oneshot(request).await.unwrap()
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn get_request(uri: &str) -> Request {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    /// Sets the flag when dropped.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Router with `/slow` handler sleeping for `delay`, the handler notifies when it's entered
+    /// and sets the flag when it's finished or dropped.
+    fn slow_router(
+        delay: Duration,
+        entered: Arc<Notify>,
+        dropped: Arc<AtomicBool>,
+    ) -> OpenApiRouter {
+        OpenApiRouter::new().route(
+            "/slow",
+            get(move || async move {
+                let _flag = DropFlag(dropped);
+                entered.notify_one();
+                tokio::time::sleep(delay).await;
+                "done"
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn shutdown_timeout_aborts_slow_requests() {
+        let entered = Arc::new(Notify::new());
+        let dropped = Arc::new(AtomicBool::new(false));
+        let mut config = test_config();
+        config.shutdown_timeout = Duration::from_millis(200).into();
+        let server = Server::new(config).router(slow_router(
+            Duration::from_secs(60),
+            entered.clone(),
+            dropped.clone(),
+        ));
+
+        let started = Instant::now();
+        let (result, request) = serve_with(&server, |addr, shutdown| async move {
+            let request = tokio::spawn(reqwest::get(format!("http://{addr}/slow")));
+            entered.notified().await;
+            shutdown.cancel();
+            request.await.unwrap()
+        })
+        .await;
+        result.unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // the handler is aborted along with its connection
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(request.is_err());
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown_completes_in_flight_requests() {
+        let entered = Arc::new(Notify::new());
+        let finished = Arc::new(AtomicBool::new(false));
+        let server = Server::new(test_config()).router(slow_router(
+            Duration::from_millis(200),
+            entered.clone(),
+            finished.clone(),
+        ));
+
+        let (result, request) = serve_with(&server, |addr, shutdown| async move {
+            let request = tokio::spawn(reqwest::get(format!("http://{addr}/slow")));
+            entered.notified().await;
+            shutdown.cancel();
+            request.await.unwrap()
+        })
+        .await;
+        result.unwrap();
+
+        let response = request.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "done");
+    }
+
+    /// Counts runs started with a live token.
+    struct RunCounter {
+        runs: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Process for RunCounter {
+        async fn pre_run(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn run(&self, token: CancellationToken) -> anyhow::Result<()> {
+            if !token.is_cancelled() {
+                self.runs.fetch_add(1, Ordering::SeqCst);
+            }
+            token.cancelled().await;
+            Ok(())
+        }
+    }
+
+    async fn serve_until_process_runs(process: &'static RunCounter, runs: usize) {
+        let processes: Vec<&'static dyn Process> = vec![process];
+        let server = Server::new(test_config()).processes(&processes);
+        let bound = server.bind().await.unwrap();
+        let shutdown = bound.shutdown_token();
+
+        let stop = async {
+            while process.runs.load(Ordering::SeqCst) < runs {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            shutdown.cancel();
+        };
+
+        let (result, ()) = timeout(Duration::from_secs(5), async {
+            tokio::join!(bound.serve(), stop)
+        })
+        .await
+        .unwrap();
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn processes_of_next_server_run_after_programmatic_shutdown() {
+        static PROCESS: RunCounter = RunCounter {
+            runs: AtomicUsize::new(0),
+        };
+
+        serve_until_process_runs(&PROCESS, 1).await;
+        serve_until_process_runs(&PROCESS, 2).await;
+    }
+
+    /// Pre run waits until the process is released.
+    struct GatedProcess {
+        release: Notify,
+    }
+
+    #[async_trait]
+    impl Process for GatedProcess {
+        async fn pre_run(&self) -> anyhow::Result<()> {
+            self.release.notified().await;
+            Ok(())
+        }
+
+        async fn run(&self, token: CancellationToken) -> anyhow::Result<()> {
+            token.cancelled().await;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn readiness_fails_until_pre_run_is_finished() {
+        let process: &'static GatedProcess = Box::leak(Box::new(GatedProcess {
+            release: Notify::new(),
+        }));
+        let processes: Vec<&'static dyn Process> = vec![process];
+        let server = Server::new(test_config()).processes(&processes);
+
+        let (result, statuses) = serve_with(&server, |addr, shutdown| async move {
+            let status = async |path: &str| {
+                reqwest::get(format!("http://{addr}{path}"))
+                    .await
+                    .unwrap()
+                    .status()
+            };
+            let before = [status("/readiness").await, status("/liveness").await];
+
+            process.release.notify_one();
+            let mut after = status("/readiness").await;
+            for _ in 0..100 {
+                if after == StatusCode::OK {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                after = status("/readiness").await;
+            }
+
+            shutdown.cancel();
+            (before, after)
+        })
+        .await;
+        result.unwrap();
+
+        assert_eq!(
+            statuses.0,
+            [StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK]
+        );
+        assert_eq!(statuses.1, StatusCode::OK);
+    }
+
+    /// Records when it's cancelled.
+    struct EventProcess {
+        events: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait]
+    impl Process for EventProcess {
+        async fn pre_run(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn run(&self, token: CancellationToken) -> anyhow::Result<()> {
+            token.cancelled().await;
+            self.events.lock().unwrap().push("process cancelled");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn servers_are_drained_before_processes_are_cancelled() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let process: &'static EventProcess = Box::leak(Box::new(EventProcess {
+            events: events.clone(),
+        }));
+        let processes: Vec<&'static dyn Process> = vec![process];
+        let entered = Arc::new(Notify::new());
+        let router = OpenApiRouter::new().route(
+            "/slow",
+            get({
+                let entered = entered.clone();
+                let events = events.clone();
+                move || async move {
+                    entered.notify_one();
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    events.lock().unwrap().push("request finished");
+                    "done"
+                }
+            }),
+        );
+        let server = Server::new(test_config())
+            .router(router)
+            .processes(&processes);
+
+        let (result, response) = serve_with(&server, |addr, shutdown| async move {
+            let request = tokio::spawn(reqwest::get(format!("http://{addr}/slow")));
+            entered.notified().await;
+            shutdown.cancel();
+            request.await.unwrap()
+        })
+        .await;
+        result.unwrap();
+
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+        // the process is awaited before serve returns
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["request finished", "process cancelled"]
+        );
+    }
+
+    #[tokio::test]
+    async fn lifecycle_hooks_are_called_around_serving() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let process: &'static EventProcess = Box::leak(Box::new(EventProcess {
+            events: events.clone(),
+        }));
+        let processes: Vec<&'static dyn Process> = vec![process];
+        let server = Server::new(test_config())
+            .processes(&processes)
+            .on_startup({
+                let events = events.clone();
+                move || events.lock().unwrap().push("startup")
+            })
+            .on_shutdown({
+                let events = events.clone();
+                move || events.lock().unwrap().push("shutdown")
+            });
+
+        let (result, serving_events) = serve_with(&server, |addr, shutdown| {
+            let events = events.clone();
+            async move {
+                reqwest::get(format!("http://{addr}/liveness"))
+                    .await
+                    .unwrap();
+                let serving_events = events.lock().unwrap().clone();
+                shutdown.cancel();
+                serving_events
+            }
+        })
+        .await;
+        result.unwrap();
+
+        assert_eq!(serving_events, ["startup"]);
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["startup", "shutdown", "process cancelled"]
+        );
+    }
+
+    #[tokio::test]
+    async fn bound_addrs_of_ephemeral_ports_are_reported() {
+        let mut config = test_config();
+        config.metrics_enabled = true;
+        let server = Server::new(config);
+        let shutdown = server.shutdown_token();
+
+        let (result, addrs) = tokio::join!(server.run(), async {
+            let addrs = server.bound_addrs().await.unwrap();
+            for addr in [addrs.app.unwrap(), addrs.metrics.unwrap()] {
+                let response = reqwest::get(format!("http://{addr}/liveness"))
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+            shutdown.cancel();
+            addrs
+        });
+        result.unwrap();
+
+        let (app, metrics) = (addrs.app.unwrap(), addrs.metrics.unwrap());
+        assert_ne!(app.port(), 0);
+        assert_ne!(metrics.port(), 0);
+        assert_ne!(app, metrics);
+    }
+
+    #[tokio::test]
+    async fn bound_addrs_are_none_when_binding_fails() {
+        let taken = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = test_config();
+        config.host = "127.0.0.1".to_owned();
+        config.port = taken.local_addr().unwrap().port().to_string();
+        let server = Server::new(config);
+
+        let (result, addrs) = tokio::join!(server.run(), server.bound_addrs());
+
+        assert!(result.is_err());
+        assert!(addrs.is_none());
+    }
+
+    #[tokio::test]
+    async fn taken_metrics_port_is_fatal_only_when_required() {
+        let taken = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = test_config();
+        config.metrics_enabled = true;
+        config.metrics_port = taken.local_addr().unwrap().port().to_string();
+        let server = Server::new(config.clone())
+            .router(OpenApiRouter::new().route("/ping", get(|| async { "pong" })));
+
+        let bound = server.bind().await.unwrap();
+        assert!(bound.addrs().metrics.is_none());
+        let addr = bound.addrs().app.unwrap();
+        let shutdown = bound.shutdown_token();
+        let (result, body) = tokio::join!(bound.serve(), async {
+            let body = reqwest::get(format!("http://{addr}/ping"))
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+            shutdown.cancel();
+            body
+        });
+        assert!(result.is_ok());
+        assert_eq!(body, "pong");
+
+        config.metrics_required = true;
+        assert!(Server::new(config).bind().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn bound_server_accepts_connections_before_serving() {
+        let server = Server::new(test_config())
+            .router(OpenApiRouter::new().route("/ping", get(|| async { "pong" })));
+        let bound = server.bind().await.unwrap();
+        let addr = bound.addrs().app.unwrap();
+        let shutdown = bound.shutdown_token();
+        assert_eq!(server.bound_addrs().await.unwrap().app, Some(addr));
+
+        // the connection is queued by the bound listener until it's served
+        let request = tokio::spawn(reqwest::get(format!("http://{addr}/ping")));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!request.is_finished());
+
+        let (result, body) = tokio::join!(bound.serve(), async {
+            let body = request.await.unwrap().unwrap().text().await.unwrap();
+            shutdown.cancel();
+            body
+        });
+        result.unwrap();
+
+        assert_eq!(body, "pong");
+        // the listener is closed after shutdown
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[test]
+    fn server_runs_on_explicit_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("caslex-test-worker")
+            .enable_all()
+            .build()
+            .unwrap();
+        let server = Server::new(test_config()).router(OpenApiRouter::new().route(
+            "/thread",
+            get(|| async { std::thread::current().name().unwrap_or_default().to_owned() }),
+        ));
+        let shutdown = server.shutdown_token();
+
+        let (result, thread_name) = std::thread::scope(|scope| {
+            let serving = scope.spawn(|| server.run_on(runtime.handle().clone()));
+
+            // the client runs on its own runtime
+            let client = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let thread_name = client.block_on(async {
+                let addr = server.bound_addrs().await.unwrap().app.unwrap();
+                let response = reqwest::get(format!("http://{addr}/thread")).await.unwrap();
+                shutdown.cancel();
+                response.text().await.unwrap()
+            });
+
+            (serving.join().unwrap(), thread_name)
+        });
+        result.unwrap();
+
+        assert_eq!(thread_name, "caslex-test-worker");
+    }
+
+    async fn failing_handler() -> Result<(), errors::DefaultError> {
+        Err(anyhow!("boom").into())
+    }
+
+    async fn panicking_handler() {
+        panic!("boom");
+    }
+
+    fn failing_router() -> OpenApiRouter {
+        OpenApiRouter::new()
+            .route("/fail", get(failing_handler))
+            .route("/panic", get(panicking_handler))
+    }
+
+    #[tokio::test]
+    async fn error_response_carries_request_id() {
+        let server = Server::new(test_config()).router(failing_router());
+        let request = Request::get("/fail")
+            .header(REQUEST_ID_HEADER, "req-123")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = call(&server, request).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(json_body(response).await["error"]["trace_id"], "req-123");
+    }
+
+    #[tokio::test]
+    async fn panic_response_carries_request_id() {
+        let server = Server::new(test_config()).router(failing_router());
+        let request = Request::get("/panic")
+            .header(REQUEST_ID_HEADER, "req-456")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = call(&server, request).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["kind"], "unhandled_error");
+        assert_eq!(body["error"]["trace_id"], "req-456");
+    }
+
+    #[tokio::test]
+    async fn error_response_carries_generated_request_id() {
+        let server = Server::new(test_config()).router(failing_router());
+
+        let response = call(&server, get_request("/fail")).await;
+
+        let request_id = response.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert_eq!(json_body(response).await["error"]["trace_id"], request_id);
+    }
+
+    static CONFIG_PROCESS: RunCounter = RunCounter {
+        runs: AtomicUsize::new(0),
+    };
+
+    fn metrics_router(args: &[&str]) -> Router {
+        let config = Config::try_parse_from(["caslex"].iter().chain(args)).unwrap();
+        let processes: Vec<&'static dyn Process> = vec![&CONFIG_PROCESS];
+        let server = Server::new(config).processes(&processes);
+
+        get_metrics_router(
+            server.get_health_checks(),
+            &server.metrics_path,
+            server.metrics_basic_auth.clone(),
+            server.get_config(),
+        )
+    }
+
+    fn config_request(credentials: Option<(&str, &str)>) -> Request {
+        let mut request = get_request(CONFIG_PATH);
+        if let Some((user, password)) = credentials {
+            request
+                .headers_mut()
+                .typed_insert(Authorization::basic(user, password));
+        }
+        request
+    }
+
+    #[tokio::test]
+    async fn config_endpoint_requires_basic_auth() {
+        let router = metrics_router(&[]);
+        let response = router.oneshot(config_request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let router = metrics_router(&[
+            "--metrics-basic-auth-user",
+            "admin",
+            "--metrics-basic-auth-password",
+            "secret",
+        ]);
+        let response = router.clone().oneshot(config_request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = router
+            .oneshot(config_request(Some(("admin", "wrong"))))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn metrics_require_basic_auth_and_probes_stay_public() {
+        let router = metrics_router(&[
+            "--metrics-basic-auth-user",
+            "admin",
+            "--metrics-basic-auth-password",
+            "secret",
+        ]);
+        let metrics_request = |credentials: Option<(&str, &str)>| {
+            let mut request = get_request("/metrics");
+            if let Some((user, password)) = credentials {
+                request
+                    .headers_mut()
+                    .typed_insert(Authorization::basic(user, password));
+            }
+            request
+        };
+
+        let response = router.clone().oneshot(metrics_request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = router
+            .clone()
+            .oneshot(metrics_request(Some(("admin", "secret"))))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = router.oneshot(get_request("/liveness")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // metrics are public without configured credentials
+        let response = metrics_router(&[])
+            .oneshot(metrics_request(None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn config_endpoint_returns_redacted_config() {
+        let router = metrics_router(&[
+            "--port",
+            "8081",
+            "--request-timeout",
+            "5s",
+            "--metrics-basic-auth-user",
+            "admin",
+            "--metrics-basic-auth-password",
+            "hunter2",
+        ]);
+
+        let response = router
+            .oneshot(config_request(Some(("admin", "hunter2"))))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        let config = &body["config"];
+
+        assert_eq!(config["port"], json!("8081"));
+        assert_eq!(config["request_timeout"], json!("5s"));
+        assert_eq!(config["uds_path"], json!(null));
+        assert_eq!(config["metrics_basic_auth_user"], json!(REDACTED));
+        assert_eq!(config["metrics_basic_auth_password"], json!(REDACTED));
+        assert!(!body.to_string().contains("hunter2"), "{body}");
+
+        let processes = body["processes"].as_array().unwrap();
+        assert_eq!(processes.len(), 1);
+        assert!(processes[0].as_str().unwrap().ends_with("RunCounter"));
+    }
+
+    #[test]
+    fn unknown_config_fields_are_redacted() {
+        let config = redact_config(json!({
+            "port": 8080,
+            "db_password": "secret",
+            "db_user": null,
+        }));
+
+        assert_eq!(
+            config,
+            json!({ "port": 8080, "db_password": REDACTED, "db_user": null })
+        );
+    }
+
+    fn echo_router() -> OpenApiRouter {
+        OpenApiRouter::new().route(
+            "/echo",
+            axum::routing::post(|body: String| async move { body }),
+        )
+    }
+
+    #[tokio::test]
+    async fn oversized_body_is_rejected() {
+        let mut config = test_config();
+        config.max_body_size = 16;
+        let server = Server::new(config).router(echo_router());
+
+        let request = Request::post("/echo")
+            .body(Body::from(vec![b'a'; 1024]))
+            .unwrap();
+        let response = call(&server, request).await;
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            json_body(response).await["error"]["kind"],
+            json!("payload_too_large")
+        );
+
+        let request = Request::post("/echo").body(Body::from("small")).unwrap();
+        assert_eq!(call(&server, request).await.status(), StatusCode::OK);
+    }
+
+    fn cors_server(origins: &str) -> Server<'static> {
+        let mut config = test_config();
+        config.cors_allow_origins = origins
+            .split(',')
+            .map(|origin| HeaderValue::from_str(origin).unwrap())
+            .collect();
+        Server::new(config).router(echo_router())
+    }
+
+    fn cors_preflight(origin: &str) -> Request {
+        Request::options("/echo")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cors_allowed_origin_header_is_present() {
+        let server = cors_server("https://app.example.com,https://admin.example.com");
+
+        let request = Request::post("/echo")
+            .header(header::ORIGIN, "https://admin.example.com")
+            .body(Body::from("hello"))
+            .unwrap();
+        let response = call(&server, request).await;
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://admin.example.com"
+        );
+
+        let response = call(&server, cors_preflight("https://app.example.com")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+
+        let response = call(&server, cors_preflight("https://evil.example.com")).await;
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[tokio::test]
+    async fn cors_wildcard_origin_allows_any_origin() {
+        let server = cors_server("*");
+
+        let response = call(&server, cors_preflight("https://any.example.com")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    async fn text_body(response: Response) -> String {
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn request_metrics_are_labeled_by_method_route_and_status() {
+        let router = OpenApiRouter::new().route(
+            "/orders/{id}",
+            get(|| async { "order" }).delete(|| async { StatusCode::NOT_FOUND }),
+        );
+        let server = Server::new(test_config()).router(router);
+
+        call(&server, get_request("/orders/7")).await;
+        let request = Request::delete("/orders/8").body(Body::empty()).unwrap();
+        call(&server, request).await;
+        let metrics = text_body(call(&server, get_request("/metrics")).await).await;
+
+        for series in [
+            r#"http_requests_total{method="GET",path="/orders/{id}",status="2xx"} 1"#,
+            r#"http_requests_total{method="DELETE",path="/orders/{id}",status="4xx"} 1"#,
+            r#"http_request_duration_count{method="GET",path="/orders/{id}",status="2xx"} 1"#,
+            r#"http_request_size{method="GET",path="/orders/{id}",status="2xx"}"#,
+            r#"http_response_size{method="GET",path="/orders/{id}",status="2xx"}"#,
+        ] {
+            assert!(metrics.contains(series), "{series} is missing in {metrics}");
+        }
+    }
+
+    #[tokio::test]
+    async fn request_metrics_are_labeled_by_route_template_or_unmatched() {
+        let router = OpenApiRouter::new().route("/members/{id}", get(|| async { "member" }));
+        let server = Server::new(test_config()).router(router);
+
+        call(&server, get_request("/members/42")).await;
+        call(&server, get_request("/members-42")).await;
+        let metrics = text_body(call(&server, get_request("/metrics")).await).await;
+
+        assert!(
+            metrics
+                .contains(r#"http_requests_total{method="GET",path="/members/{id}",status="2xx"}"#),
+            "{metrics}"
+        );
+        assert!(metrics.contains(r#"path="<unmatched>""#), "{metrics}");
+        assert!(!metrics.contains(r#"path="/members/42""#), "{metrics}");
+        assert!(!metrics.contains(r#"path="/members-42""#), "{metrics}");
+    }
+
+    /// Fails the first `failures` runs.
+    struct FlakyProcess {
+        failures: usize,
+        runs: AtomicUsize,
+        restart_policy: RestartPolicy,
+    }
+
+    impl FlakyProcess {
+        fn leak(failures: usize, restart_policy: RestartPolicy) -> &'static FlakyProcess {
+            Box::leak(Box::new(FlakyProcess {
+                failures,
+                runs: AtomicUsize::new(0),
+                restart_policy,
+            }))
+        }
+    }
+
+    #[async_trait]
+    impl Process for FlakyProcess {
+        async fn pre_run(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn run(&self, _token: CancellationToken) -> anyhow::Result<()> {
+            if self.runs.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(anyhow!("crashed"));
+            }
+            Ok(())
+        }
+
+        fn restart_policy(&self) -> RestartPolicy {
+            self.restart_policy
+        }
+    }
+
+    const ON_FAILURE: RestartPolicy = RestartPolicy::OnFailure {
+        max_retries: 3,
+        backoff: Duration::from_millis(1),
+    };
+
+    #[tokio::test]
+    async fn failed_process_is_restarted() {
+        let process = FlakyProcess::leak(2, ON_FAILURE);
+
+        supervise_process(process, CancellationToken::new())
+            .await
+            .unwrap();
+
+        assert_eq!(process.runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn failed_process_is_not_restarted_beyond_max_retries() {
+        let process = FlakyProcess::leak(10, ON_FAILURE);
+
+        let result = supervise_process(process, CancellationToken::new()).await;
+
+        assert!(result.is_err());
+        assert_eq!(process.runs.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn failed_process_is_not_restarted_by_default() {
+        let process = FlakyProcess::leak(1, RestartPolicy::Never);
+
+        let result = supervise_process(process, CancellationToken::new()).await;
+
+        assert!(result.is_err());
+        assert_eq!(process.runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_process_is_not_restarted_after_shutdown() {
+        let process = FlakyProcess::leak(1, ON_FAILURE);
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let result = supervise_process(process, token).await;
+
+        assert!(result.is_err());
+        assert_eq!(process.runs.load(Ordering::SeqCst), 1);
+    }
+
+    /// Health check failing with the error when it's set.
+    struct FixedCheck(Option<&'static str>);
+
+    #[async_trait]
+    impl HealthCheck for FixedCheck {
+        async fn check(&self) -> anyhow::Result<()> {
+            match self.0 {
+                Some(error) => Err(anyhow!(error)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    fn started(server: Server<'_>) -> Server<'_> {
+        server.started.store(true, Ordering::Release);
+        server
+    }
+
+    #[tokio::test]
+    async fn readiness_reports_failed_checks() {
+        let server = started(
+            Server::new(test_config())
+                .health_check("cache", Arc::new(FixedCheck(None)))
+                .health_check("db", Arc::new(FixedCheck(Some("connection refused")))),
+        );
+
+        let response = call(&server, get_request("/readiness")).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = json_body(response).await;
+        assert_eq!(body["status"], json!("unavailable"));
+        let failed = body["failed_checks"].as_array().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0]["name"], json!("db"));
+        assert_eq!(failed[0]["error"], json!("connection refused"));
+
+        let response = call(&server, get_request("/liveness")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn readiness_is_ok_when_checks_pass() {
+        let server =
+            started(Server::new(test_config()).health_check("cache", Arc::new(FixedCheck(None))));
+
+        let response = call(&server, get_request("/readiness")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    /// Health check passing after the delay.
+    struct SlowCheck(Duration);
+
+    #[async_trait]
+    impl HealthCheck for SlowCheck {
+        async fn check(&self) -> anyhow::Result<()> {
+            tokio::time::sleep(self.0).await;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn verbose_readiness_reports_check_latencies() {
+        let server = started(
+            Server::new(test_config())
+                .health_check("cache", Arc::new(FixedCheck(None)))
+                .health_check("db", Arc::new(SlowCheck(Duration::from_millis(50)))),
+        );
+
+        let response = call(&server, get_request("/readiness?verbose=true")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["status"], json!("ok"));
+        let checks = body["checks"].as_array().unwrap();
+        assert_eq!(checks[0]["name"], json!("cache"));
+        assert_eq!(checks[0]["status"], json!("ok"));
+        assert_eq!(checks[1]["name"], json!("db"));
+        assert!(checks[1]["latency_ms"].as_f64().unwrap() >= 50.0, "{body}");
+        assert!(checks[0]["latency_ms"].as_f64().unwrap() < 50.0, "{body}");
+
+        // failed checks keep the failed status code
+        let server = started(
+            Server::new(test_config()).health_check("db", Arc::new(FixedCheck(Some("timeout")))),
+        );
+        let response = call(&server, get_request("/readiness?verbose=1")).await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = json_body(response).await;
+        assert_eq!(body["status"], json!("unavailable"));
+        assert_eq!(body["checks"][0]["error"], json!("timeout"));
+        assert!(body["checks"][0]["latency_ms"].is_number());
+    }
+
+    fn request_id_router() -> OpenApiRouter {
+        OpenApiRouter::new().route(
+            "/id",
+            get(|RequestId(request_id): RequestId| async move { request_id }),
+        )
+    }
+
+    #[tokio::test]
+    async fn response_carries_generated_request_id() {
+        let server = Server::new(test_config()).router(request_id_router());
+
+        let response = call(&server, get_request("/id")).await;
+
+        let request_id = response.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        // UUID v4
+        assert_eq!(request_id.len(), 36, "{request_id}");
+        assert_eq!(request_id.chars().nth(14), Some('4'), "{request_id}");
+        // the handler sees the same id
+        assert_eq!(text_body(response).await, request_id);
+    }
+
+    #[tokio::test]
+    async fn response_carries_received_request_id() {
+        let server = Server::new(test_config()).router(request_id_router());
+
+        let request = Request::get("/id")
+            .header(REQUEST_ID_HEADER, "req-42")
+            .body(Body::empty())
+            .unwrap();
+        let response = call(&server, request).await;
+
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "req-42");
+        assert_eq!(text_body(response).await, "req-42");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn liveness_is_served_over_unix_socket() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let path = std::env::temp_dir().join(format!("caslex-{}.sock", std::process::id()));
+        let mut config = test_config();
+        config.uds_path = Some(path.clone());
+        let server = Server::new(config);
+
+        let bound = server.bind().await.unwrap();
+        assert!(bound.addrs().app.is_none());
+        let shutdown = bound.shutdown_token();
+
+        let client = async {
+            let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+            stream
+                .write_all(
+                    b"GET /liveness HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            shutdown.cancel();
+            response
+        };
+        let (result, response) = tokio::join!(bound.serve(), client);
+        result.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        // the socket file is removed on shutdown
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn config_is_read_from_toml() {
+        let config = Config::from_reader(
+            r#"
+            host = "0.0.0.0"
+            port = 9000
+            request_timeout = "5s"
+            metrics_enabled = false
+            cors_allow_origins = ["https://example.com"]
+            metrics_buckets = [0.1, 0.5]
+            "#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(config.get_addr(), "0.0.0.0:9000");
+        assert_eq!(
+            Duration::from(config.request_timeout),
+            Duration::from_secs(5)
+        );
+        assert!(!config.metrics_enabled);
+        assert_eq!(config.cors_allow_origins, ["https://example.com"]);
+        assert_eq!(config.metrics_buckets, [0.1, 0.5]);
+        // defaults are kept for omitted keys
+        assert_eq!(config.metrics_path, "/metrics");
+    }
+
+    /// Appends the name to `x-layers` response header.
+    async fn record_layer(name: &'static str, req: Request, next: middleware::Next) -> Response {
+        let has_request_id = req.headers().contains_key(REQUEST_ID_HEADER);
+        let mut response = next.run(req).await;
+        assert!(has_request_id, "built-in layers run before {name}");
+        response
+            .headers_mut()
+            .append("x-layers", HeaderValue::from_static(name));
+        response
+    }
+
+    #[tokio::test]
+    async fn custom_layers_wrap_routes_inside_built_in_layers() {
+        let server = Server::new(test_config())
+            .router(request_id_router())
+            .layer(middleware::from_fn(|req, next| {
+                record_layer("inner", req, next)
+            }))
+            .layer(middleware::from_fn(|req, next| {
+                record_layer("outer", req, next)
+            }));
+
+        let response = call(&server, get_request("/id")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let layers: Vec<_> = response.headers().get_all("x-layers").iter().collect();
+        assert_eq!(layers, ["inner", "outer"]);
+    }
+
+    #[tokio::test]
+    async fn metrics_listener_is_not_bound_when_disabled() {
+        let server = Server::new(test_config());
+        let bound = server.bind().await.unwrap();
+        assert!(bound.addrs().app.is_some());
+        assert!(bound.addrs().metrics.is_none());
+        drop(bound);
+
+        let mut config = test_config();
+        config.metrics_enabled = true;
+        let server = Server::new(config);
+        let bound = server.bind().await.unwrap();
+        assert!(bound.addrs().metrics.is_some());
+    }
+
+    #[tokio::test]
+    async fn metrics_are_scraped_from_main_port() {
+        let mut config = test_config();
+        config.metrics_enabled = true;
+        let server = Server::new(config)
+            .router(request_id_router())
+            .expose_metrics_on_main_router(true);
+
+        let (result, metrics) = serve_with(&server, |addr, shutdown| async move {
+            reqwest::get(format!("http://{addr}/id")).await.unwrap();
+            let response = reqwest::get(format!("http://{addr}/metrics"))
+                .await
+                .unwrap();
+            shutdown.cancel();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.text().await.unwrap()
+        })
+        .await;
+        result.unwrap();
+
+        assert!(
+            metrics.contains(r#"http_requests_total{method="GET",path="/id",status="2xx"}"#),
+            "{metrics}"
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_are_not_served_on_main_port_by_default() {
+        let mut config = test_config();
+        config.metrics_enabled = true;
+        let server = Server::new(config);
+
+        let response = call(&server, get_request("/metrics")).await;
+
+        assert_ne!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn handler_panic_is_counted() {
+        let router = OpenApiRouter::new().route("/explode/{id}", get(panicking_handler));
+        let server = Server::new(test_config()).router(router);
+
+        let response = call(&server, get_request("/explode/1")).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(json_body(response).await["error"]["details"], json!("boom"));
+
+        let metrics = text_body(call(&server, get_request("/metrics")).await).await;
+        assert!(
+            metrics.contains(r#"http_handler_panics_total{path="/explode/{id}"} 1"#),
+            "{metrics}"
+        );
+    }
+
+    #[tokio::test]
+    async fn disabled_docs_hit_fallback() {
+        let mut config = test_config();
+        config.docs_enabled = false;
+        let server = Server::new(config);
+
+        let response = call(&server, get_request("/docs")).await;
+        assert_eq!(
+            json_body(response).await["error"]["kind"],
+            json!("method_not_found")
+        );
+
+        let response = call(&server, get_request("/openapi.json")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn openapi_servers_are_set_by_builder() {
+        let server =
+            Server::new(test_config()).openapi_servers(vec![utoipa::openapi::Server::new(
+                "https://api.example.com",
+            )]);
+
+        let spec = json_body(call(&server, get_request("/openapi.json")).await).await;
+
+        assert_eq!(spec["servers"], json!([{"url": "https://api.example.com"}]));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sighup_calls_reload_callback_without_shutdown() {
+        // keep the process alive if SIGHUP arrives before the server handler is installed
+        let _hangup = signal::unix::signal(signal::unix::SignalKind::hangup()).unwrap();
+
+        let reloaded = Arc::new(Notify::new());
+        let server = Server::new(test_config()).on_reload({
+            let reloaded = reloaded.clone();
+            move || reloaded.notify_one()
+        });
+
+        let (result, status) = serve_with(&server, |addr, shutdown| async move {
+            // the handler is installed by a spawned task, repeat the signal until it's handled
+            let sighup = async {
+                loop {
+                    std::process::Command::new("kill")
+                        .args(["-HUP", &std::process::id().to_string()])
+                        .status()
+                        .unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(5), async {
+                tokio::select! {
+                    _ = reloaded.notified() => {},
+                    _ = sighup => {},
+                }
+            })
+            .await
+            .expect("reload callback isn't called");
+
+            let status = reqwest::get(format!("http://{addr}/liveness"))
+                .await
+                .map(|response| response.status());
+            shutdown.cancel();
+            status
+        })
+        .await;
+        result.unwrap();
+
+        assert_eq!(status.unwrap(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn log_level_is_replaced_on_metrics_listener() {
+        use tracing_subscriber::{filter::LevelFilter, layer::Layer, reload};
+
+        let (filter, handle) = reload::Layer::new(LevelFilter::INFO);
+        log_level::set_log_level_reloader(move |level| {
+            Ok(handle.reload(level.parse::<LevelFilter>()?)?)
+        });
+        let subscriber = filter.with_subscriber(
+            tracing_subscriber::fmt()
+                .with_max_level(LevelFilter::TRACE)
+                .with_writer(std::io::sink)
+                .finish(),
+        );
+        let _guard = tracing::subscriber::set_default(subscriber);
+        assert!(!tracing::enabled!(tracing::Level::DEBUG));
+
+        let log_level_request = || {
+            Request::put(LOG_LEVEL_PATH)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"level": "debug"}"#))
+                .unwrap()
+        };
+
+        // admin endpoints aren't served by the application listener
+        let server = Server::new(test_config());
+        let response = call(&server, log_level_request()).await;
+        assert_ne!(response.status(), StatusCode::OK);
+        assert!(!tracing::enabled!(tracing::Level::DEBUG));
+
+        let response = metrics_router(&[])
+            .oneshot(log_level_request())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(tracing::enabled!(tracing::Level::DEBUG));
+    }
+
+    #[tokio::test]
+    async fn custom_fallback_handlers_are_used() {
+        let server = Server::new(test_config())
+            .router(OpenApiRouter::new().route("/items", get(|| async { "items" })))
+            .not_found_handler(|| async {
+                (
+                    StatusCode::NOT_FOUND,
+                    axum::response::Html("<h1>Lost?</h1>"),
+                )
+            })
+            .method_not_allowed_handler(|| async { (StatusCode::METHOD_NOT_ALLOWED, "nope") });
+
+        let response = call(&server, get_request("/missing")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(text_body(response).await, "<h1>Lost?</h1>");
+
+        let request = Request::delete("/items").body(Body::empty()).unwrap();
+        let response = call(&server, request).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(text_body(response).await, "nope");
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_concurrency_limit_are_shed() {
+        let entered = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(Notify::new());
+        let mut config = test_config();
+        config.max_concurrent_requests = 2;
+        let server = Server::new(config).router(OpenApiRouter::new().route(
+            "/wait",
+            get({
+                let entered = entered.clone();
+                let release = release.clone();
+                move || async move {
+                    entered.fetch_add(1, Ordering::SeqCst);
+                    release.notified().await;
+                    "done"
+                }
+            }),
+        ));
+        let router = server.setup_router();
+
+        let in_flight: Vec<_> = (0..2)
+            .map(|_| tokio::spawn(router.clone().oneshot(get_request("/wait"))))
+            .collect();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while entered.load(Ordering::SeqCst) < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let response = router.oneshot(get_request("/wait")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            json_body(response).await["error"]["kind"],
+            json!("overloaded")
+        );
+
+        release.notify_waiters();
+        for request in in_flight {
+            assert_eq!(request.await.unwrap().unwrap().status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_request_times_out() {
+        let mut config = test_config();
+        config.request_timeout = Duration::from_millis(100).into();
+        let server = Server::new(config).router(OpenApiRouter::new().route(
+            "/sleep",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                "done"
+            }),
+        ));
+
+        let response = call(&server, get_request("/sleep")).await;
+
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["kind"], json!("request_timeout"));
+        assert_eq!(body["error"]["details"], json!("request timed out"));
+    }
+
+    #[test]
+    fn upgrade_requests_are_detected() {
+        let upgrade = Request::get("/ws")
+            .header(header::CONNECTION, "keep-alive, Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .body(Body::empty())
+            .unwrap();
+        let connect = Request::connect("/ws").body(Body::empty()).unwrap();
+        let no_upgrade_header = Request::get("/ws")
+            .header(header::CONNECTION, "upgrade")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(is_upgrade_request(&upgrade));
+        assert!(is_upgrade_request(&connect));
+        assert!(!is_upgrade_request(&no_upgrade_header));
+        assert!(!is_upgrade_request(&get_request("/ws")));
+    }
+
+    #[tokio::test]
+    async fn upgraded_connection_outlives_request_timeout() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut config = test_config();
+        config.request_timeout = Duration::from_millis(100).into();
+        // the handshake is slower than the timeout, the upgraded connection echoes data
+        let server = Server::new(config).router(OpenApiRouter::new().route(
+            "/echo",
+            get(|mut req: Request| async move {
+                let on_upgrade = hyper::upgrade::on(&mut req);
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                tokio::spawn(async move {
+                    let mut io = hyper_util::rt::TokioIo::new(on_upgrade.await.unwrap());
+                    let mut buf = [0; 4];
+                    io.read_exact(&mut buf).await.unwrap();
+                    io.write_all(&buf).await.unwrap();
+                });
+                Response::builder()
+                    .status(StatusCode::SWITCHING_PROTOCOLS)
+                    .header(header::CONNECTION, "upgrade")
+                    .header(header::UPGRADE, "echo")
+                    .body(Body::empty())
+                    .unwrap()
+            }),
+        ));
+
+        let (result, echoed) = serve_with(&server, |addr, shutdown| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(
+                    b"GET /echo HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: echo\r\n\r\n",
+                )
+                .await
+                .unwrap();
+
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(stream.read_u8().await.unwrap());
+            }
+            assert!(head.starts_with(b"HTTP/1.1 101"), "{head:?}");
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            stream.write_all(b"ping").await.unwrap();
+            let mut echoed = [0; 4];
+            stream.read_exact(&mut echoed).await.unwrap();
+
+            shutdown.cancel();
+            echoed
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(&echoed, b"ping");
+    }
+
+    /// Sends the event once, then stays open without events.
+    struct OneEvent(Option<axum::response::sse::Event>);
+
+    impl futures_core::Stream for OneEvent {
+        type Item = Result<axum::response::sse::Event, std::convert::Infallible>;
+
+        fn poll_next(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Self::Item>> {
+            match self.0.take() {
+                Some(event) => std::task::Poll::Ready(Some(Ok(event))),
+                None => std::task::Poll::Pending,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn event_stream_sends_keep_alive_beyond_request_timeout() {
+        use http_body_util::BodyExt;
+
+        use crate::sse::EventStream;
+
+        let mut config = test_config();
+        config.request_timeout = Duration::from_millis(100).into();
+        let server = Server::new(config).router(OpenApiRouter::new().route(
+            "/events",
+            get(|| async {
+                EventStream::new(OneEvent(Some(
+                    axum::response::sse::Event::default().data("hello"),
+                )))
+                .keep_alive(Duration::from_millis(50))
+            }),
+        ));
+
+        let response = call(&server, get_request("/events")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+
+        let started = Instant::now();
+        let mut body = response.into_body();
+        let mut chunks = Vec::new();
+        // keep reading past the request timeout
+        while started.elapsed() < Duration::from_millis(300) {
+            let frame = tokio::time::timeout(Duration::from_secs(1), body.frame())
+                .await
+                .expect("stream is idle")
+                .expect("stream is closed")
+                .unwrap();
+            if let Ok(data) = frame.into_data() {
+                chunks.push(String::from_utf8(data.to_vec()).unwrap());
+            }
+        }
+
+        assert_eq!(chunks[0], "data: hello\n\n");
+        assert!(chunks.len() > 2, "{chunks:?}");
+        assert!(
+            chunks[1..].iter().all(|chunk| chunk == ":\n\n"),
+            "{chunks:?}"
+        );
+    }
+
+    #[derive(Clone)]
+    struct AppState {
+        greeting: &'static str,
+        count: usize,
+    }
+
+    impl axum::extract::FromRef<AppState> for usize {
+        fn from_ref(state: &AppState) -> Self {
+            state.count
+        }
+    }
+
+    #[tokio::test]
+    async fn router_state_and_sub_states_are_shared() {
+        let router = OpenApiRouter::new()
+            .route(
+                "/greeting",
+                get(|State(state): State<AppState>| async move { state.greeting }),
+            )
+            .route(
+                "/count",
+                get(|State(count): State<usize>| async move { count.to_string() }),
+            );
+        let server = Server::new(test_config()).router_with_state(
+            router,
+            AppState {
+                greeting: "hello",
+                count: 3,
+            },
+        );
+
+        let greeting = call(&server, get_request("/greeting")).await;
+        let count = call(&server, get_request("/count")).await;
+
+        assert_eq!(text_body(greeting).await, "hello");
+        assert_eq!(text_body(count).await, "3");
+    }
+
+    /// Returns `Content-Encoding` of responses to `/small` and `/large` requests accepting
+    /// `encoding`.
+    async fn response_encodings(config: Config, encoding: &str) -> [Option<String>; 2] {
+        let server = Server::new(config).router(
+            OpenApiRouter::new()
+                .route("/small", get(|| async { "small" }))
+                .route("/large", get(|| async { "large ".repeat(1024) })),
+        );
+        let router = server.setup_router();
+
+        let mut encodings = [None, None];
+        for (uri, content_encoding) in ["/small", "/large"].into_iter().zip(&mut encodings) {
+            let request = Request::get(uri)
+                .header(header::ACCEPT_ENCODING, encoding)
+                .body(Body::empty())
+                .unwrap();
+            let response = router.clone().oneshot(request).await.unwrap();
+            *content_encoding = response
+                .headers()
+                .get(header::CONTENT_ENCODING)
+                .map(|v| v.to_str().unwrap().to_owned());
+        }
+        encodings
+    }
+
+    #[tokio::test]
+    async fn responses_below_min_size_are_not_compressed() {
+        let mut config = test_config();
+        config.compression_min_size = 1024;
+
+        let encodings = response_encodings(config, "gzip").await;
+
+        assert_eq!(encodings, [None, Some("gzip".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn compression_follows_algorithms_and_toggle() {
+        let mut config = test_config();
+        config.compression_algorithms = vec![CompressionAlgorithm::Deflate];
+        assert_eq!(
+            response_encodings(config.clone(), "gzip").await,
+            [None, None]
+        );
+        assert_eq!(
+            response_encodings(config, "deflate").await,
+            [None, Some("deflate".to_owned())]
+        );
+
+        let mut config = test_config();
+        config.compression_enabled = false;
+        assert_eq!(response_encodings(config, "gzip").await, [None, None]);
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gzip_request(body: Vec<u8>) -> Request {
+        Request::post("/echo")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn gzip_request_body_is_decompressed() {
+        let mut config = test_config();
+        config.request_decompression_enabled = true;
+        let server = Server::new(config).router(echo_router());
+
+        let response = call(&server, gzip_request(gzip(b"hello, gzip"))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(text_body(response).await, "hello, gzip");
+    }
+
+    #[tokio::test]
+    async fn malformed_and_oversized_gzip_bodies_are_rejected() {
+        let mut config = test_config();
+        config.request_decompression_enabled = true;
+        config.max_body_size = 1024;
+        let server = Server::new(config).router(echo_router());
+
+        let response = call(&server, gzip_request(b"not gzip".to_vec())).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            json_body(response).await["error"]["kind"],
+            json!("invalid_body")
+        );
+
+        // small compressed body inflating beyond the body size limit
+        let bomb = gzip(&[0; 64 * 1024]);
+        assert!(bomb.len() < 1024);
+        let response = call(&server, gzip_request(bomb)).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[utoipa::path(get, path = "/ping", responses((status = 200, description = "pong")))]
+    async fn ping() -> &'static str {
+        "pong"
+    }
+
+    fn base_path_server(args: &[&str]) -> Server<'static> {
+        let mut config = Config::try_parse_from(
+            ["caslex", "--port", "0", "--base-path", "/api/v1/"]
+                .iter()
+                .chain(args),
+        )
+        .unwrap();
+        config.metrics_enabled = false;
+        Server::new(config).router(OpenApiRouter::new().routes(routes!(ping)))
+    }
+
+    #[tokio::test]
+    async fn routes_and_docs_are_served_under_base_path() {
+        let server = base_path_server(&[]);
+
+        let response = call(&server, get_request("/api/v1/ping")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(text_body(response).await, "pong");
+        let response = call(&server, get_request("/ping")).await;
+        assert_eq!(
+            json_body(response).await["error"]["kind"],
+            json!("method_not_found")
+        );
+
+        let response = call(&server, get_request("/api/v1/docs")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = call(&server, get_request("/api/v1/openapi.json")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let spec = json_body(response).await;
+        assert!(spec["paths"].get("/api/v1/ping").is_some());
+        assert!(spec["paths"].get("/ping").is_none());
+
+        // probes stay at the root unless prefixed explicitly
+        let response = call(&server, get_request("/liveness")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn probes_are_served_under_base_path_when_enabled() {
+        let server = base_path_server(&["--base-path-probes"]);
+
+        let response = call(&server, get_request("/api/v1/liveness")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = call(&server, get_request("/liveness")).await;
+        assert_eq!(
+            json_body(response).await["error"]["kind"],
+            json!("method_not_found")
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Contains server-sent events response.
+//!
+//! [`EventStream`] wraps axum [`Sse`] response, it sends keep-alive comments every 15 seconds by
+//! default, so proxies don't close idle streams. The stream is polled inside `sse_stream` span,
+//! a child of the request span, which records the stream lifetime and the sent events count as
+//! `sse.events` field.
+//!
+//! Streams aren't bounded by the server request timeout, it applies to producing the response
+//! head only.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::{convert::Infallible, time::Duration};
+//!
+//! use axum::response::{IntoResponse, sse::Event};
+//! use caslex::sse::EventStream;
+//! use futures_core::Stream;
+//!
+//! fn events(
+//!     stream: impl Stream<Item = Result<Event, Infallible>> + Send + 'static,
+//! ) -> impl IntoResponse {
+//!     EventStream::new(stream).keep_alive(Duration::from_secs(5))
+//! }
+//! ```
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    BoxError,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures_core::Stream;
+use tracing::Span;
+
+/// Default interval of keep-alive comments.
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Define server-sent events response.
+pub struct EventStream<S> {
+    stream: S,
+    keep_alive: Duration,
+}
+
+impl<S, E> EventStream<S>
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    /// Creates response sending events of the stream.
+    pub fn new(stream: S) -> Self {
+        EventStream {
+            stream,
+            keep_alive: DEFAULT_KEEP_ALIVE,
+        }
+    }
+
+    /// Sets interval of keep-alive comments.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+}
+
+impl<S, E> IntoResponse for EventStream<S>
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    fn into_response(self) -> Response {
+        let stream = TracedStream {
+            stream: Box::pin(self.stream),
+            span: tracing::trace_span!("sse_stream", sse.events = 0),
+            events: 0,
+        };
+
+        Sse::new(stream)
+            .keep_alive(KeepAlive::new().interval(self.keep_alive))
+            .into_response()
+    }
+}
+
+/// Polls the stream inside the span and counts sent events.
+struct TracedStream<S> {
+    stream: Pin<Box<S>>,
+    span: Span,
+    events: u64,
+}
+
+impl<S, E> Stream for TracedStream<S>
+where
+    S: Stream<Item = Result<Event, E>>,
+{
+    type Item = Result<Event, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let _entered = this.span.enter();
+
+        let poll = this.stream.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(_))) = poll {
+            this.events += 1;
+            this.span.record("sse.events", this.events);
+        }
+
+        poll
+    }
+}
@@ -1,11 +1,21 @@
-use axum::Router;
+use axum::{Router, routing::get};
+use bytes::Bytes;
+use http::{
+    HeaderMap,
+    header::{ACCEPT, CONTENT_TYPE},
+};
 use utoipa::{
     Modify, OpenApi,
-    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    openapi::{
+        Info, Server,
+        security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme},
+    },
 };
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_scalar::{Scalar, Servable as ScalarServable};
 
+use crate::server::DocsUi;
+
 #[derive(OpenApi)]
 #[openapi(
     modifiers(&SecurityAddon),
@@ -33,10 +43,330 @@ impl Modify for SecurityAddon {
     }
 }
 
-pub fn get_openapi_router(router: OpenApiRouter, docs_url: String) -> Router {
-    let (router, api) = OpenApiRouter::with_openapi(ApiDoc::openapi())
+/// Adds user security schemes, schemes named as the built-in ones replace them.
+struct CustomSecurityAddon<'a> {
+    schemes: &'a [(String, SecurityScheme)],
+    security: Option<&'a Vec<SecurityRequirement>>,
+}
+
+impl Modify for CustomSecurityAddon<'_> {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for (name, scheme) in self.schemes {
+            components.add_security_scheme(name, scheme.clone());
+        }
+
+        if let Some(security) = self.security {
+            openapi.security = Some(security.clone());
+        }
+    }
+}
+
+/// Define OpenAPI docs routes options.
+pub(crate) struct DocsOptions {
+    pub(crate) docs_url: String,
+    pub(crate) docs_enabled: bool,
+    pub(crate) docs_ui: DocsUi,
+    pub(crate) openapi_json_enabled: bool,
+    pub(crate) openapi_json_path: String,
+    pub(crate) info: Option<Info>,
+    pub(crate) servers: Option<Vec<Server>>,
+    pub(crate) security_schemes: Vec<(String, SecurityScheme)>,
+    pub(crate) security: Option<Vec<SecurityRequirement>>,
+}
+
+pub fn get_openapi_router(router: OpenApiRouter, opts: &DocsOptions) -> Router {
+    let mut openapi = ApiDoc::openapi();
+    if let Some(info) = opts.info.clone() {
+        openapi.info = info;
+    }
+    if let Some(servers) = opts.servers.clone() {
+        openapi.servers = Some(servers);
+    }
+    CustomSecurityAddon {
+        schemes: &opts.security_schemes,
+        security: opts.security.as_ref(),
+    }
+    .modify(&mut openapi);
+
+    let (mut router, api) = OpenApiRouter::with_openapi(openapi)
         .merge(router)
         .split_for_parts();
 
-    router.merge(Scalar::with_url(docs_url, api))
+    if opts.openapi_json_enabled {
+        let json = Bytes::from(api.to_json().unwrap_or_default());
+        let yaml = Bytes::from(api.to_yaml().unwrap_or_default());
+        router = router.route(
+            &opts.openapi_json_path,
+            get(move |headers: HeaderMap| async move {
+                // YAML is served on request, JSON is the default
+                let wants_yaml = headers
+                    .get(ACCEPT)
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.contains("yaml"));
+                if wants_yaml {
+                    ([(CONTENT_TYPE, "application/yaml")], yaml)
+                } else {
+                    ([(CONTENT_TYPE, "application/json")], json)
+                }
+            }),
+        );
+    }
+
+    if !opts.docs_enabled {
+        return router;
+    }
+
+    // UIs other than Scalar load the spec from a separate path under the docs path
+    #[allow(unused_variables)]
+    let spec_url = format!("{}/openapi.json", opts.docs_url.trim_end_matches('/'));
+    match opts.docs_ui {
+        DocsUi::Scalar => router.merge(Scalar::with_url(opts.docs_url.clone(), api)),
+        #[cfg(feature = "swagger-ui")]
+        DocsUi::SwaggerUi => router
+            .merge(utoipa_swagger_ui::SwaggerUi::new(opts.docs_url.clone()).url(spec_url, api)),
+        #[cfg(feature = "rapidoc")]
+        DocsUi::RapiDoc => router.merge(
+            utoipa_rapidoc::RapiDoc::with_openapi(spec_url, api).path(opts.docs_url.clone()),
+        ),
+        DocsUi::None => router,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{Request, StatusCode};
+    use tower::ServiceExt;
+    use utoipa_axum::routes;
+
+    use super::*;
+
+    #[utoipa::path(get, path = "/ping", responses((status = 200, description = "pong")))]
+    async fn ping() {}
+
+    fn options() -> DocsOptions {
+        DocsOptions {
+            docs_url: "/docs".to_owned(),
+            docs_enabled: true,
+            docs_ui: DocsUi::Scalar,
+            openapi_json_enabled: true,
+            openapi_json_path: "/openapi.json".to_owned(),
+            info: None,
+            servers: None,
+            security_schemes: vec![],
+            security: None,
+        }
+    }
+
+    async fn status(router: &Router, uri: &str) -> StatusCode {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+
+        router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    /// Returns content type and body of the spec served at `uri`.
+    async fn fetch_spec(router: &Router, uri: &str, accept: &str) -> (String, Bytes) {
+        let request = Request::get(uri)
+            .header(ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let content_type = response.headers()[CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        (content_type, body)
+    }
+
+    /// Returns spec of the router with `/ping` route served with the options.
+    async fn served_spec(opts: &DocsOptions) -> utoipa::openapi::OpenApi {
+        let router = get_openapi_router(OpenApiRouter::new().routes(routes!(ping)), opts);
+        let (_, body) = fetch_spec(&router, &opts.openapi_json_path, "application/json").await;
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn docs_routes_follow_flags() {
+        for (docs_enabled, openapi_json_enabled) in
+            [(true, true), (true, false), (false, true), (false, false)]
+        {
+            let opts = DocsOptions {
+                docs_enabled,
+                openapi_json_enabled,
+                ..options()
+            };
+            let router = get_openapi_router(OpenApiRouter::new(), &opts);
+
+            let expected = |enabled| match enabled {
+                true => StatusCode::OK,
+                false => StatusCode::NOT_FOUND,
+            };
+            assert_eq!(status(&router, "/docs").await, expected(docs_enabled));
+            assert_eq!(
+                status(&router, "/openapi.json").await,
+                expected(openapi_json_enabled)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn spec_is_served_as_json() {
+        let api = served_spec(&options()).await;
+
+        assert!(api.paths.paths.contains_key("/ping"));
+    }
+
+    #[tokio::test]
+    async fn spec_is_served_at_custom_path_and_as_yaml() {
+        let opts = DocsOptions {
+            openapi_json_path: "/spec".to_owned(),
+            ..options()
+        };
+        let router = get_openapi_router(OpenApiRouter::new().routes(routes!(ping)), &opts);
+
+        let (content_type, body) = fetch_spec(&router, "/spec", "application/json").await;
+        assert_eq!(content_type, "application/json");
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(json["openapi"].as_str().unwrap().starts_with("3."));
+
+        let (content_type, body) = fetch_spec(&router, "/spec", "application/yaml").await;
+        assert_eq!(content_type, "application/yaml");
+        assert!(std::str::from_utf8(&body).unwrap().contains("/ping:"));
+
+        assert_eq!(
+            status(&router, "/openapi.json").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn default_server_is_localhost() {
+        let api = served_spec(&options()).await;
+
+        let servers = api.servers.unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].url, "http://localhost:9000");
+    }
+
+    #[tokio::test]
+    async fn servers_and_info_are_overridden() {
+        let opts = DocsOptions {
+            info: Some(
+                utoipa::openapi::InfoBuilder::new()
+                    .title("orders")
+                    .version("1.2.3")
+                    .description(Some("Orders API"))
+                    .build(),
+            ),
+            servers: Some(vec![
+                Server::new("https://api.example.com"),
+                Server::new("https://staging.example.com"),
+            ]),
+            ..options()
+        };
+
+        let api = served_spec(&opts).await;
+
+        let urls: Vec<_> = api.servers.unwrap().into_iter().map(|s| s.url).collect();
+        assert_eq!(
+            urls,
+            ["https://api.example.com", "https://staging.example.com"]
+        );
+        assert_eq!(api.info.title, "orders");
+        assert_eq!(api.info.version, "1.2.3");
+        assert_eq!(api.info.description.as_deref(), Some("Orders API"));
+    }
+
+    #[tokio::test]
+    async fn each_docs_ui_is_mounted_at_docs_url() {
+        let uis = [
+            DocsUi::Scalar,
+            #[cfg(feature = "swagger-ui")]
+            DocsUi::SwaggerUi,
+            #[cfg(feature = "rapidoc")]
+            DocsUi::RapiDoc,
+        ];
+
+        for docs_ui in uis {
+            let opts = DocsOptions {
+                docs_ui,
+                ..options()
+            };
+            let router = get_openapi_router(OpenApiRouter::new(), &opts);
+
+            // Swagger UI redirects to the path with the trailing slash
+            let docs_status = status(&router, "/docs").await;
+            assert!(
+                docs_status.is_success() || docs_status.is_redirection(),
+                "{docs_status}"
+            );
+            assert_eq!(status(&router, "/openapi.json").await, StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn no_docs_ui_serves_only_spec() {
+        let opts = DocsOptions {
+            docs_ui: DocsUi::None,
+            ..options()
+        };
+        let router = get_openapi_router(OpenApiRouter::new(), &opts);
+
+        assert_eq!(status(&router, "/docs").await, StatusCode::NOT_FOUND);
+        assert_eq!(status(&router, "/openapi.json").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn custom_security_schemes_are_merged() {
+        use utoipa::openapi::security::{ApiKey, ApiKeyValue};
+
+        let opts = DocsOptions {
+            security_schemes: vec![(
+                "api_key".to_owned(),
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+            )],
+            security: Some(vec![SecurityRequirement::new(
+                "api_key",
+                Vec::<String>::new(),
+            )]),
+            ..options()
+        };
+
+        let api = serde_json::to_value(served_spec(&opts).await).unwrap();
+
+        assert_eq!(
+            api["components"]["securitySchemes"],
+            serde_json::json!({
+                "token": {"type": "http", "scheme": "bearer", "bearerFormat": "JWT"},
+                "api_key": {"type": "apiKey", "in": "header", "name": "X-Api-Key"},
+            })
+        );
+        assert_eq!(api["security"], serde_json::json!([{"api_key": []}]));
+    }
+
+    #[tokio::test]
+    async fn built_in_security_scheme_is_overridden() {
+        let opts = DocsOptions {
+            security_schemes: vec![(
+                "token".to_owned(),
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).build()),
+            )],
+            ..options()
+        };
+
+        let api = serde_json::to_value(served_spec(&opts).await).unwrap();
+
+        assert_eq!(
+            api["components"]["securitySchemes"],
+            serde_json::json!({"token": {"type": "http", "scheme": "basic"}})
+        );
+    }
 }
@@ -1,20 +1,40 @@
 //! Contains trace layer for HTTP server.
+//!
+//! The `http_request` span is linked to the trace of the caller when the request carries context
+//! propagated in headers (`traceparent` with the default propagator). The `http.path` field is
+//! the matched route template, `<unmatched>` when no route matched, same as the metrics `path`
+//! label.
 
-use std::{fmt::Display, time::Duration};
+use std::{fmt::Display, sync::Arc, time::Duration};
 
-use axum::{Router, body::HttpBody, extract::MatchedPath};
+use axum::{Router, body::HttpBody};
 use axum_core::body::Body;
+use http::{HeaderMap, HeaderName};
+use opentelemetry::{global, propagation::Extractor, trace::TraceContextExt};
 use tower_http::{classify::ServerErrorsFailureClass, trace::TraceLayer};
 use tracing::{Level, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::extractors;
+use crate::{
+    extractors,
+    middlewares::{client_ip::client_ip, request_id::REQUEST_ID_HEADER},
+};
 
-/// Add tracing/logging middleware.
-pub fn with_trace_layer(router: Router) -> Router {
+/// Placeholder recorded instead of redacted query param values.
+pub(crate) const REDACTED: &str = "***";
+
+/// Add tracing/logging middleware, requests to `exclude_paths` routes don't produce spans,
+/// values of `redact_query_params` query params are replaced with `***`.
+pub fn with_trace_layer(
+    router: Router,
+    exclude_paths: Arc<[String]>,
+    redact_query_params: Arc<[String]>,
+) -> Router {
     router.layer(
         TraceLayer::new_for_http()
-            .make_span_with(make_span_with_handler)
+            .make_span_with(move |request: &axum_core::extract::Request<Body>| {
+                make_span_with_handler(request, &exclude_paths, &redact_query_params)
+            })
             .on_request(())
             .on_body_chunk(())
             .on_eos(())
@@ -37,13 +57,18 @@ impl Display for OtelStatusCode {
     }
 }
 
-fn make_span_with_handler(request: &axum_core::extract::Request<Body>) -> Span {
-    let matched_path = request
-        .extensions()
-        .get::<MatchedPath>()
-        .map(MatchedPath::as_str);
+fn make_span_with_handler(
+    request: &axum_core::extract::Request<Body>,
+    exclude_paths: &[String],
+    redact_query_params: &[String],
+) -> Span {
+    let matched_path = extractors::matched_route(request);
+
+    if exclude_paths.iter().any(|v| v == matched_path) {
+        return Span::none();
+    }
 
-    tracing::span!(
+    let span = tracing::span!(
         Level::TRACE,
         "http_request",
         otel.kind = "server",
@@ -51,13 +76,63 @@ fn make_span_with_handler(request: &axum_core::extract::Request<Body>) -> Span {
         otel.status_message = tracing::field::Empty,
         http.method = ?request.method(),
         http.path = matched_path,
-        http.query_params = request.uri().query(),
+        http.query_params = request
+            .uri()
+            .query()
+            .map(|query| redact_query(query, redact_query_params)),
         http.status_code = tracing::field::Empty,
         http.request_size = request.body().size_hint().lower(),
         http.response_size = tracing::field::Empty,
+        http.request_body = tracing::field::Empty,
+        http.response_body = tracing::field::Empty,
+        request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok()),
         user_agent = extractors::user_agent(request),
+        client.ip = client_ip(request).map(tracing::field::display),
         http.request_headers = ?request.headers(),
-    )
+    );
+
+    let parent_cx = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    if parent_cx.span().span_context().is_valid() {
+        span.set_parent(parent_cx);
+    }
+
+    span
+}
+
+/// Reads context propagated by the caller from request headers.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+/// Replaces values of `params` in the query, order and the rest of params are kept as is.
+fn redact_query(query: &str, params: &[String]) -> String {
+    if params.is_empty() {
+        return query.to_owned();
+    }
+
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if params.iter().any(|p| p.eq_ignore_ascii_case(key)) => {
+                format!("{key}={REDACTED}")
+            }
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
 }
 
 fn on_response_handler(
@@ -96,3 +171,300 @@ fn span_err(span: &Span, message: String) {
     span.set_attribute("otel.status_code", OtelStatusCode::Error.to_string());
     span.set_attribute("otel.status_message", message);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::routing::get;
+    use tower::ServiceExt;
+    use tracing::{
+        Event, Metadata, Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+    use tracing_core::span::Current;
+
+    use super::*;
+
+    /// Span name and recorded fields.
+    #[derive(Clone, Debug, Default)]
+    struct RecordedSpan {
+        name: &'static str,
+        metadata: Option<&'static Metadata<'static>>,
+        fields: Vec<(&'static str, String)>,
+    }
+
+    impl Visit for RecordedSpan {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.fields.push((field.name(), format!("{value:?}")));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.fields.push((field.name(), value.to_owned()));
+        }
+    }
+
+    /// Records created spans and fields recorded later, span id is its index in the list plus one.
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans: Arc<Mutex<Vec<RecordedSpan>>>,
+        entered: Arc<Mutex<Vec<Id>>>,
+    }
+
+    impl SpanRecorder {
+        fn spans(&self) -> Vec<RecordedSpan> {
+            self.spans.lock().unwrap().clone()
+        }
+
+        fn index(span: &Id) -> usize {
+            usize::try_from(span.into_u64() - 1).unwrap()
+        }
+    }
+
+    impl Subscriber for SpanRecorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut recorded = RecordedSpan {
+                name: span.metadata().name(),
+                metadata: Some(span.metadata()),
+                ..RecordedSpan::default()
+            };
+            span.record(&mut recorded);
+
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(recorded);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            values.record(&mut self.spans.lock().unwrap()[Self::index(span)]);
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock().unwrap().push(span.clone());
+        }
+
+        fn exit(&self, _span: &Id) {
+            self.entered.lock().unwrap().pop();
+        }
+
+        fn current_span(&self) -> Current {
+            let entered = self.entered.lock().unwrap();
+            let spans = self.spans.lock().unwrap();
+
+            match entered
+                .last()
+                .and_then(|id| Some((id.clone(), spans[Self::index(id)].metadata?)))
+            {
+                Some((id, metadata)) => Current::new(id, metadata),
+                None => Current::none(),
+            }
+        }
+    }
+
+    fn traced_router(exclude_paths: &[&str], redact_query_params: &[&str]) -> Router {
+        let router = Router::new()
+            .route("/liveness", get(|| async { "ok" }))
+            .route("/users/{id}", get(|| async { "user" }));
+
+        with_trace_layer(
+            router,
+            exclude_paths.iter().map(|v| (*v).to_owned()).collect(),
+            redact_query_params
+                .iter()
+                .map(|v| (*v).to_owned())
+                .collect(),
+        )
+    }
+
+    fn get_request(uri: &str) -> axum_core::extract::Request {
+        axum_core::extract::Request::get(uri)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// Calls the router and returns spans created while handling the request.
+    async fn request_spans(
+        router: Router,
+        request: axum_core::extract::Request,
+    ) -> Vec<RecordedSpan> {
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        router.oneshot(request).await.unwrap();
+
+        recorder
+            .spans()
+            .into_iter()
+            .filter(|span| span.name == "http_request")
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn excluded_path_produces_no_span() {
+        let router = traced_router(&["/liveness"], &[]);
+
+        assert!(
+            request_spans(router.clone(), get_request("/liveness"))
+                .await
+                .is_empty()
+        );
+        assert_eq!(
+            request_spans(router, get_request("/users/1")).await.len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn client_ip_is_recorded() {
+        let mut request = get_request("/users/1");
+        request
+            .extensions_mut()
+            .insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                [192, 0, 2, 1],
+                40000,
+            ))));
+
+        let spans = request_spans(traced_router(&[], &[]), request).await;
+
+        assert!(
+            spans[0]
+                .fields
+                .contains(&("client.ip", "192.0.2.1".to_owned())),
+            "{spans:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn sensitive_query_params_are_redacted() {
+        let router = traced_router(&[], &["token", "api_key"]);
+
+        let spans = request_spans(router, get_request("/users/1?token=abc&x=1")).await;
+
+        assert!(
+            spans[0]
+                .fields
+                .contains(&("http.query_params", "token=***&x=1".to_owned())),
+            "{spans:?}"
+        );
+    }
+
+    #[test]
+    fn redacted_query_keeps_order_and_other_params() {
+        let params = ["token".to_owned(), "api_key".to_owned()];
+
+        assert_eq!(
+            redact_query("a=1&API_KEY=k&flag&token=t&token=u&b=", &params),
+            "a=1&API_KEY=***&flag&token=***&token=***&b="
+        );
+        assert_eq!(redact_query("token=t", &[]), "token=t");
+    }
+
+    #[tokio::test]
+    async fn captured_bodies_are_recorded() {
+        use axum::{Json, middleware, routing::post};
+
+        use crate::middlewares::body_capture::{BodyCapture, body_capture_handler};
+
+        let router = Router::new()
+            .route(
+                "/webhook",
+                post(|Json(body): Json<serde_json::Value>| async move {
+                    Json(serde_json::json!({ "received": body["event"] }))
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                BodyCapture::new(1024),
+                body_capture_handler,
+            ));
+        let router = with_trace_layer(router, Arc::new([]), Arc::new([]));
+
+        let request = axum_core::extract::Request::post("/webhook")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"event":"paid"}"#))
+            .unwrap();
+        let spans = request_spans(router, request).await;
+
+        let fields = &spans[0].fields;
+        assert!(
+            fields.contains(&("http.request_body", r#"{"event":"paid"}"#.to_owned())),
+            "{fields:?}"
+        );
+        // the handler read the body passed further
+        assert!(
+            fields.contains(&("http.response_body", r#"{"received":"paid"}"#.to_owned())),
+            "{fields:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn propagated_context_is_parent_of_request_span() {
+        use opentelemetry::trace::{SpanId, TraceId, TracerProvider};
+        use opentelemetry_sdk::{
+            propagation::TraceContextPropagator,
+            trace::{InMemorySpanExporter, SdkTracerProvider},
+        };
+        use tracing_subscriber::layer::SubscriberExt;
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("caslex-test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let request = axum_core::extract::Request::get("/users/1")
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(Body::empty())
+            .unwrap();
+        traced_router(&[], &[]).oneshot(request).await.unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans
+            .iter()
+            .find(|span| span.name == "http_request")
+            .unwrap();
+        assert_eq!(
+            span.span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+        assert_eq!(
+            span.parent_span_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn path_is_recorded_as_route_template_or_unmatched() {
+        let router = traced_router(&[], &[]);
+
+        let matched = request_spans(router.clone(), get_request("/users/42")).await;
+        let unmatched = request_spans(router, get_request("/users-42")).await;
+
+        assert!(
+            matched[0]
+                .fields
+                .contains(&("http.path", "/users/{id}".to_owned())),
+            "{matched:?}"
+        );
+        assert!(
+            unmatched[0]
+                .fields
+                .contains(&("http.path", extractors::UNMATCHED_ROUTE.to_owned())),
+            "{unmatched:?}"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Run with
+//!
+//! ```not_rust
+//! LOG_LEVEL=trace OTEL_LOG_LEVEL=trace cargo run -p example-http-app-state
+//! ```
+
+#![allow(clippy::exit)]
+
+use std::{env, sync::Arc};
+
+use axum::extract::{FromRef, State};
+use caslex::{
+    errors::DefaultError,
+    server::{Config, Server},
+};
+use caslex_extra::{cleanup_resources, setup_application, storages::postgres_pool};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+static SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Shared dependencies, handlers extract the fields thanks to `FromRef`.
+#[derive(Clone, FromRef)]
+struct AppState {
+    pool: deadpool_postgres::Pool,
+    http_client: reqwest::Client,
+}
+
+#[tokio::main]
+async fn main() {
+    setup_application(SERVICE_NAME);
+
+    let pg_config = postgres_pool::Config::parse();
+    let pool = postgres_pool::build_pool_from_config(pg_config)
+        .await
+        .unwrap();
+
+    let state = AppState {
+        pool: pool.clone(),
+        http_client: reqwest::Client::new(),
+    };
+
+    let router = OpenApiRouter::new()
+        .routes(routes!(db_handler))
+        .routes(routes!(upstream_handler));
+
+    let server_config = Config::parse();
+    let result = Server::new(server_config)
+        .health_check("postgres", Arc::new(pool))
+        .router_with_state(router, state)
+        .run()
+        .await;
+
+    cleanup_resources();
+
+    match result {
+        Ok(_) => std::process::exit(0),
+        Err(e) => {
+            println!("failed to start server: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/db",
+    responses(
+        (status = 200, description = "Ok")
+    )
+)]
+async fn db_handler(State(pool): State<deadpool_postgres::Pool>) -> Result<String, DefaultError> {
+    let conn = pool.get().await?;
+
+    let row = conn.query_one("select 1 + 1", &[]).await?;
+    let two: i32 = row.try_get(0)?;
+
+    Ok(two.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/upstream",
+    responses(
+        (status = 200, description = "Ok")
+    )
+)]
+async fn upstream_handler(
+    State(http_client): State<reqwest::Client>,
+) -> Result<String, DefaultError> {
+    let url =
+        env::var("UPSTREAM_URL").unwrap_or_else(|_| "http://localhost:9007/liveness".to_owned());
+
+    let status = http_client
+        .get(url)
+        .send()
+        .await
+        .map_err(anyhow::Error::from)?
+        .status();
+
+    Ok(status.to_string())
+}
@@ -0,0 +1,46 @@
+//! Run with
+//!
+//! ```not_rust
+//! cargo run -p example-http-custom-layers
+//! ```
+
+#![allow(clippy::exit)]
+
+use caslex::server::{Config, Server};
+use http::{HeaderName, HeaderValue};
+use tower_http::set_header::SetResponseHeaderLayer;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+#[utoipa::path(
+    get,
+    path = "/",
+    responses(
+        (status = 200, description = "Ok")
+    )
+)]
+async fn handler() -> &'static str {
+    "Hello, World!"
+}
+
+#[tokio::main]
+async fn main() {
+    let config = Config::parse();
+    let router = OpenApiRouter::new().routes(routes!(handler));
+
+    let result = Server::new(config)
+        .router(router)
+        // Add custom header to every response
+        .layer(SetResponseHeaderLayer::overriding(
+            HeaderName::from_static("x-powered-by"),
+            HeaderValue::from_static("caslex"),
+        ))
+        .run()
+        .await;
+
+    match result {
+        Ok(_) => std::process::exit(0),
+        Err(_) => {
+            std::process::exit(1);
+        }
+    }
+}
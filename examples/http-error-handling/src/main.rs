@@ -11,7 +11,7 @@ use std::{env, error::Error as StdError, fmt, fmt::Display};
 use anyhow::anyhow;
 use axum::http::StatusCode;
 use caslex::{
-    errors::{AppError, AppJson, DefaultError},
+    errors::{AppError, AppJson, AppPath, DefaultError, ErrorInfo, ValidatedJson},
     server::{Config, Server},
 };
 use caslex_extra::observability::{setup_opentelemetry, unset_opentelemetry};
@@ -30,6 +30,8 @@ async fn main() {
     let router = OpenApiRouter::new()
         .routes(routes!(custom_error_handler))
         .routes(routes!(validation_error_handler))
+        .routes(routes!(path_error_handler))
+        .routes(routes!(batch_error_handler))
         .routes(routes!(other_error_handler));
 
     let result = Server::new(config).router(router).run().await;
@@ -50,16 +52,56 @@ async fn main() {
     path = "/validation",
     request_body = BodyError,
     responses(
-        (status = 400, description = "returns validation error")
+        (status = 422, description = "returns validation error")
     )
 )]
 async fn validation_error_handler(
-    AppJson(payload): AppJson<BodyError>,
+    ValidatedJson(_payload): ValidatedJson<BodyError>,
 ) -> Result<&'static str, DefaultError> {
-    match payload.validate() {
-        Ok(_) => Ok("nothing"),
-        Err(err) => Err(DefaultError::ValidationError(err)),
+    Ok("nothing")
+}
+
+#[utoipa::path(
+    get,
+    path = "/items/{id}",
+    params(
+        ("id" = u64, Path, description = "Item id")
+    ),
+    responses(
+        (status = 200, description = "returns item id"),
+        (status = 400, description = "returns path rejection error")
+    )
+)]
+async fn path_error_handler(AppPath(id): AppPath<u64>) -> Result<String, DefaultError> {
+    Ok(format!("item {id}"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/batch",
+    request_body = Vec<BodyError>,
+    responses(
+        (status = 422, description = "returns errors of all invalid items")
+    )
+)]
+async fn batch_error_handler(
+    AppJson(items): AppJson<Vec<BodyError>>,
+) -> Result<&'static str, DefaultError> {
+    let errors: Vec<ErrorInfo> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| {
+            item.validate()
+                .err()
+                .map(|e| ErrorInfo::new("validation_error", format!("item {i}: {e}")))
+        })
+        .collect();
+
+    if !errors.is_empty() {
+        return Err(DefaultError::Multiple(errors));
     }
+
+    Ok("nothing")
 }
 
 #[derive(Debug, Deserialize, Validate, ToSchema)]
@@ -127,4 +169,10 @@ impl AppError for CustomError {
             CustomError::TestErrorOne => "test_error_one".to_owned(),
         }
     }
+
+    fn code(&self) -> Option<String> {
+        match self {
+            CustomError::TestErrorOne => Some("E1001".to_owned()),
+        }
+    }
 }
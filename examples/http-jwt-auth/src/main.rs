@@ -11,7 +11,7 @@ use std::env;
 use axum::Json;
 use caslex::{
     errors::DefaultError,
-    middlewares::auth::Claims,
+    middlewares::auth::{Claims, RequireScope, Scope},
     server::{Config, Server},
 };
 use caslex_extra::{
@@ -30,7 +30,8 @@ async fn main() {
     let config = Config::parse();
     let router = OpenApiRouter::new()
         .routes(routes!(encode_handler))
-        .routes(routes!(decode_handler));
+        .routes(routes!(decode_handler))
+        .routes(routes!(admin_handler));
 
     let result = Server::new(config).router(router).run().await;
 
@@ -64,6 +65,7 @@ async fn encode_handler() -> Result<Json<TokenResponse>, DefaultError> {
     let claims = Claims {
         sub: USER_ID.to_string(),
         exp: jwt::expiry(TOKEN_LIFETIME_SECS),
+        scope: Some("read".to_owned()),
     };
 
     let token = jwt::encode_token(&claims);
@@ -83,3 +85,23 @@ async fn encode_handler() -> Result<Json<TokenResponse>, DefaultError> {
 async fn decode_handler(claims: Claims) -> Result<Json<Claims>, DefaultError> {
     Ok(Json(claims))
 }
+
+struct Admin;
+
+impl Scope for Admin {
+    const SCOPE: &'static str = "admin";
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin",
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 403, description = "Insufficient scope")
+    )
+)]
+async fn admin_handler(
+    RequireScope(claims, _): RequireScope<Admin>,
+) -> Result<Json<Claims>, DefaultError> {
+    Ok(Json(claims))
+}
@@ -9,7 +9,7 @@
 use std::{env, sync::OnceLock};
 
 use async_trait::async_trait;
-use caslex::server::{Config, Process, Server};
+use caslex::server::{Config, Process, RestartPolicy, Server};
 use caslex_extra::{cleanup_resources, setup_application};
 use tokio_util::sync::CancellationToken;
 
@@ -75,4 +75,12 @@ impl Process for DummyProcess {
             }
         }
     }
+
+    fn restart_policy(&self) -> RestartPolicy {
+        // Restart the process up to 3 times if it fails, waiting 1s, 2s, 4s between restarts.
+        RestartPolicy::OnFailure {
+            max_retries: 3,
+            backoff: std::time::Duration::from_secs(1),
+        }
+    }
 }
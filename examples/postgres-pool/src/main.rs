@@ -6,10 +6,13 @@
 
 #![allow(clippy::exit)]
 
-use std::env;
+use std::{env, sync::Arc, time::Duration};
 
-use axum::{extract::State, http::StatusCode};
-use caslex::server::{Config, Server};
+use axum::extract::State;
+use caslex::{
+    errors::DefaultError,
+    server::{Config, Server},
+};
 use caslex_extra::{cleanup_resources, setup_application, storages::postgres_pool};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
@@ -23,13 +26,21 @@ async fn main() {
     let pool = postgres_pool::build_pool_from_config(pg_config)
         .await
         .unwrap();
+    tokio::spawn(postgres_pool::record_pool_metrics(
+        pool.clone(),
+        Duration::from_secs(5),
+    ));
 
     let router = OpenApiRouter::new()
         .routes(routes!(handler))
         .with_state(pool.clone());
 
     let server_config = Config::parse();
-    let result = Server::new(server_config).router(router).run().await;
+    let result = Server::new(server_config)
+        .health_check("postgres", Arc::new(pool))
+        .router(router)
+        .run()
+        .await;
 
     cleanup_resources();
 
@@ -49,25 +60,11 @@ async fn main() {
         (status = 200, description = "Ok")
     )
 )]
-async fn handler(
-    State(state): State<deadpool_postgres::Pool>,
-) -> Result<String, (StatusCode, String)> {
-    let conn = state.get().await.map_err(internal_error)?;
+async fn handler(State(state): State<deadpool_postgres::Pool>) -> Result<String, DefaultError> {
+    let conn = state.get().await?;
 
-    let row = conn
-        .query_one("select 1 + 1", &[])
-        .await
-        .map_err(internal_error)?;
-    let two: i32 = row.try_get(0).map_err(internal_error)?;
+    let row = conn.query_one("select 1 + 1", &[]).await?;
+    let two: i32 = row.try_get(0)?;
 
     Ok(two.to_string())
 }
-
-/// Utility function for mapping any error into a `500 Internal Server Error`
-/// response.
-fn internal_error<E>(err: E) -> (StatusCode, String)
-where
-    E: std::error::Error,
-{
-    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
-}